@@ -0,0 +1,91 @@
+//! Renders lambdas in a long section, comparing how long it takes when the
+//! text they return is compiled once and reused with when it's different every
+//! time, so it has to be compiled on every call. Run with
+//! `cargo bench --bench lambda`.
+
+extern crate mustache;
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use mustache::{Context, Data, MapBuilder, Template};
+
+const ITEMS: usize = 10_000;
+const RUNS: u32 = 10;
+
+/// The text a lambda returns for the section text `text`. When `unique` is
+/// set, a comment with a counter makes it different on every call, so it can't
+/// be found in the cache.
+fn wrap(text: &str, unique: bool, calls: &AtomicUsize) -> String {
+    if unique {
+        format!("<li>{}</li>{{{{! {} }}}}", text, calls.fetch_add(1, Ordering::Relaxed))
+    } else {
+        format!("<li>{}</li>", text)
+    }
+}
+
+fn data(unique: bool) -> Data {
+    let fun_calls = AtomicUsize::new(0);
+    let lambda_calls = Arc::new(AtomicUsize::new(0));
+
+    MapBuilder::new()
+        .insert_vec("items", |mut b| {
+            for i in 0..ITEMS {
+                b = b.push_map(move |b| b.insert_str("name", format!("item {}", i % 10)));
+            }
+            b
+        })
+        .insert_fn("fun", move |text| wrap(&text, unique, &fun_calls))
+        .insert_lambda("lambda", move |ctx| {
+            let text = wrap(ctx.text(), unique, &lambda_calls);
+            ctx.render(&text)
+        })
+        .build()
+}
+
+fn template(src: &str) -> Template {
+    // Enough partials that copying them for every lambda call would show.
+    let partials: HashMap<String, String> = (0..50)
+        .map(|i| (format!("partial{}", i), format!("{{{{name}}}} {}", i)))
+        .collect();
+
+    let mut ctx = Context::new(PathBuf::from("."));
+    ctx.partial_loader = Some(Arc::new(partials));
+
+    let uses: String = (0..50).map(|i| format!("{{{{^items}}}}{{{{> partial{}}}}}{{{{/items}}}}", i)).collect();
+    ctx.compile((uses + src).chars()).expect("Failed to compile")
+}
+
+fn time(src: &str, data: &Data) -> Duration {
+    let template = template(src);
+
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        let mut bytes = vec![];
+        template.render_data(&mut bytes, data).expect("Failed to render");
+    }
+    start.elapsed() / RUNS
+}
+
+fn ms(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1e3 + duration.subsec_nanos() as f64 / 1e6
+}
+
+fn bench(name: &str, src: &str) {
+    let cached = time(src, &data(false));
+    let uncached = time(src, &data(true));
+
+    println!("{:<8} {:>10.3} ms/render cached {:>10.3} ms/render uncached {:>6.1}x",
+             name,
+             ms(cached),
+             ms(uncached),
+             ms(uncached) / ms(cached));
+}
+
+fn main() {
+    bench("fun", "{{#items}}{{#fun}}{{name}}{{/fun}}{{/items}}");
+    bench("lambda", "{{#items}}{{#lambda}}{{name}}{{/lambda}}{{/items}}");
+}
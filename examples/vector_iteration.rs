@@ -4,7 +4,6 @@ extern crate serde_derive;
 
 extern crate mustache;
 
-use std::str;
 use std::collections::HashMap;
 
 #[derive(Serialize)]
@@ -27,8 +26,7 @@ fn main() {
     let mut data = HashMap::new();
     data.insert("users", users);
 
-    let mut bytes = vec![];
-    template.render(&mut bytes, &data).expect("Failed to render");
+    let rendered = template.render_to_string(&data).expect("Failed to render");
 
-    assert_eq!(str::from_utf8(&bytes), Ok("Hello Harry!\nHello Samantha!\n"))
+    assert_eq!(rendered, "Hello Harry!\nHello Samantha!\n")
 }
@@ -0,0 +1,248 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use proc_macro2::{Span, TokenStream};
+use syn::{Data, DeriveInput, Field, Fields, Ident, Lit, Meta, NestedMeta, Type};
+
+use embed::{self, Embedded};
+use parser::Token;
+
+/// `impl ToData`, turning the struct into a map of its fields.
+pub fn to_data(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let fields = try!(fields(input));
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let keys = fields.iter().map(|field| field.to_string());
+    let fields = fields.iter();
+
+    Ok(quote! {
+        impl #impl_generics ::mustache::ToData for #name #ty_generics #where_clause {
+            fn to_data(&self) -> ::mustache::Data {
+                let mut map = ::mustache::Map::new();
+                #(map.insert(#keys.to_string(), ::mustache::ToData::to_data(&self.#fields));)*
+                ::mustache::Data::Map(map)
+            }
+        }
+    })
+}
+
+/// `impl ContextValue`, looking up the fields of the struct by name.
+pub fn context_value(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let fields = try!(fields(input));
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let keys = fields.iter().map(|field| field.to_string());
+    let fields = fields.iter();
+
+    Ok(quote! {
+        impl #impl_generics ::mustache::ContextValue for #name #ty_generics #where_clause {
+            fn get<'v>(&'v self, key: &str)
+                       -> ::std::option::Option<::mustache::__private::ContextValueRef<'v>> {
+                match key {
+                    #(#keys => ::std::option::Option::Some(&self.#fields),)*
+                    _ => ::std::option::Option::None,
+                }
+            }
+
+            fn truthy(&self) -> bool {
+                true
+            }
+
+            fn write_to(&self, _out: &mut ::std::string::String) {}
+        }
+    })
+}
+
+/// `impl MustacheTemplate` with the template of the `#[template(path = "..")]`
+/// attribute, which must only use the fields of the struct. The struct is
+/// rendered through its own `ContextValue` impl, which this doesn't emit.
+pub fn mustache_template(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let (path, span) = try!(template_path(input));
+    let embedded = try!(embed::load(&path).map_err(|msg| syn::Error::new(span, msg)));
+
+    let named = try!(named_fields(input));
+    let name_of = |field: &&Field| field.ident.as_ref().map(|ident| ident.to_string());
+    let fields = named.iter().filter_map(&name_of).collect();
+    let flags = named.iter().filter(|field| is_bool(&field.ty)).filter_map(&name_of).collect();
+    let mut checker = Checker {
+        embedded: &embedded,
+        fields: &fields,
+        flags: &flags,
+        visited: HashSet::new(),
+        unknown: Vec::new(),
+    };
+    checker.check(&embedded.tokens, &path);
+
+    if let Some((first, rest)) = checker.unknown.split_first() {
+        let mut err = syn::Error::new(span, unknown_field(&input.ident, first));
+        for unknown in rest {
+            err.combine(syn::Error::new(span, unknown_field(&input.ident, unknown)));
+        }
+        return Err(err);
+    }
+
+    let template = embed::template_expr(&embedded);
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::mustache::MustacheTemplate for #name #ty_generics #where_clause {
+            fn template() -> &'static ::mustache::Template {
+                static TEMPLATE: ::std::sync::OnceLock<::mustache::Template> =
+                    ::std::sync::OnceLock::new();
+                TEMPLATE.get_or_init(|| #template)
+            }
+        }
+    })
+}
+
+fn fields(input: &DeriveInput) -> syn::Result<Vec<&Ident>> {
+    let fields = try!(named_fields(input));
+    Ok(fields.into_iter().filter_map(|field| field.ident.as_ref()).collect())
+}
+
+fn named_fields(input: &DeriveInput) -> syn::Result<Vec<&Field>> {
+    match input.data {
+        Data::Struct(ref data) => {
+            match data.fields {
+                Fields::Named(ref fields) => Ok(fields.named.iter().collect()),
+                Fields::Unit => Ok(Vec::new()),
+                Fields::Unnamed(_) => {
+                    Err(syn::Error::new(input.ident.span(),
+                                        "only structs with named fields can be rendered"))
+                }
+            }
+        }
+        _ => Err(syn::Error::new(input.ident.span(), "only structs can be rendered")),
+    }
+}
+
+/// Whether a field is a plain `bool`, so a section on it renders its children
+/// with the struct as the context.
+fn is_bool(ty: &Type) -> bool {
+    match *ty {
+        Type::Path(ref ty) => ty.qself.is_none() && ty.path.is_ident("bool"),
+        _ => false,
+    }
+}
+
+fn template_path(input: &DeriveInput) -> syn::Result<(String, Span)> {
+    if let Some(attr) = input.attrs.iter().find(|attr| attr.path.is_ident("template")) {
+        if let Meta::List(list) = try!(attr.parse_meta()) {
+            for nested in list.nested.iter() {
+                if let NestedMeta::Meta(Meta::NameValue(ref pair)) = *nested {
+                    if let Lit::Str(ref path) = pair.lit {
+                        if pair.path.is_ident("path") {
+                            return Ok((path.value(), path.span()));
+                        }
+                    }
+                }
+            }
+        }
+
+        return Err(syn::Error::new_spanned(attr, "expected #[template(path = \"...\")]"));
+    }
+
+    Err(syn::Error::new(input.ident.span(),
+                        "missing #[template(path = \"...\")] attribute"))
+}
+
+/// A name used by the template that isn't a field, and where it's used.
+struct Unknown {
+    name: String,
+    file: String,
+    line: usize,
+    col: usize,
+}
+
+fn unknown_field(ident: &Ident, unknown: &Unknown) -> String {
+    format!("{}:{}:{}: `{}` is not a field of `{}`",
+            unknown.file,
+            unknown.line,
+            unknown.col,
+            unknown.name,
+            ident)
+}
+
+/// Walks the tokens rendered with the struct itself as the context, which are
+/// the ones that aren't inside a section iterating over or entering a value.
+/// The children of inverted sections and of sections on `bool` fields are
+/// checked; the others can use the fields of whatever the section enters, so
+/// they aren't.
+struct Checker<'a> {
+    embedded: &'a Embedded,
+    fields: &'a HashSet<String>,
+    flags: &'a HashSet<String>,
+    visited: HashSet<String>,
+    unknown: Vec<Unknown>,
+}
+
+impl<'a> Checker<'a> {
+    fn check(&mut self, tokens: &[Token], file: &str) {
+        for token in tokens.iter() {
+            match *token {
+                Token::EscapedTag(ref path, _, _, _, span) |
+                Token::UnescapedTag(ref path, _, _, _, span) |
+                Token::DynamicPartial(ref path, _, _, span) => {
+                    self.check_path(path, file, span.start.line, span.start.col);
+                }
+                Token::Section(ref path, inverted, ref children, _, _, _, _, _, span) => {
+                    self.check_path(path, file, span.start.line, span.start.col);
+
+                    // Inverted sections and sections on flags keep the
+                    // same context.
+                    if inverted || (path.len() == 1 && self.flags.contains(&path[0])) {
+                        self.check(children, file);
+                    }
+                }
+                Token::Partial(ref name, _, _, _) => self.check_partial(name, file),
+                Token::Block(_, _, ref children, _, _, _, _) => self.check(children, file),
+                Token::Parent(ref name, _, ref children, _, _, _, _) => {
+                    self.check(children, file);
+                    self.check_partial(name, file);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn check_partial(&mut self, name: &str, file: &str) {
+        if !self.visited.insert(name.to_string()) {
+            return;
+        }
+
+        let embedded = self.embedded;
+        for &(ref partial, ref tokens) in embedded.partials.iter() {
+            if partial == name {
+                let extension = Path::new(file)
+                    .extension()
+                    .and_then(|ext| ext.to_str())
+                    .unwrap_or("mustache");
+                let partial_file = Path::new(file)
+                    .with_file_name(name.to_string() + "." + extension)
+                    .to_string_lossy()
+                    .into_owned();
+                self.check(tokens, &partial_file);
+            }
+        }
+    }
+
+    fn check_path(&mut self, path: &[String], file: &str, line: usize, col: usize) {
+        // `{{.}}` is the struct itself.
+        let name = match path.first() {
+            Some(name) => name,
+            None => return,
+        };
+
+        if !self.fields.contains(name) {
+            self.unknown.push(Unknown {
+                name: name.clone(),
+                file: file.to_string(),
+                line: line,
+                col: col,
+            });
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use std::env;
+use std::fs::File;
+use std::io::ErrorKind::NotFound;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use proc_macro2::TokenStream;
+
+use format::Format;
+use parser::{self, Arg, Parser, Span, Token};
+
+/// A template and its partials, parsed at build time.
+pub struct Embedded {
+    /// The path the template was given as, which errors point at.
+    pub path: String,
+    pub tokens: Vec<Token>,
+    pub partials: Vec<(String, Vec<Token>)>,
+    /// Every file that was read, so the crate is rebuilt when one changes.
+    pub files: Vec<PathBuf>,
+}
+
+/// Parse the template at `path`, relative to the manifest of the crate being
+/// built, along with the partials it uses. Partials are looked up next to the
+/// template with the same extension, like `mustache::compile_path` does.
+pub fn load(path: &str) -> Result<Embedded, String> {
+    let dir = env::var("CARGO_MANIFEST_DIR").map(PathBuf::from).unwrap_or_default();
+    let file = dir.join(path);
+
+    let source = match try!(read(&file)) {
+        Some(source) => source,
+        None => return Err(format!("couldn't find template `{}`", file.display())),
+    };
+
+    let (tokens, mut names) = try!(parse(&source, Path::new(path)));
+
+    let template_dir = file.parent().map(Path::to_path_buf).unwrap_or_default();
+    let extension = file.extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("mustache")
+        .to_string();
+
+    let mut embedded = Embedded {
+        path: path.to_string(),
+        tokens: tokens,
+        partials: Vec::new(),
+        files: vec![file],
+    };
+
+    let mut seen = Vec::new();
+    while let Some(name) = names.pop() {
+        if seen.contains(&name) {
+            continue;
+        }
+        seen.push(name.clone());
+
+        // Partials that don't exist render as nothing, just like when the
+        // template is compiled at runtime.
+        let partial_file = template_dir.join(name.clone() + "." + &extension);
+        match try!(read(&partial_file)) {
+            Some(source) => {
+                let display = Path::new(path).with_file_name(name.clone() + "." + &extension);
+                let (tokens, partials) = try!(parse(&source, &display));
+                names.extend(partials);
+                embedded.partials.push((name, tokens));
+                embedded.files.push(partial_file);
+            }
+            None => embedded.partials.push((name, Vec::new())),
+        }
+    }
+
+    Ok(embedded)
+}
+
+fn read(file: &Path) -> Result<Option<String>, String> {
+    let mut source = String::new();
+    match File::open(file).and_then(|mut f| f.read_to_string(&mut source)) {
+        Ok(_) => Ok(Some(source)),
+        Err(ref e) if e.kind() == NotFound => Ok(None),
+        Err(e) => Err(format!("couldn't read `{}`: {}", file.display(), e)),
+    }
+}
+
+fn parse(source: &str, file: &Path) -> Result<(Vec<Token>, Vec<String>), String> {
+    let mut reader = source.chars();
+    let parser = Parser::new(&mut reader, "{{", "}}");
+    parser.parse().map_err(|mut err| {
+        parser::set_file(&mut err, file);
+        err.to_string()
+    })
+}
+
+/// The expression building a `mustache::Template` out of `embedded`.
+pub fn template_expr(embedded: &Embedded) -> TokenStream {
+    let file = &embedded.path;
+    let tokens = tokens_expr(&embedded.tokens);
+    let partials = embedded.partials.iter().map(|&(ref name, ref tokens)| {
+        let tokens = tokens_expr(tokens);
+        quote! { (#name, #tokens) }
+    });
+    let files = embedded.files.iter().map(|file| file.to_string_lossy().into_owned());
+
+    quote! {
+        {
+            #(const _: &'static [u8] = include_bytes!(#files);)*
+            ::mustache::__private::template(#file, #tokens, vec![#(#partials),*])
+        }
+    }
+}
+
+fn tokens_expr(tokens: &[Token]) -> TokenStream {
+    let tokens = tokens.iter().map(token_expr);
+    quote! { vec![#(#tokens),*] }
+}
+
+fn token_expr(token: &Token) -> TokenStream {
+    match *token {
+        Token::Text(ref text, span) => {
+            let span = span_expr(span);
+            quote! { ::mustache::__private::Token::Text(#text.to_string(), #span) }
+        }
+        Token::EscapedTag(ref path, ref filters, ref format, ref tag, span) => {
+            let path = path_expr(path);
+            let filters = filters_expr(filters);
+            let format = format_expr(format);
+            let span = span_expr(span);
+            quote! {
+                ::mustache::__private::Token::EscapedTag(#path,
+                                                      #filters,
+                                                      #format,
+                                                      #tag.to_string(),
+                                                      #span)
+            }
+        }
+        Token::UnescapedTag(ref path, ref filters, ref format, ref tag, span) => {
+            let path = path_expr(path);
+            let filters = filters_expr(filters);
+            let format = format_expr(format);
+            let span = span_expr(span);
+            quote! {
+                ::mustache::__private::Token::UnescapedTag(#path,
+                                                      #filters,
+                                                      #format,
+                                                      #tag.to_string(),
+                                                      #span)
+            }
+        }
+        Token::Section(ref path, inverted, ref children, ref otag, ref osection, ref src,
+                       ref csection, ref ctag, span) => {
+            let path = path_expr(path);
+            let children = tokens_expr(children);
+            let span = span_expr(span);
+            quote! {
+                ::mustache::__private::Token::Section(#path,
+                                                      #inverted,
+                                                      #children,
+                                                      #otag.to_string(),
+                                                      #osection.to_string(),
+                                                      #src.to_string(),
+                                                      #csection.to_string(),
+                                                      #ctag.to_string(),
+                                                      #span)
+            }
+        }
+        Token::Partial(ref name, ref indent, ref tag, span) => {
+            let span = span_expr(span);
+            quote! {
+                ::mustache::__private::Token::Partial(#name.to_string(),
+                                                      #indent.to_string(),
+                                                      #tag.to_string(),
+                                                      #span)
+            }
+        }
+        Token::DynamicPartial(ref path, ref indent, ref tag, span) => {
+            let path = path_expr(path);
+            let span = span_expr(span);
+            quote! {
+                ::mustache::__private::Token::DynamicPartial(#path,
+                                                             #indent.to_string(),
+                                                             #tag.to_string(),
+                                                             #span)
+            }
+        }
+        Token::Block(ref name, ref indent, ref children, ref osection, ref src, ref csection,
+                     span) => {
+            let children = tokens_expr(children);
+            let span = span_expr(span);
+            quote! {
+                ::mustache::__private::Token::Block(#name.to_string(),
+                                                    #indent.to_string(),
+                                                    ::std::sync::Arc::new(#children),
+                                                    #osection.to_string(),
+                                                    #src.to_string(),
+                                                    #csection.to_string(),
+                                                    #span)
+            }
+        }
+        Token::Parent(ref name, ref indent, ref children, ref osection, ref src, ref csection,
+                      span) => {
+            let children = tokens_expr(children);
+            let span = span_expr(span);
+            quote! {
+                ::mustache::__private::Token::Parent(#name.to_string(),
+                                                     #indent.to_string(),
+                                                     #children,
+                                                     #osection.to_string(),
+                                                     #src.to_string(),
+                                                     #csection.to_string(),
+                                                     #span)
+            }
+        }
+        Token::IncompleteSection(..) |
+        Token::IncompleteBlock(..) |
+        Token::IncompleteParent(..) => bug!("incomplete token after parsing: {:?}", token),
+    }
+}
+
+fn path_expr(path: &[String]) -> TokenStream {
+    quote! { vec![#(#path.to_string()),*] }
+}
+
+fn filters_expr(filters: &[(String, Vec<Arg>)]) -> TokenStream {
+    let filters = filters.iter().map(|&(ref name, ref args)| {
+        let args = args.iter().map(|arg| {
+            match *arg {
+                Arg::String(ref s) => quote! { ::mustache::Arg::String(#s.to_string()) },
+                Arg::Int(n) => quote! { ::mustache::Arg::Int(#n) },
+                Arg::Float(n) => quote! { ::mustache::Arg::Float(#n) },
+            }
+        });
+        quote! { (#name.to_string(), vec![#(#args),*]) }
+    });
+    quote! { vec![#(#filters),*] }
+}
+
+fn format_expr(format: &Option<Format>) -> TokenStream {
+    match *format {
+        // The spec was checked when parsing, so it'll parse again at runtime.
+        Some(ref format) => {
+            let spec = format.spec();
+            quote! { ::mustache::__private::format(#spec) }
+        }
+        None => quote! { None },
+    }
+}
+
+fn span_expr(span: Span) -> TokenStream {
+    let (start_line, start_col) = (span.start.line, span.start.col);
+    let (end_line, end_col) = (span.end.line, span.end.col);
+    quote! { ::mustache::__private::span(#start_line, #start_col, #end_line, #end_col) }
+}
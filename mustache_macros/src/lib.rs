@@ -0,0 +1,102 @@
+//! Procedural macros for rust-mustache. Don't depend on this crate directly,
+//! enable the `macros` feature of `mustache` and use the macros from there.
+
+#[macro_use]
+extern crate mustache_parser;
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use syn::{DeriveInput, LitStr};
+
+// The parser is shared with mustache itself, so templates are parsed at build
+// time exactly like they would be at runtime.
+use mustache_parser::{format, parser};
+
+mod derive;
+mod embed;
+
+/// Parses a template and all of its partials at build time, and expands to a
+/// `mustache::Template` holding the parsed tokens. See the documentation of
+/// `mustache::include_template!`.
+///
+/// Broken templates don't compile:
+///
+/// ```rust,compile_fail
+/// let template = mustache::include_template!("tests/templates/unclosed.mustache");
+/// ```
+///
+/// And neither do missing ones:
+///
+/// ```rust,compile_fail
+/// let template = mustache::include_template!("tests/templates/nope.mustache");
+/// ```
+#[proc_macro]
+pub fn include_template(input: TokenStream) -> TokenStream {
+    let path = syn::parse_macro_input!(input as LitStr);
+
+    let expanded = match embed::load(&path.value()) {
+        Ok(embedded) => embed::template_expr(&embedded),
+        Err(msg) => syn::Error::new(path.span(), msg).to_compile_error(),
+    };
+
+    expanded.into()
+}
+
+/// Implements `mustache::MustacheTemplate` for a struct, with the template of
+/// its `#[template(path = "...")]` attribute. See the documentation of
+/// `mustache::MustacheTemplate`. It doesn't implement `ContextValue` or
+/// `ToData`, so it can be combined with their derives.
+///
+/// Templates that use a name that isn't a field don't compile:
+///
+/// ```rust,compile_fail
+/// #[macro_use]
+/// extern crate mustache;
+///
+/// #[derive(MustacheTemplate, ContextValue, ToData)]
+/// #[template(path = "tests/templates/item.mustache")]
+/// struct Item {
+///     title: String,
+/// }
+/// # fn main() {}
+/// ```
+///
+/// That includes the names inside sections on `bool` fields:
+///
+/// ```rust,compile_fail
+/// #[macro_use]
+/// extern crate mustache;
+///
+/// #[derive(MustacheTemplate, ContextValue, ToData)]
+/// #[template(path = "tests/templates/admin.mustache")]
+/// struct Admin {
+///     name: String,
+///     admin: bool,
+/// }
+/// # fn main() {}
+/// ```
+#[proc_macro_derive(MustacheTemplate, attributes(template))]
+pub fn derive_mustache_template(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    derive::mustache_template(&input).unwrap_or_else(|err| err.to_compile_error()).into()
+}
+
+/// Implements `mustache::ToData` for a struct, as a map of its fields.
+#[proc_macro_derive(ToData)]
+pub fn derive_to_data(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    derive::to_data(&input).unwrap_or_else(|err| err.to_compile_error()).into()
+}
+
+/// Implements `mustache::ContextValue` for a struct, so templates can look up
+/// its fields without copying it into `Data`. The fields have to implement
+/// `ContextValue` too.
+#[proc_macro_derive(ContextValue)]
+pub fn derive_context_value(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    derive::context_value(&input).unwrap_or_else(|err| err.to_compile_error()).into()
+}
@@ -0,0 +1,111 @@
+#[macro_use]
+extern crate mustache;
+
+use mustache::MustacheTemplate;
+
+#[derive(ContextValue, ToData)]
+struct Friend {
+    name: String,
+    age: u32,
+}
+
+#[derive(MustacheTemplate, ContextValue, ToData)]
+#[template(path = "tests/templates/user.mustache")]
+struct UserPage {
+    name: String,
+    admin: bool,
+    friends: Vec<Friend>,
+}
+
+fn render<T: MustacheTemplate>(page: &T) -> String {
+    let mut out = vec![];
+    page.render(&mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn test_derive_render() {
+    let page = UserPage {
+        name: "<Bob>".to_string(),
+        admin: false,
+        friends: vec![
+            Friend { name: "Alice".to_string(), age: 30 },
+            Friend { name: "Carol".to_string(), age: 41 },
+        ],
+    };
+
+    assert_eq!(render(&page),
+               "<h1>&lt;Bob&gt;</h1>\nNot an admin.\n- Alice (30)\n- Carol (41)\n");
+}
+
+#[test]
+fn test_derive_render_twice() {
+    let page = UserPage {
+        name: "Bob".to_string(),
+        admin: true,
+        friends: vec![],
+    };
+
+    assert_eq!(render(&page), "<h1>Bob</h1>\n");
+    assert_eq!(render(&page), "<h1>Bob</h1>\n");
+}
+
+#[derive(MustacheTemplate, ContextValue, ToData)]
+#[template(path = "tests/templates/item.mustache")]
+struct Item<'a> {
+    name: &'a str,
+    #[allow(dead_code)]
+    unused: Option<u8>,
+}
+
+#[test]
+fn test_derive_borrowed_fields() {
+    assert_eq!(render(&Item { name: "pear", unused: None }), "<li>pear</li>\n");
+}
+
+#[derive(MustacheTemplate, ContextValue, ToData)]
+#[template(path = "tests/templates/flag.mustache")]
+struct Flag {
+    name: String,
+    admin: bool,
+}
+
+#[test]
+fn test_derive_bool_section() {
+    assert_eq!(render(&Flag { name: "Bob".to_string(), admin: true }), "Bob is an admin.\n");
+    assert_eq!(render(&Flag { name: "Bob".to_string(), admin: false }), "\n");
+}
+
+#[derive(ContextValue, ToData)]
+struct Order<'a> {
+    id: u64,
+    customer: &'a str,
+    lines: Vec<OrderLine>,
+}
+
+#[derive(ContextValue, ToData)]
+struct OrderLine {
+    product: String,
+    quantity: u32,
+}
+
+#[test]
+fn test_derive_context_value() {
+    let order = Order {
+        id: 7,
+        customer: "<Bob>",
+        lines: vec![
+            OrderLine { product: "pear".to_string(), quantity: 2 },
+            OrderLine { product: "fig".to_string(), quantity: 0 },
+        ],
+    };
+
+    let template = mustache::compile_str("#{{id}} for {{customer}}:\
+                                          {{#lines}} {{quantity}}x{{product}}{{/lines}}\
+                                          {{#missing}}!{{/missing}}")
+        .unwrap();
+    let mut out = vec![];
+    template.render_value(&mut out, &order).unwrap();
+
+    assert_eq!(String::from_utf8(out).unwrap(), "#7 for &lt;Bob&gt;: 2xpear 0xfig");
+}
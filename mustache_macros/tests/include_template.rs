@@ -0,0 +1,76 @@
+#[macro_use]
+extern crate mustache;
+
+use mustache::{MapBuilder, Template};
+
+fn render(template: &Template, data: &mustache::Data) -> String {
+    let mut out = vec![];
+    template.render_data(&mut out, data).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn test_include_template_with_partials() {
+    let template = include_template!("tests/templates/page.mustache");
+
+    let data = MapBuilder::new()
+        .insert_str("title", "Fruits & vegetables")
+        .insert_vec("items", |vec| {
+            vec.push_map(|map| map.insert_str("name", "apple"))
+               .push_map(|map| map.insert_str("name", "carrot"))
+        })
+        .build();
+
+    let compiled = mustache::compile_path("tests/templates/page.mustache").unwrap();
+    assert_eq!(render(&template, &data), render(&compiled, &data));
+    assert!(render(&template, &data).starts_with("<h1>Fruits &amp; vegetables</h1>\n<ul>\n"));
+}
+
+#[test]
+fn test_include_template_missing_partial() {
+    let template = include_template!("tests/templates/absent.mustache");
+    assert_eq!(render(&template, &MapBuilder::new().build()), "done\n");
+}
+
+#[test]
+fn test_include_template_via_path() {
+    let template = mustache::include_template!("tests/templates/item.mustache");
+    let data = MapBuilder::new().insert_str("name", "pear").build();
+    assert_eq!(render(&template, &data), "<li>pear</li>\n");
+}
+
+#[test]
+fn test_include_template_format_specs() {
+    let template = include_template!("tests/templates/price.mustache");
+    let data = MapBuilder::new().insert_str("name", "Tea").insert_float("price", 3.5).build();
+    assert_eq!(render(&template, &data), "Tea-------    3.50\n");
+}
+
+#[test]
+fn test_include_template_filters() {
+    let mut template = include_template!("tests/templates/filtered.mustache");
+    template.register_filter("repeat", |value: &mustache::Data, args: &[mustache::Arg]| {
+        let times = args[0].as_int().unwrap() as usize;
+        let separator = args[1].to_string();
+        match *value {
+            mustache::Data::String(ref s) => {
+                Ok(mustache::Data::String(vec![s.clone(); times].join(&separator)))
+            }
+            _ => Ok(mustache::Data::Null),
+        }
+    });
+
+    let data = MapBuilder::new().insert_str("name", "ho").build();
+    assert_eq!(render(&template, &data), "ho, ho\n");
+}
+
+#[test]
+fn test_include_template_doesnt_read_files() {
+    let template = include_template!("tests/templates/dynamic.mustache");
+    let data = MapBuilder::new().insert_str("partial", "item").insert_str("name", "pear").build();
+
+    // `item.mustache` is next to the template, but it wasn't embedded.
+    let compiled = mustache::compile_path("tests/templates/dynamic.mustache").unwrap();
+    assert_eq!(render(&compiled, &data), "[<li>pear</li>\n]\n");
+    assert_eq!(render(&template, &data), "[]\n");
+}
@@ -0,0 +1,310 @@
+use std::iter;
+
+/// A format spec of an interpolation tag, like the `>8.2` in `{{price:>8.2}}`.
+/// It's the same as the format spec of Rust's `format!`, plus an optional `,`
+/// or `_` after the width to group the thousands of numbers:
+///
+/// ```text
+/// [[fill]align][sign]['0'][width][grouping]['.' precision]
+/// ```
+///
+/// Unlike in Rust, a precision on an integer prints that many zero decimals,
+/// so `{{price:.2}}` prints `5.00` for `5`. The width and precision can't be
+/// more than 1024.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Format {
+    spec: String,
+    fill: char,
+    align: Option<Align>,
+    plus: bool,
+    zero: bool,
+    width: Option<usize>,
+    grouping: Option<char>,
+    precision: Option<usize>,
+}
+
+/// The largest width or precision a spec can have, so a template can't make
+/// rendering allocate more than a little padding per tag.
+const MAX_WIDTH: usize = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+fn align(ch: char) -> Option<Align> {
+    match ch {
+        '<' => Some(Align::Left),
+        '^' => Some(Align::Center),
+        '>' => Some(Align::Right),
+        _ => None,
+    }
+}
+
+impl Format {
+    /// Parses a format spec, or returns `None` if it isn't valid.
+    pub fn parse(spec: &str) -> Option<Format> {
+        let mut format = Format {
+            spec: spec.to_string(),
+            fill: ' ',
+            align: None,
+            plus: false,
+            zero: false,
+            width: None,
+            grouping: None,
+            precision: None,
+        };
+
+        let chars: Vec<char> = spec.chars().collect();
+        let mut i = 0;
+
+        if chars.len() >= 2 && align(chars[1]).is_some() {
+            format.fill = chars[0];
+            format.align = align(chars[1]);
+            i = 2;
+        } else if !chars.is_empty() && align(chars[0]).is_some() {
+            format.align = align(chars[0]);
+            i = 1;
+        }
+
+        match chars.get(i) {
+            Some(&'+') => {
+                format.plus = true;
+                i += 1;
+            }
+            Some(&'-') => i += 1,
+            _ => {}
+        }
+
+        if chars.get(i) == Some(&'0') {
+            format.zero = true;
+            i += 1;
+        }
+
+        let (width, next) = number(&chars, i);
+        if width.map_or(false, |width| width > MAX_WIDTH) {
+            return None;
+        }
+        format.width = width;
+        i = next;
+
+        if let Some(&ch) = chars.get(i) {
+            if ch == ',' || ch == '_' {
+                format.grouping = Some(ch);
+                i += 1;
+            }
+        }
+
+        if chars.get(i) == Some(&'.') {
+            let (precision, next) = number(&chars, i + 1);
+            if precision.map_or(true, |precision| precision > MAX_WIDTH) {
+                return None;
+            }
+            format.precision = precision;
+            i = next;
+        }
+
+        if i == chars.len() {
+            Some(format)
+        } else {
+            None
+        }
+    }
+
+    /// The spec this was parsed from.
+    pub fn spec(&self) -> &str {
+        &self.spec
+    }
+
+    /// The fewest bytes a value formatted with this spec can take, so a
+    /// render can tell it won't fit before padding it.
+    pub fn min_len(&self) -> usize {
+        self.width.unwrap_or(0)
+    }
+
+    /// Strings are left aligned, and the precision is their maximum length.
+    pub fn format_str(&self, s: &str) -> String {
+        let s = match self.precision {
+            Some(precision) => s.chars().take(precision).collect(),
+            None => s.to_string(),
+        };
+
+        self.pad(s, Align::Left)
+    }
+
+    pub fn format_int(&self, n: i64) -> String {
+        let digits = (n as i128).abs().to_string();
+        let fraction = self.precision.map(|precision| zeros(precision));
+        self.format_number(n < 0, digits, fraction)
+    }
+
+    pub fn format_uint(&self, n: u64) -> String {
+        let fraction = self.precision.map(|precision| zeros(precision));
+        self.format_number(false, n.to_string(), fraction)
+    }
+
+    pub fn format_float(&self, n: f64) -> String {
+        let abs = match self.precision {
+            Some(precision) => format!("{:.*}", precision, n.abs()),
+            None => n.abs().to_string(),
+        };
+
+        let (digits, fraction) = match abs.find('.') {
+            Some(pos) => (abs[..pos].to_string(), Some(abs[pos + 1..].to_string())),
+            None => (abs, None),
+        };
+
+        self.format_number(n.is_sign_negative() && !n.is_nan(), digits, fraction)
+    }
+
+    fn format_number(&self, negative: bool, digits: String, fraction: Option<String>) -> String {
+        let sign = if negative {
+            "-"
+        } else if self.plus {
+            "+"
+        } else {
+            ""
+        };
+
+        let mut body = match self.grouping {
+            Some(separator) if digits.chars().all(|ch| ch.is_digit(10)) => {
+                group(&digits, separator)
+            }
+            _ => digits,
+        };
+
+        if let Some(fraction) = fraction {
+            if !fraction.is_empty() {
+                body.push('.');
+                body.push_str(&fraction);
+            }
+        }
+
+        // Zero padding goes between the sign and the digits, and overrides the
+        // fill and alignment.
+        if self.zero {
+            let len = sign.len() + body.chars().count();
+            let padding = self.width.map_or(0, |width| width.saturating_sub(len));
+            return sign.to_string() + &zeros(padding) + &body;
+        }
+
+        self.pad(sign.to_string() + &body, Align::Right)
+    }
+
+    fn pad(&self, s: String, default: Align) -> String {
+        let len = s.chars().count();
+        let padding = match self.width {
+            Some(width) if width > len => width - len,
+            _ => return s,
+        };
+
+        let (before, after) = match self.align.unwrap_or(default) {
+            Align::Left => (0, padding),
+            Align::Center => (padding / 2, padding - padding / 2),
+            Align::Right => (padding, 0),
+        };
+
+        let fill = |n| iter::repeat(self.fill).take(n).collect::<String>();
+        fill(before) + &s + &fill(after)
+    }
+}
+
+/// Reads the decimal number starting at `chars[i]`, if there is one, and
+/// returns it along with the index of the character after it. Numbers above
+/// `MAX_WIDTH` are read as `MAX_WIDTH + 1`, which isn't a valid spec.
+fn number(chars: &[char], mut i: usize) -> (Option<usize>, usize) {
+    let start = i;
+    let mut n: usize = 0;
+    while let Some(digit) = chars.get(i).and_then(|ch| ch.to_digit(10)) {
+        n = (n * 10 + digit as usize).min(MAX_WIDTH + 1);
+        i += 1;
+    }
+
+    if i == start {
+        (None, i)
+    } else {
+        (Some(n), i)
+    }
+}
+
+fn zeros(n: usize) -> String {
+    iter::repeat('0').take(n).collect()
+}
+
+fn group(digits: &str, separator: char) -> String {
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, ch) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            grouped.push(separator);
+        }
+        grouped.push(ch);
+    }
+    grouped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Format;
+
+    fn format(spec: &str) -> Format {
+        Format::parse(spec).expect("invalid spec")
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert_eq!(Format::parse("x"), None);
+        assert_eq!(Format::parse(".x"), None);
+        assert_eq!(Format::parse("."), None);
+        assert_eq!(Format::parse("8.2f"), None);
+    }
+
+    #[test]
+    fn test_parse_too_wide() {
+        assert!(Format::parse("1024.1024").is_some());
+        assert_eq!(Format::parse("1025"), None);
+        assert_eq!(Format::parse(".1025"), None);
+        assert_eq!(Format::parse("99999999999999"), None);
+        assert_eq!(Format::parse("0.99999999999999999999999"), None);
+    }
+
+    #[test]
+    fn test_min_len() {
+        assert_eq!(format("").min_len(), 0);
+        assert_eq!(format("*^7.2").min_len(), 7);
+    }
+
+    #[test]
+    fn test_format_str() {
+        assert_eq!(format("").format_str("abc"), "abc");
+        assert_eq!(format("6").format_str("abc"), "abc   ");
+        assert_eq!(format(">6").format_str("abc"), "   abc");
+        assert_eq!(format("*^7").format_str("abc"), "**abc**");
+        assert_eq!(format(".2").format_str("abc"), "ab");
+        assert_eq!(format("-<4.1").format_str("héllo"), "h---");
+    }
+
+    #[test]
+    fn test_format_int() {
+        assert_eq!(format("6").format_int(42), "    42");
+        assert_eq!(format("<6").format_int(42), "42    ");
+        assert_eq!(format("+").format_int(42), "+42");
+        assert_eq!(format("06").format_int(-42), "-00042");
+        assert_eq!(format(",").format_int(-1234567), "-1,234,567");
+        assert_eq!(format("_").format_uint(1000), "1_000");
+        assert_eq!(format(".2").format_int(5), "5.00");
+        assert_eq!(format("").format_int(i64::min_value()), "-9223372036854775808");
+    }
+
+    #[test]
+    fn test_format_float() {
+        assert_eq!(format(".2").format_float(1.23456), "1.23");
+        assert_eq!(format(">8.2").format_float(-1.23456), "   -1.23");
+        assert_eq!(format("08.3").format_float(2.5), "0002.500");
+        assert_eq!(format(",.2").format_float(1234567.891), "1,234,567.89");
+        assert_eq!(format(".0").format_float(2.5), "2");
+        assert_eq!(format("").format_float(0.1), "0.1");
+        assert_eq!(format(",").format_float(::std::f64::INFINITY), "inf");
+    }
+}
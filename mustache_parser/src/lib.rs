@@ -0,0 +1,10 @@
+//! The template parser of rust-mustache. It's its own crate so that
+//! `mustache_macros` parses templates at build time exactly like `mustache`
+//! does at runtime. Don't depend on this crate directly; nothing here is
+//! public API of `mustache`, except what `mustache` re-exports.
+
+#[macro_use]
+mod macros;
+
+pub mod format;
+pub mod parser;
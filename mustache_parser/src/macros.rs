@@ -1,5 +1,7 @@
 /// This should be the only place to panic inside non-test code.
 // TODO: ensure no panics elsewhere via clippy
+#[doc(hidden)]
+#[macro_export]
 macro_rules! bug {
     ($msg:expr) => ({
         bug!("{}", $msg)
@@ -0,0 +1,1526 @@
+use std::error::Error as StdError;
+use std::mem;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use format::Format;
+
+/// `Token` is a section of a compiled mustache string. The tokens of a block
+/// are shared, so the parents that override it don't need to copy them.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Token {
+    Text(String, Span),
+    EscapedTag(Vec<String>, Vec<(String, Vec<Arg>)>, Option<Format>, String, Span),
+    UnescapedTag(Vec<String>, Vec<(String, Vec<Arg>)>, Option<Format>, String, Span),
+    Section(Vec<String>, bool, Vec<Token>, String, String, String, String, String, Span),
+    IncompleteSection(Vec<String>, bool, String, bool, Span),
+    Partial(String, String, String, Span),
+    DynamicPartial(Vec<String>, String, String, Span),
+    Block(String, String, Arc<Vec<Token>>, String, String, String, Span),
+    IncompleteBlock(String, String, bool, String, Span),
+    Parent(String, String, Vec<Token>, String, String, String, Span),
+    IncompleteParent(String, String, bool, Span),
+}
+
+impl Token {
+    /// Where the token is in the source of its template.
+    pub fn span(&self) -> Span {
+        match *self {
+            Token::Text(_, span) |
+            Token::EscapedTag(_, _, _, _, span) |
+            Token::UnescapedTag(_, _, _, _, span) |
+            Token::Section(_, _, _, _, _, _, _, _, span) |
+            Token::IncompleteSection(_, _, _, _, span) |
+            Token::Partial(_, _, _, span) |
+            Token::DynamicPartial(_, _, _, span) |
+            Token::Block(_, _, _, _, _, _, span) |
+            Token::IncompleteBlock(_, _, _, _, span) |
+            Token::Parent(_, _, _, _, _, _, span) |
+            Token::IncompleteParent(_, _, _, span) => span,
+        }
+    }
+}
+
+/// An argument given to a filter in a tag like `{{name | truncate 20 "..."}}`.
+/// Numbers become `Int` or `Float`, and anything else, quoted or not, becomes a
+/// `String`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Arg {
+    String(String),
+    Int(i64),
+    Float(f64),
+}
+
+impl Arg {
+    fn parse(word: String, quoted: bool) -> Arg {
+        if !quoted {
+            if let Ok(n) = word.parse() {
+                return Arg::Int(n);
+            }
+            // Don't let words like `inf` or `nan` turn into numbers.
+            if word.starts_with(|ch: char| ch.is_digit(10) || ch == '-' || ch == '.') {
+                if let Ok(n) = word.parse::<f64>() {
+                    if n.is_finite() {
+                        return Arg::Float(n);
+                    }
+                }
+            }
+        }
+        Arg::String(word)
+    }
+
+    /// The argument as an integer, if it is one.
+    pub fn as_int(&self) -> Option<i64> {
+        match *self {
+            Arg::Int(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    /// The argument as a float, if it's a number.
+    pub fn as_float(&self) -> Option<f64> {
+        match *self {
+            Arg::Int(n) => Some(n as f64),
+            Arg::Float(n) => Some(n),
+            _ => None,
+        }
+    }
+}
+
+/// Writes the argument as a string, which numbers are converted to, so
+/// `arg.to_string()` works for any argument.
+impl fmt::Display for Arg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Arg::String(ref s) => f.write_str(s),
+            Arg::Int(n) => write!(f, "{}", n),
+            Arg::Float(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+/// A line and column in the source of a template, both starting at 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Position {
+    pub fn new(line: usize, col: usize) -> Position {
+        Position {
+            line: line,
+            col: col,
+        }
+    }
+
+    /// The position right after `text`, if `text` started at this position.
+    pub fn advance(self, text: &str) -> Position {
+        let mut position = self;
+        for ch in text.chars() {
+            if ch == '\n' {
+                position.line += 1;
+                position.col = 1;
+            } else {
+                position.col += 1;
+            }
+        }
+        position
+    }
+}
+
+impl Default for Position {
+    fn default() -> Position {
+        Position::new(1, 1)
+    }
+}
+
+/// The part of the source of a template a `Token` was parsed from. The end
+/// is the position right after the token.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    pub fn new(start: Position, end: Position) -> Span {
+        Span {
+            start: start,
+            end: end,
+        }
+    }
+
+    /// A span covering the single character at `position`.
+    pub fn at(position: Position) -> Span {
+        Span::new(position, Position::new(position.line, position.col + 1))
+    }
+}
+
+/// Error type to represent parsing failure, along with where in the template
+/// it happened.
+#[derive(Debug, PartialEq)]
+pub struct Error {
+    kind: ErrorKind,
+    file: Option<PathBuf>,
+    span: Span,
+}
+
+impl Error {
+    fn new(kind: ErrorKind, span: Span) -> Error {
+        Error {
+            kind: kind,
+            file: None,
+            span: span,
+        }
+    }
+
+    /// What went wrong.
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    /// The file the template was read from, if it was read from a file.
+    pub fn file(&self) -> Option<&Path> {
+        self.file.as_ref().map(|file| &**file)
+    }
+
+    /// The part of the template the error is about, usually the offending tag.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// The line the error was found on.
+    pub fn line(&self) -> usize {
+        self.span.start.line
+    }
+
+    /// The column the error was found at.
+    pub fn col(&self) -> usize {
+        self.span.start.col
+    }
+}
+
+impl StdError for Error {
+    fn description(&self) -> &str {
+        self.kind.description()
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.file {
+            Some(ref file) => {
+                write!(f, "{}:{}:{}: {}", file.display(), self.line(), self.col(), self.kind)
+            }
+            None => write!(f, "{}:{}: {}", self.line(), self.col(), self.kind),
+        }
+    }
+}
+
+/// Record the file a template was read from on an error. Errors from nested
+/// partials already know their file, so those are left alone. This is not part
+/// of the impl of Error so it is not exported outside of mustache.
+pub fn set_file(err: &mut Error, file: &Path) {
+    if err.file.is_none() {
+        err.file = Some(file.to_path_buf());
+    }
+}
+
+/// The different kinds of parsing failure.
+///
+/// This type is not intended to be matched exhaustively as new variants
+/// may be added in future without a version bump.
+#[derive(Debug, PartialEq)]
+pub enum ErrorKind {
+    BadClosingTag(char, char),
+    UnclosedTag,
+    UnclosedSection(String),
+    UnbalancedUnescapeTag,
+    EmptyTag,
+    EarlySectionClose(String),
+    MissingSetDelimeterClosingTag,
+    InvalidSetDelimeterSyntax,
+    InvalidFormat(String),
+    InvalidFilter(String),
+
+    #[doc(hidden)]
+    __Nonexhaustive,
+}
+
+impl StdError for ErrorKind {
+    fn description(&self) -> &'static str {
+        match *self {
+            ErrorKind::BadClosingTag(..) => "found a malformed closing tag",
+            ErrorKind::UnclosedTag => "found an unclosed tag",
+            ErrorKind::UnclosedSection(..) => "found an unclosed section",
+            ErrorKind::UnbalancedUnescapeTag => "found an unbalanced unescape tag",
+            ErrorKind::EmptyTag => "found an empty tag",
+            ErrorKind::EarlySectionClose(..) => "found a closing tag for an unopened section",
+            ErrorKind::MissingSetDelimeterClosingTag => "missing the new closing tag in set delimeter tag",
+            ErrorKind::InvalidSetDelimeterSyntax => "invalid set delimeter tag syntax",
+            ErrorKind::InvalidFormat(..) => "found an invalid format spec",
+            ErrorKind::InvalidFilter(..) => "found an invalid filter",
+            ErrorKind::__Nonexhaustive => unreachable!(),
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Provide more information where possible
+        match *self {
+            ErrorKind::BadClosingTag(actual, expected) => {
+                write!(f,
+                       "character {:?} was unexpected in the closing tag, expected {:?}",
+                       actual,
+                       expected)
+            }
+            ErrorKind::UnclosedSection(ref name) => {
+                write!(f, "found an unclosed section: {:?}", name)
+            },
+            ErrorKind::EarlySectionClose(ref name) => {
+                write!(f, "found a closing tag for an unopened section {:?}", name)
+            },
+            ErrorKind::InvalidFormat(ref spec) => {
+                write!(f, "found an invalid format spec: {:?}", spec)
+            },
+            ErrorKind::InvalidFilter(ref filter) => {
+                write!(f, "found an invalid filter: {:?}", filter)
+            },
+            _ => write!(f, "{}", self.description()),
+        }
+    }
+}
+
+enum TokenClass {
+    Normal,
+    StandAlone,
+    WhiteSpace(String, usize),
+}
+
+/// `Parser` parses a string into a series of `Token`s.
+pub struct Parser<'a, T: 'a> {
+    reader: &'a mut T,
+    ch: Option<char>,
+    lookahead: Option<char>,
+    line: usize,
+    col: usize,
+    text_start: Position,
+    tag_start: Position,
+    content: String,
+    state: ParserState,
+    opening_tag: String,
+    closing_tag: String,
+    opening_tag_chars: Vec<char>,
+    closing_tag_chars: Vec<char>,
+    tag_position: usize,
+    tokens: Vec<Token>,
+    partials: Vec<String>,
+    pipelines: bool,
+}
+
+enum ParserState {
+    Text,
+    OpeningTag,
+    Tag,
+    ClosingTag,
+}
+
+impl<'a, T: Iterator<Item = char>> Parser<'a, T> {
+    pub fn new(reader: &'a mut T, opening_tag: &str, closing_tag: &str) -> Parser<'a, T> {
+        let mut parser = Parser {
+            reader: reader,
+            ch: None,
+            lookahead: None,
+            line: 1,
+            col: 1,
+            text_start: Position::default(),
+            tag_start: Position::default(),
+            content: String::new(),
+            state: ParserState::Text,
+            opening_tag: opening_tag.to_string(),
+            closing_tag: closing_tag.to_string(),
+            opening_tag_chars: opening_tag.chars().collect(),
+            closing_tag_chars: closing_tag.chars().collect(),
+            tag_position: 0,
+            tokens: Vec::new(),
+            partials: Vec::new(),
+            pipelines: true,
+        };
+
+        parser.bump();
+        parser
+    }
+
+    /// Whether `|` and `:` in interpolation tags start filters and a format
+    /// spec, which they do by default. When they don't, they're part of the
+    /// name, like they were before filters and format specs were added.
+    pub fn pipelines(mut self, pipelines: bool) -> Parser<'a, T> {
+        self.pipelines = pipelines;
+        self
+    }
+
+    fn bump(&mut self) {
+        // Move the position past the character we're leaving behind, so it
+        // always points at `self.ch`.
+        if let Some(ch) = self.ch {
+            if ch == '\n' {
+                self.line += 1;
+                self.col = 1;
+            } else {
+                self.col += 1;
+            }
+        }
+
+        match self.lookahead.take() {
+            None => {
+                self.ch = self.reader.next();
+            }
+            Some(ch) => {
+                self.ch = Some(ch);
+            }
+        }
+    }
+
+    fn position(&self) -> Position {
+        Position::new(self.line, self.col)
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        match self.lookahead {
+            None => {
+                self.lookahead = self.reader.next();
+                self.lookahead
+            }
+            Some(ch) => Some(ch),
+        }
+    }
+
+    fn ch_is(&self, ch: char) -> bool {
+        match self.ch {
+            Some(c) => c == ch,
+            None => false,
+        }
+    }
+
+    /// Parse the template into tokens and a list of partial files.
+    pub fn parse(mut self) -> Result<(Vec<Token>, Vec<String>), Error> {
+        let mut curly_brace_tag = false;
+
+        while let Some(ch) = self.ch {
+            match self.state {
+                ParserState::Text => {
+                    if ch == self.opening_tag_chars[0] {
+                        self.tag_start = self.position();
+                        if self.opening_tag_chars.len() > 1 {
+                            self.tag_position = 1;
+                            self.state = ParserState::OpeningTag;
+                        } else {
+                            self.add_text();
+                            self.state = ParserState::Tag;
+                        }
+                    } else {
+                        self.content.push(ch);
+                    }
+                    self.bump();
+                }
+                ParserState::OpeningTag => {
+                    if ch == self.opening_tag_chars[self.tag_position] {
+                        if self.tag_position == self.opening_tag_chars.len() - 1 {
+                            self.add_text();
+                            curly_brace_tag = false;
+                            self.state = ParserState::Tag;
+                        } else {
+                            self.tag_position = self.tag_position + 1;
+                        }
+                    } else {
+                        // We don't have a tag, so add all the tag parts we've seen
+                        // so far to the string.
+                        self.state = ParserState::Text;
+                        self.not_otag();
+                        self.content.push(ch);
+                    }
+                    self.bump();
+                }
+                ParserState::Tag => {
+                    if self.content.is_empty() && ch == '{' {
+                        curly_brace_tag = true;
+                        self.content.push(ch);
+                        self.bump();
+                    } else if curly_brace_tag && ch == '}' {
+                        curly_brace_tag = false;
+                        self.content.push(ch);
+                        self.bump();
+                    } else if ch == self.closing_tag_chars[0] {
+                        if self.closing_tag_chars.len() > 1 {
+                            self.tag_position = 1;
+                            self.state = ParserState::ClosingTag;
+                            self.bump();
+                        } else {
+                            try!(self.add_tag());
+                            self.state = ParserState::Text;
+                        }
+                    } else {
+                        self.content.push(ch);
+                        self.bump();
+                    }
+                }
+                ParserState::ClosingTag => {
+                    if ch == self.closing_tag_chars[self.tag_position] {
+                        if self.tag_position == self.closing_tag_chars.len() - 1 {
+                            try!(self.add_tag());
+                            self.state = ParserState::Text;
+                        } else {
+                            self.state = ParserState::Tag;
+                            self.not_ctag();
+                            self.content.push(ch);
+                            self.bump();
+                        }
+                    } else {
+                        let expected = self.closing_tag_chars[self.tag_position];
+                        return Err(Error::new(ErrorKind::BadClosingTag(ch, expected),
+                                              Span::at(self.position())));
+                    }
+                }
+            }
+        }
+
+        // Any text left over runs up to the end of the template.
+        if let ParserState::Text = self.state {
+            self.tag_start = self.position();
+        }
+
+        match self.state {
+            ParserState::Text => {
+                self.add_text();
+            }
+            ParserState::OpeningTag => {
+                self.not_otag();
+                self.tag_start = self.position();
+                self.add_text();
+            }
+            ParserState::ClosingTag => {
+                self.not_ctag();
+                self.tag_start = self.position();
+                self.add_text();
+            }
+            ParserState::Tag => {
+                let span = Span::new(self.tag_start, self.position());
+                return Err(Error::new(ErrorKind::UnclosedTag, span))
+            }
+        }
+
+        // Check that we don't have any incomplete sections, blocks or parents.
+        for token in self.tokens.iter().rev() {
+            match *token {
+                Token::IncompleteSection(ref path, _, _, _, span) => {
+                    return Err(Error::new(ErrorKind::UnclosedSection(path.join(".")), span))
+                }
+                Token::IncompleteBlock(ref name, _, _, _, span) |
+                Token::IncompleteParent(ref name, _, _, span) => {
+                    return Err(Error::new(ErrorKind::UnclosedSection(name.clone()), span))
+                }
+                _ => {}
+            }
+        }
+
+        let Parser { tokens, partials, .. } = self;
+
+        Ok((tokens, partials))
+    }
+
+    fn add_text(&mut self) {
+        if !self.content.is_empty() {
+            let mut content = String::new();
+            mem::swap(&mut content, &mut self.content);
+
+            let span = Span::new(self.text_start, self.tag_start);
+            self.tokens.push(Token::Text(content, span));
+        }
+    }
+
+    // This function classifies whether or not a token is standalone, or if it
+    // has trailing whitespace. It's looking for this pattern:
+    //
+    //   ("\n" | "\r\n") whitespace* token ("\n" | "\r\n")
+    //
+    fn classify_token(&mut self) -> TokenClass {
+        // Exit early if the next character is not '\n' or '\r\n'.
+        if let Some(ch) = self.ch {
+            if !(ch == '\n' || (ch == '\r' && self.peek() == Some('\n'))) {
+                return TokenClass::Normal;
+            }
+        }
+
+        match self.tokens.last() {
+            // If the last token ends with a newline (or there is no previous
+            // token), then this token is standalone.
+            None |
+            Some(&Token::IncompleteSection(_, _, _, true, _)) |
+            Some(&Token::IncompleteBlock(_, _, true, _, _)) |
+            Some(&Token::IncompleteParent(_, _, true, _)) => TokenClass::StandAlone,
+
+            Some(&Token::Text(ref s, _)) if !s.is_empty() => {
+                // Look for the last newline character that may have whitespace
+                // following it.
+                match s.rfind(|c: char| c == '\n' || !c.is_whitespace()) {
+                    // It's all whitespace.
+                    None => {
+                        match self.tokens.iter().rev().nth(1) {
+                            None |
+                            Some(&Token::IncompleteBlock(_, _, true, _, _)) |
+                            Some(&Token::IncompleteParent(_, _, true, _)) => {
+                                TokenClass::WhiteSpace(s.clone(), 0)
+                            }
+                            _ => TokenClass::Normal,
+                        }
+                    }
+                    Some(pos) => {
+                        if s.as_bytes()[pos] == b'\n' {
+                            if pos == s.len() - 1 {
+                                TokenClass::StandAlone
+                            } else {
+                                TokenClass::WhiteSpace(s.clone(), pos + 1)
+                            }
+                        } else {
+                            TokenClass::Normal
+                        }
+                    }
+                }
+            }
+            Some(_) => TokenClass::Normal,
+        }
+    }
+
+    fn eat_whitespace(&mut self) -> bool {
+        self.eat_standalone().is_some()
+    }
+
+    // If the next character is a newline, and the last token ends with a
+    // newline and whitespace, clear out the whitespace. Returns the
+    // whitespace that was removed if the token was standalone.
+    fn eat_standalone(&mut self) -> Option<String> {
+        match self.classify_token() {
+            TokenClass::Normal => None,
+            TokenClass::StandAlone => {
+                if self.ch_is('\r') {
+                    self.bump();
+                }
+                self.bump();
+                Some("".to_string())
+            }
+            TokenClass::WhiteSpace(s, pos) => {
+                if self.ch_is('\r') {
+                    self.bump();
+                }
+                self.bump();
+
+                // Trim the whitespace from the last token.
+                if let Some(Token::Text(_, span)) = self.tokens.pop() {
+                    let text = &s[0..pos];
+                    let span = Span::new(span.start, span.start.advance(text));
+                    self.tokens.push(Token::Text(text.to_string(), span));
+                }
+
+                Some(s[pos..].to_string())
+            }
+        }
+    }
+
+    // Returns the whitespace in front of the current position if nothing else
+    // has been written on this line so far.
+    fn line_indent(&self) -> Option<String> {
+        match self.tokens.last() {
+            Some(&Token::Text(ref s, _)) => {
+                let (ws, line_start) = match s.rfind('\n') {
+                    Some(pos) => (&s[pos + 1..], true),
+                    None => (&s[..], opens_line(self.tokens.iter().rev().nth(1))),
+                };
+
+                if line_start && ws.chars().all(char::is_whitespace) {
+                    Some(ws.to_string())
+                } else {
+                    None
+                }
+            }
+            last => {
+                if opens_line(last) {
+                    Some("".to_string())
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    fn add_tag(&mut self) -> Result<(), Error> {
+        self.bump();
+
+        let tag = self.opening_tag.clone() + &self.content + &self.closing_tag;
+        let span = Span::new(self.tag_start, self.position());
+
+        // Move the content to avoid a copy.
+        let mut content = String::new();
+        mem::swap(&mut content, &mut self.content);
+        let len = content.len();
+        try!(deny_blank(&content, span));
+        let content = content;
+
+        match content.as_bytes()[0] as char {
+            '!' => {
+                // ignore comments
+                self.eat_whitespace();
+            }
+            '&' => {
+                let name = &content[1..len];
+                let (name, filters, format) = try!(self.get_pipeline(name, span));
+                self.tokens.push(Token::UnescapedTag(name, filters, format, tag, span));
+            }
+            '{' => {
+                if content.ends_with('}') {
+                    let name = &content[1..len - 1];
+                    let (name, filters, format) = try!(self.get_pipeline(name, span));
+                    self.tokens.push(Token::UnescapedTag(name, filters, format, tag, span));
+                } else {
+                    return Err(Error::new(ErrorKind::UnbalancedUnescapeTag, span))
+                }
+            }
+            '#' => {
+                let newlined = self.eat_whitespace();
+
+                let name = try!(get_name_or_implicit(&content[1..len], span));
+                self.tokens.push(Token::IncompleteSection(name, false, tag, newlined, span));
+            }
+            '^' => {
+                let newlined = self.eat_whitespace();
+
+                let name = try!(get_name_or_implicit(&content[1..len], span));
+                self.tokens.push(Token::IncompleteSection(name, true, tag, newlined, span));
+            }
+            '/' => {
+                try!(self.close_tag(&content[1..len], tag, span));
+            }
+            '$' => {
+                let name = try!(deny_blank(&content[1..len], span)).to_string();
+
+                // A block may either be standalone on its line, or sit behind
+                // nothing but whitespace. Either way we remember the indentation
+                // so the content can be reindented wherever it gets rendered.
+                let (standalone, indent) = match self.eat_standalone() {
+                    Some(indent) => (true, indent),
+                    None => (false, self.line_indent().unwrap_or_default()),
+                };
+
+                self.tokens.push(Token::IncompleteBlock(name, tag, standalone, indent, span));
+            }
+            '<' => {
+                let name = try!(deny_blank(&content[1..len], span)).to_string();
+
+                // Whether or not a parent is standalone can only be decided once
+                // it's closed, as everything but its blocks is ignored.
+                let line_start = self.line_indent().is_some();
+                self.tokens.push(Token::IncompleteParent(name, tag, line_start, span));
+            }
+            '>' => {
+                try!(self.add_partial(&content, tag, span));
+            }
+            '=' => {
+                self.eat_whitespace();
+
+                if len > 2usize && content.ends_with('=') {
+                    let s = try!(deny_blank(&content[1..len - 1], span));
+
+                    let pos = s.find(char::is_whitespace);
+                    let pos = match pos {
+                        None => {
+                            return Err(Error::new(ErrorKind::MissingSetDelimeterClosingTag, span))
+                        }
+                        Some(pos) => pos,
+                    };
+
+                    self.opening_tag = s[0..pos].to_string();
+                    self.opening_tag_chars = self.opening_tag.chars().collect();
+
+                    let s2 = &s[pos..];
+                    let pos = s2.find(|c: char| !c.is_whitespace());
+                    let pos = match pos {
+                        None => {
+                            return Err(Error::new(ErrorKind::MissingSetDelimeterClosingTag, span))
+                        }
+                        Some(pos) => pos,
+                    };
+
+                    self.closing_tag = s2[pos..].to_string();
+                    self.closing_tag_chars = self.closing_tag.chars().collect();
+                } else {
+                    return Err(Error::new(ErrorKind::InvalidSetDelimeterSyntax, span))
+                }
+            }
+            _ => {
+                // If the name is "." then we want the top element, which we represent with
+                // an empty name.
+                let (name, filters, format) = try!(self.get_pipeline(&content, span));
+                self.tokens.push(Token::EscapedTag(name, filters, format, tag, span));
+            }
+        };
+
+        // Whatever comes next starts after the tag, and after any whitespace
+        // the tag consumed.
+        self.text_start = self.position();
+
+        Ok(())
+    }
+
+    /// The name, filters and format spec of an interpolation tag.
+    fn get_pipeline(&self,
+                    content: &str,
+                    span: Span)
+                    -> Result<(Vec<String>, Vec<(String, Vec<Arg>)>, Option<Format>), Error> {
+        if self.pipelines {
+            get_pipeline(content, span)
+        } else {
+            Ok((try!(get_name_or_implicit(content, span)), Vec::new(), None))
+        }
+    }
+
+    fn add_partial(&mut self, content: &str, tag: String, span: Span) -> Result<(), Error> {
+        let indent = self.eat_standalone().unwrap_or_default();
+
+        // We can't inline the tokens directly as we may have a recursive
+        // partial. So instead, we'll cache the partials we used and look them
+        // up later.
+        let name = &content[1..content.len()];
+        let name = try!(deny_blank(name, span));
+
+        // A dynamic partial names the data holding the name of the partial,
+        // so it can only be looked up when rendering.
+        if name.starts_with('*') {
+            let path = try!(get_name_or_implicit(&name[1..], span));
+            self.tokens.push(Token::DynamicPartial(path, indent, tag, span));
+            return Ok(());
+        }
+
+        self.tokens.push(Token::Partial(name.into(), indent, tag, span));
+        self.partials.push(name.into());
+
+        Ok(())
+    }
+
+    fn close_tag(&mut self, name: &str, tag: String, span: Span) -> Result<(), Error> {
+        let path = try!(get_name_or_implicit(name, span));
+        let name = path.join(".");
+
+        let closes_parent = match self.tokens.iter().rev().find(|token| is_incomplete(token)) {
+            Some(&Token::IncompleteParent(..)) => true,
+            _ => false,
+        };
+
+        // A parent is standalone as a whole, so we can only tell once all of
+        // its children have been collected.
+        if !closes_parent {
+            self.eat_whitespace();
+        }
+
+        let mut children: Vec<Token> = Vec::new();
+
+        let opening = loop {
+            match self.tokens.pop() {
+                None => return Err(Error::new(ErrorKind::EarlySectionClose(name), span)),
+                Some(token) => {
+                    if is_incomplete(&token) {
+                        break token;
+                    }
+                    children.push(token);
+                }
+            }
+        };
+
+        children.reverse();
+
+        // Cache the combination of all the sources in the section. It's
+        // unfortunate, but we need to do this in case the user uses a function
+        // to instantiate the tag.
+        let mut src = String::new();
+        for child in children.iter() {
+            push_source(&mut src, child);
+        }
+
+        match opening {
+            Token::IncompleteSection(section_name, inverted, osection, _, open) => {
+                if section_name != path {
+                    let kind = ErrorKind::UnclosedSection(section_name.join("."));
+                    return Err(Error::new(kind, open));
+                }
+
+                self.tokens.push(Token::Section(path,
+                                                inverted,
+                                                children,
+                                                self.opening_tag.clone(),
+                                                osection,
+                                                src,
+                                                tag,
+                                                self.closing_tag.clone(),
+                                                Span::new(open.start, span.end)));
+            }
+            Token::IncompleteBlock(block_name, osection, standalone, indent, open) => {
+                if block_name != name {
+                    return Err(Error::new(ErrorKind::UnclosedSection(block_name), open));
+                }
+
+                // A standalone block takes its indentation from its content, and
+                // that indentation is stripped so it can be replaced by the
+                // indentation of wherever the block ends up.
+                let indent = if standalone {
+                    first_line_indent(&children).unwrap_or(indent)
+                } else {
+                    indent
+                };
+                dedent(&mut children, &indent, standalone);
+
+                let span = Span::new(open.start, span.end);
+                let children = Arc::new(children);
+                self.tokens.push(Token::Block(name, indent, children, osection, src, tag, span));
+            }
+            Token::IncompleteParent(parent_name, osection, _, open) => {
+                if parent_name != name {
+                    return Err(Error::new(ErrorKind::UnclosedSection(parent_name), open));
+                }
+
+                // Only the blocks inside of a parent matter, anything else is
+                // ignored.
+                let blocks = children.into_iter()
+                    .filter(|child| match *child {
+                        Token::Block(..) => true,
+                        _ => false,
+                    })
+                    .collect();
+
+                let indent = self.eat_standalone().unwrap_or_default();
+
+                // Parents are resolved just like partials.
+                self.partials.push(name.clone());
+                let span = Span::new(open.start, span.end);
+                self.tokens.push(Token::Parent(name, indent, blocks, osection, src, tag, span));
+            }
+            _ => bug!("close_tag should only pop incomplete tokens"),
+        }
+
+        Ok(())
+    }
+
+    fn not_otag(&mut self) {
+        for (i, ch) in self.opening_tag_chars.iter().enumerate() {
+            if !(i < self.tag_position) {
+                break;
+            }
+            self.content.push(*ch);
+        }
+    }
+
+    fn not_ctag(&mut self) {
+        for (i, ch) in self.closing_tag_chars.iter().enumerate() {
+            if !(i < self.tag_position) {
+                break;
+            }
+            self.content.push(*ch);
+        }
+    }
+}
+
+fn is_incomplete(token: &Token) -> bool {
+    match *token {
+        Token::IncompleteSection(..) |
+        Token::IncompleteBlock(..) |
+        Token::IncompleteParent(..) => true,
+        _ => false,
+    }
+}
+
+// Whether the token is a line start, either because it is the start of the
+// template, or because it is a standalone opening tag.
+fn opens_line(token: Option<&Token>) -> bool {
+    match token {
+        None |
+        Some(&Token::IncompleteSection(_, _, _, true, _)) |
+        Some(&Token::IncompleteBlock(_, _, true, _, _)) |
+        Some(&Token::IncompleteParent(_, _, true, _)) => true,
+        _ => false,
+    }
+}
+
+fn push_source(src: &mut String, token: &Token) {
+    match *token {
+        Token::Text(ref s, _) |
+        Token::EscapedTag(_, _, _, ref s, _) |
+        Token::UnescapedTag(_, _, _, ref s, _) |
+        Token::Partial(_, _, ref s, _) |
+        Token::DynamicPartial(_, _, ref s, _) => src.push_str(s),
+        Token::Section(_, _, _, _, ref osection, ref s, ref csection, _, _) |
+        Token::Block(_, _, _, ref osection, ref s, ref csection, _) |
+        Token::Parent(_, _, _, ref osection, ref s, ref csection, _) => {
+            src.push_str(osection);
+            src.push_str(s);
+            src.push_str(csection);
+        }
+        _ => bug!("Incomplete sections should not be nested"),
+    }
+}
+
+// The indentation of the first line of content, if there is any content.
+fn first_line_indent(tokens: &[Token]) -> Option<String> {
+    let first = tokens.iter().find(|token| match **token {
+        Token::Text(ref s, _) => !s.is_empty(),
+        _ => true,
+    });
+
+    match first {
+        Some(&Token::Text(ref s, _)) => {
+            Some(s.chars().take_while(|&c| c != '\n' && c.is_whitespace()).collect())
+        }
+        Some(_) => Some("".to_string()),
+        None => None,
+    }
+}
+
+// Strip `indent` from the start of every line in the tokens. Returns whether
+// the tokens ended at the start of a line.
+fn dedent(tokens: &mut [Token], indent: &str, mut line_start: bool) -> bool {
+    if indent.is_empty() {
+        return line_start;
+    }
+
+    for token in tokens.iter_mut() {
+        match *token {
+            Token::Text(ref mut s, _) => {
+                let mut text = String::with_capacity(s.len());
+
+                for (i, line) in s.split('\n').enumerate() {
+                    if i > 0 {
+                        text.push('\n');
+                        line_start = true;
+                    }
+
+                    if line_start && line.starts_with(indent) {
+                        text.push_str(&line[indent.len()..]);
+                    } else {
+                        text.push_str(line);
+                    }
+
+                    if !line.is_empty() {
+                        line_start = false;
+                    }
+                }
+
+                *s = text;
+            }
+            Token::Section(_, _, ref mut children, _, _, _, _, _, _) => {
+                line_start = dedent(children, indent, line_start);
+            }
+            Token::Block(_, ref mut block_indent, ref children, _, _, _, _) => {
+                // The content of a block is relative to the block itself.
+                if block_indent.starts_with(indent) {
+                    block_indent.drain(..indent.len());
+                }
+                line_start = ends_line(children);
+            }
+            Token::Partial(_, ref mut partial_indent, _, _) |
+            Token::DynamicPartial(_, ref mut partial_indent, _, _) |
+            Token::Parent(_, ref mut partial_indent, _, _, _, _, _) => {
+                // Only standalone partials have an indentation, and they
+                // consume the rest of their line.
+                if partial_indent.starts_with(indent) {
+                    partial_indent.drain(..indent.len());
+                    line_start = true;
+                } else {
+                    line_start = false;
+                }
+            }
+            _ => line_start = false,
+        }
+    }
+
+    line_start
+}
+
+fn ends_line(tokens: &[Token]) -> bool {
+    match tokens.last() {
+        Some(&Token::Text(ref s, _)) => s.ends_with('\n'),
+        _ => false,
+    }
+}
+
+fn get_name_or_implicit(name: &str, span: Span) -> Result<Vec<String>, Error> {
+    // If the name is "." then we want the top element, which we represent with
+    // an empty name.
+    let name = try!(deny_blank(&name, span));
+    Ok(if name == "." {
+        Vec::new()
+    } else {
+        name.split_terminator('.')
+            .map(|x| x.to_string())
+            .collect()
+    })
+}
+
+/// Splits an interpolation tag like `price | round 1 :>8.2` into the name, the
+/// filters with their arguments, and the format spec after the first `:`.
+/// Quoted filter arguments may contain `|`, `:` and whitespace.
+fn get_pipeline(content: &str,
+                span: Span)
+                -> Result<(Vec<String>, Vec<(String, Vec<Arg>)>, Option<Format>), Error> {
+    let invalid = |filter: &str| Error::new(ErrorKind::InvalidFilter(filter.trim().to_string()), span);
+
+    // The words of every `|` separated segment.
+    let mut segments = vec![Vec::new()];
+    let mut word = None;
+    let mut quoted = false;
+    let mut spec = None;
+
+    let mut chars = content.char_indices();
+    while let Some((pos, ch)) = chars.next() {
+        if quoted {
+            let word = word.get_or_insert_with(|| (String::new(), true));
+            match ch {
+                '"' => quoted = false,
+                '\\' => {
+                    match chars.next() {
+                        Some((_, ch)) => word.0.push(ch),
+                        None => return Err(invalid(content)),
+                    }
+                }
+                _ => word.0.push(ch),
+            }
+            continue;
+        }
+
+        match ch {
+            '"' if word.is_none() => {
+                quoted = true;
+                word = Some((String::new(), true));
+            }
+            '|' | ':' => {
+                flush(&mut segments, &mut word);
+
+                if ch == ':' {
+                    spec = Some(content[pos + 1..].trim_end());
+                    break;
+                }
+                segments.push(Vec::new());
+            }
+            ch if ch.is_whitespace() => flush(&mut segments, &mut word),
+            ch => word.get_or_insert_with(|| (String::new(), false)).0.push(ch),
+        }
+    }
+
+    if quoted {
+        return Err(invalid(content));
+    }
+    flush(&mut segments, &mut word);
+
+    // The name is everything before the first `|` or `:`, whitespace included,
+    // so it's taken from the content rather than from the words.
+    let name_end = content.find(|ch| ch == '|' || ch == ':').unwrap_or(content.len());
+    let name = try!(get_name_or_implicit(&content[..name_end], span));
+
+    let mut filters = Vec::new();
+    for args in segments.into_iter().skip(1) {
+        let mut args = args.into_iter();
+        match args.next() {
+            Some(Arg::String(filter)) => filters.push((filter, args.collect())),
+            Some(arg) => return Err(invalid(&arg.to_string())),
+            None => return Err(invalid(content)),
+        }
+    }
+
+    let format = match spec {
+        Some(spec) => {
+            match Format::parse(spec) {
+                Some(format) => Some(format),
+                None => return Err(Error::new(ErrorKind::InvalidFormat(spec.to_string()), span)),
+            }
+        }
+        None => None,
+    };
+
+    Ok((name, filters, format))
+}
+
+/// Ends the word being read, if there is one, as an argument of the current
+/// segment of a pipeline.
+fn flush(segments: &mut Vec<Vec<Arg>>, word: &mut Option<(String, bool)>) {
+    if let Some((word, quoted)) = word.take() {
+        if let Some(segment) = segments.last_mut() {
+            segment.push(Arg::parse(word, quoted));
+        }
+    }
+}
+
+fn deny_blank(content: &str, span: Span) -> Result<&str, Error> {
+    let trimmed = content.trim();
+    if trimmed.is_empty() {
+        Err(Error::new(ErrorKind::EmptyTag, span))
+    } else {
+        Ok(trimmed)
+    }
+}
+
+//FIXME: These tests are mainly to guide the removal of panics from the parser (turning them
+// into `Error`s instead). It would be good to make them more robust and make assertions on
+// the tokens & partials returned.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    pub fn parse(input: &str) -> Result<(Vec<Token>, Vec<String>), ErrorKind> {
+        parse_with_position(input).map_err(|err| err.kind)
+    }
+
+    pub fn parse_with_position(input: &str) -> Result<(Vec<Token>, Vec<String>), Error> {
+        let input = &mut input.chars();
+        let parser = Parser::new(input, "{{", "}}");
+        parser.parse()
+    }
+
+    pub fn error_position(input: &str) -> (usize, usize) {
+        let err = parse_with_position(input).err().expect("expected a parse error");
+        (err.line(), err.col())
+    }
+
+    pub fn assert_parse(input: &str) -> (Vec<Token>, Vec<String>) {
+        parse(input).expect(&format!("Failed to parse: {}", input))
+    }
+
+    #[test]
+    fn empty_input() {
+        assert_parse("");
+    }
+
+    #[test]
+    fn empty_tag() {
+        assert_eq!(parse("{{}}"), Err(ErrorKind::EmptyTag));
+    }
+
+    #[test]
+    fn whitespace_only_tag() {
+        assert_eq!(parse("{{ }}"), Err(ErrorKind::EmptyTag));
+    }
+
+    #[test]
+    fn bad_closing_tag() {
+        assert_eq!(parse("{{hello}?"), Err(ErrorKind::BadClosingTag('?', '}')))
+    }
+
+    #[test]
+    fn unclosed_tag() {
+        assert_eq!(parse("{{hi"), Err(ErrorKind::UnclosedTag))
+    }
+
+    mod sections {
+        use super::*;
+
+        #[test]
+        fn sanity() {
+            assert_parse("{{#people}} Hi {{name}}! {{/people}}");
+        }
+
+        #[test]
+        fn unclosed() {
+            assert_eq!(parse("{{#world}}hi"), Err(ErrorKind::UnclosedSection("world".into())))
+        }
+
+        #[test]
+        fn unclosed_nested_with_wrong_closing_tag() {
+            assert_eq!(
+                parse("{{#universe}} {{#world}} {{/universe}}"),
+                Err(ErrorKind::UnclosedSection("world".into()))
+            )
+        }
+
+        #[test]
+        fn unclosed_nested() {
+            assert_eq!(
+                parse("{{#universe}} {{#world}}"),
+                Err(ErrorKind::UnclosedSection("world".into()))
+            )
+        }
+
+        #[test]
+        fn unclosed_with_path() {
+            assert_eq!(
+                parse("{{#universe}} {{#world.and.stuff}} {{/universe}}"),
+                Err(ErrorKind::UnclosedSection("world.and.stuff".into()))
+            )
+        }
+
+        #[test]
+        fn early_close() {
+            assert_eq!(parse("{{/world}}"), Err(ErrorKind::EarlySectionClose("world".into())))
+        }
+    }
+
+    mod inverted {
+        use super::*;
+
+        #[test]
+        fn sanity() {
+            assert_parse("{{^people}} No people! {{/people}}");
+        }
+
+        #[test]
+        fn unclosed() {
+            assert_eq!(parse("{{^world}}hi"), Err(ErrorKind::UnclosedSection("world".into())))
+        }
+
+        #[test]
+        fn unclosed_nested_with_wrong_closing_tag() {
+            assert_eq!(
+                parse("{{#universe}} {{^world}} {{/universe}}"),
+                Err(ErrorKind::UnclosedSection("world".into()))
+            );
+
+            assert_eq!(
+                parse("{{^universe}} {{^world}} {{/universe}}"),
+                Err(ErrorKind::UnclosedSection("world".into()))
+            )
+        }
+
+        #[test]
+        fn unclosed_nested() {
+            assert_eq!(
+                parse("{{#universe}} {{^world}}"),
+                Err(ErrorKind::UnclosedSection("world".into()))
+            )
+        }
+
+        #[test]
+        fn unclosed_with_path() {
+            assert_eq!(
+                parse("{{#universe}} {{^world.and.stuff}} {{/universe}}"),
+                Err(ErrorKind::UnclosedSection("world.and.stuff".into()))
+            )
+        }
+    }
+
+    mod dynamic_partials {
+        use super::*;
+
+        #[test]
+        fn sanity() {
+            let (tokens, partials) = assert_parse("{{> *foo.bar }}");
+            assert_eq!(tokens, vec![Token::DynamicPartial(vec!["foo".into(), "bar".into()],
+                                                          "".into(),
+                                                          "{{> *foo.bar }}".into(),
+                                                          Span::new(Position::new(1, 1),
+                                                                    Position::new(1, 16)))]);
+            assert!(partials.is_empty());
+        }
+
+        #[test]
+        fn empty_name() {
+            assert_eq!(parse("{{>* }}"), Err(ErrorKind::EmptyTag))
+        }
+    }
+
+    mod inheritance {
+        use super::*;
+
+        #[test]
+        fn sanity() {
+            assert_parse("{{<parent}} ignored {{$title}}Hi {{name}}!{{/title}} {{/parent}}");
+        }
+
+        #[test]
+        fn parents_are_partials() {
+            let (_, partials) = assert_parse("{{<parent}}{{/parent}}");
+            assert_eq!(partials, vec!["parent".to_string()]);
+        }
+
+        #[test]
+        fn unclosed_block() {
+            assert_eq!(parse("{{$title}}hi"), Err(ErrorKind::UnclosedSection("title".into())))
+        }
+
+        #[test]
+        fn unclosed_parent() {
+            assert_eq!(
+                parse("{{<parent}} {{$title}}hi{{/title}}"),
+                Err(ErrorKind::UnclosedSection("parent".into()))
+            )
+        }
+
+        #[test]
+        fn block_closed_by_parent() {
+            assert_eq!(
+                parse("{{<parent}} {{$title}}hi {{/parent}}"),
+                Err(ErrorKind::UnclosedSection("title".into()))
+            )
+        }
+    }
+
+    mod set_delimeter {
+        use super::*;
+
+        #[test]
+        fn sanity() {
+            assert_parse("{{=<% %>=}}");
+        }
+
+        #[test]
+        fn closing_tag_is_whitespace() {
+            assert_eq!(parse("{{=<% =}}"), Err(ErrorKind::MissingSetDelimeterClosingTag))
+        }
+
+        #[test]
+        fn missing_closing_tag() {
+            assert_eq!(parse("{{=<%=}}"), Err(ErrorKind::MissingSetDelimeterClosingTag))
+        }
+
+        #[test]
+        fn missing_closing_equals() {
+            assert_eq!(parse("{{=<% %>}}"), Err(ErrorKind::InvalidSetDelimeterSyntax))
+        }
+    }
+
+    #[test]
+    fn format_spec() {
+        let (tokens, _) = parse("{{ price:>8.2 }}{{{count:,}}}").unwrap();
+        match tokens[0] {
+            Token::EscapedTag(ref name, _, Some(ref format), _, _) => {
+                assert_eq!(name, &vec!["price".to_string()]);
+                assert_eq!(format.spec(), ">8.2");
+            }
+            ref token => panic!("unexpected token {:?}", token),
+        }
+        match tokens[1] {
+            Token::UnescapedTag(_, _, Some(ref format), _, _) => assert_eq!(format.spec(), ","),
+            ref token => panic!("unexpected token {:?}", token),
+        }
+
+        assert_eq!(parse("{{price:8.x}}"), Err(ErrorKind::InvalidFormat("8.x".to_string())));
+        assert_eq!(parse("{{x:99999999999999}}"),
+                   Err(ErrorKind::InvalidFormat("99999999999999".to_string())));
+        assert_eq!(parse("{{:8}}"), Err(ErrorKind::EmptyTag));
+    }
+
+    #[test]
+    fn without_pipelines() {
+        let input = &mut "{{a|b:c}}{{{ d : 8 }}}".chars();
+        let (tokens, _) = Parser::new(input, "{{", "}}").pipelines(false).parse().unwrap();
+        match tokens[0] {
+            Token::EscapedTag(ref name, ref filters, None, _, _) => {
+                assert_eq!(name, &vec!["a|b:c".to_string()]);
+                assert!(filters.is_empty());
+            }
+            ref token => panic!("unexpected token {:?}", token),
+        }
+        match tokens[1] {
+            Token::UnescapedTag(ref name, _, None, _, _) => assert_eq!(name, &vec!["d : 8".to_string()]),
+            ref token => panic!("unexpected token {:?}", token),
+        }
+    }
+
+    #[test]
+    fn filters() {
+        let (tokens, _) = parse("{{ name | truncate 20 \"a | b: \\\"c\\\"\" -1.5 | upper :>8}}").unwrap();
+        match tokens[0] {
+            Token::EscapedTag(ref name, ref filters, ref format, _, _) => {
+                assert_eq!(name, &vec!["name".to_string()]);
+                assert_eq!(filters,
+                           &vec![("truncate".to_string(),
+                                  vec![Arg::Int(20),
+                                       Arg::String("a | b: \"c\"".to_string()),
+                                       Arg::Float(-1.5)]),
+                                 ("upper".to_string(), vec![])]);
+                assert_eq!(format.as_ref().map(|format| format.spec()), Some(">8"));
+            }
+            ref token => panic!("unexpected token {:?}", token),
+        }
+
+        assert_eq!(parse("{{name | }}"), Err(ErrorKind::InvalidFilter("name |".to_string())));
+        assert_eq!(parse("{{name | 5}}"), Err(ErrorKind::InvalidFilter("5".to_string())));
+        assert_eq!(parse("{{name | x \"y}}"),
+                   Err(ErrorKind::InvalidFilter("name | x \"y".to_string())));
+        assert_eq!(parse("{{ | upper}}"), Err(ErrorKind::EmptyTag));
+    }
+
+    #[test]
+    fn unbalanced_unescape() {
+        // use the set delimiter tag to change the brace type. Currently this error will
+        // not trigger with "{{{ }}"
+        let input = "{{=<% %>=}} <%{ %>";
+        assert_eq!(parse(input), Err(ErrorKind::UnbalancedUnescapeTag))
+    }
+
+    mod positions {
+        use super::*;
+
+        fn span(start: (usize, usize), end: (usize, usize)) -> Span {
+            Span::new(Position::new(start.0, start.1), Position::new(end.0, end.1))
+        }
+
+        #[test]
+        fn token_spans() {
+            let (tokens, _) = assert_parse("hi\n  {{name}}!\n{{>part}}");
+            assert_eq!(tokens,
+                       vec![Token::Text("hi\n  ".into(), span((1, 1), (2, 3))),
+                            Token::EscapedTag(vec!["name".into()],
+                                              vec![],
+                                              None,
+                                              "{{name}}".into(),
+                                              span((2, 3), (2, 11))),
+                            Token::Text("!\n".into(), span((2, 11), (3, 1))),
+                            Token::Partial("part".into(),
+                                           "".into(),
+                                           "{{>part}}".into(),
+                                           span((3, 1), (3, 10)))]);
+        }
+
+        #[test]
+        fn section_spans_cover_the_closing_tag() {
+            let (tokens, _) = assert_parse("a\n{{#s}}\nb\n{{/s}}\nc");
+            assert_eq!(tokens.len(), 3);
+            assert_eq!(tokens[0], Token::Text("a\n".into(), span((1, 1), (2, 1))));
+            match tokens[1] {
+                Token::Section(_, _, ref children, _, _, _, _, _, section_span) => {
+                    assert_eq!(section_span, span((2, 1), (4, 7)));
+                    assert_eq!(*children, vec![Token::Text("b\n".into(), span((3, 1), (4, 1)))]);
+                }
+                ref token => panic!("expected a section, found {:?}", token),
+            }
+            assert_eq!(tokens[2], Token::Text("c".into(), span((5, 1), (5, 2))));
+        }
+
+        #[test]
+        fn unclosed_tag() {
+            assert_eq!(error_position("hello\n  {{name"), (2, 3));
+        }
+
+        #[test]
+        fn bad_closing_tag() {
+            assert_eq!(error_position("hello\n{{name}?"), (2, 8));
+        }
+
+        #[test]
+        fn unclosed_section() {
+            assert_eq!(error_position("a\nb {{#outer}}\n{{#inner}}{{/inner}}"), (2, 3));
+        }
+
+        #[test]
+        fn empty_tag() {
+            assert_eq!(error_position("\n\n   {{ }}"), (3, 4));
+        }
+
+        #[test]
+        fn display() {
+            let err = parse_with_position("\n{{#a}}").err().unwrap();
+            assert_eq!(err.to_string(), "2:1: found an unclosed section: \"a\"");
+
+            let mut err = err;
+            set_file(&mut err, Path::new("page.mustache"));
+            assert_eq!(err.to_string(),
+                       "page.mustache:2:1: found an unclosed section: \"a\"");
+        }
+    }
+}
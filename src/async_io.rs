@@ -0,0 +1,94 @@
+//! Rendering to a tokio `AsyncWrite`. This needs the `async` feature.
+//!
+//! The future `render_async` and `render_data_async` return renders the
+//! template with a `Stream`, writing each chunk before it renders the next,
+//! so the whole output is never held in memory. Rendering doesn't do any I/O
+//! itself, except for reading partials that weren't known when the template
+//! was compiled.
+//!
+//! The future is `Send` when the writer is, since `ContextValue`s are `Sync`,
+//! so it can be passed to `tokio::spawn` along with the template and the data.
+
+use std::borrow::Cow;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context as TaskContext, Poll};
+
+use tokio::io::AsyncWrite;
+
+use super::{ContextValue, Data, Error, Result, Stream, Template};
+
+impl Template {
+    /// Renders the template with anything that implements `ContextValue`,
+    /// like `render_value` does, returning a future that writes it to `wr`.
+    /// Data that's only `Serialize` can be turned into `Data` with `to_data`
+    /// first.
+    pub fn render_async<'a, W>(&'a self, wr: &'a mut W, data: &'a ContextValue) -> RenderAsync<'a, W>
+    where W: AsyncWrite + Unpin + ?Sized,
+    {
+        RenderAsync::new(wr, self.stream_value(data))
+    }
+
+    /// Renders the template with the `Data`, returning a future that writes
+    /// it to `wr`.
+    pub fn render_data_async<'a, W>(&'a self, wr: &'a mut W, data: &'a Data) -> RenderAsync<'a, W>
+    where W: AsyncWrite + Unpin + ?Sized,
+    {
+        RenderAsync::new(wr, self.stream(data))
+    }
+}
+
+/// The future returned by `Template::render_async` and
+/// `Template::render_data_async`. It resolves once all of the output has been
+/// written, or to the error rendering failed with.
+pub struct RenderAsync<'a, W: ?Sized + 'a> {
+    wr: &'a mut W,
+    stream: Stream<'a>,
+    // The chunk being written, and how much of it has been.
+    chunk: Cow<'a, str>,
+    pos: usize,
+}
+
+impl<'a, W: ?Sized> RenderAsync<'a, W> {
+    fn new(wr: &'a mut W, stream: Stream<'a>) -> RenderAsync<'a, W> {
+        RenderAsync {
+            wr: wr,
+            stream: stream,
+            chunk: Cow::Borrowed(""),
+            pos: 0,
+        }
+    }
+}
+
+impl<'a, W: AsyncWrite + Unpin + ?Sized> Future for RenderAsync<'a, W> {
+    type Output = Result<()>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut TaskContext) -> Poll<Result<()>> {
+        let this = &mut *self;
+
+        loop {
+            while this.pos < this.chunk.len() {
+                let buf = &this.chunk.as_bytes()[this.pos..];
+                match Pin::new(&mut *this.wr).poll_write(cx, buf) {
+                    Poll::Ready(Ok(0)) => {
+                        let err = io::Error::new(io::ErrorKind::WriteZero, "failed to write whole buffer");
+                        return Poll::Ready(Err(Error::Io(err)));
+                    }
+                    Poll::Ready(Ok(n)) => this.pos += n,
+                    Poll::Ready(Err(err)) => return Poll::Ready(Err(Error::Io(err))),
+                    Poll::Pending => return Poll::Pending,
+                }
+            }
+
+            match this.stream.next() {
+                Some(Ok(chunk)) => {
+                    this.chunk = chunk;
+                    this.pos = 0;
+                }
+                Some(Err(err)) => return Poll::Ready(Err(err)),
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+    }
+}
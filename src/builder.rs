@@ -1,15 +1,16 @@
-use std::cell::RefCell;
 use std::string::ToString;
-use std::collections::HashMap;
+use std::error::Error as StdError;
+use std::sync::Mutex;
 use serde::Serialize;
 
 use encoder::Error;
-use super::{Data, to_data};
+use template::LambdaContext;
+use super::{Data, Map, to_data};
 
 /// `MapBuilder` is a helper type that construct `Data` types.
 #[derive(Default)]
 pub struct MapBuilder {
-    data: HashMap<String, Data>,
+    data: Map,
 }
 
 impl MapBuilder {
@@ -77,6 +78,63 @@ impl MapBuilder {
         MapBuilder { data: data }
     }
 
+    /// Add an `i64` to the `MapBuilder`.
+    ///
+    /// ```rust
+    /// use mustache::MapBuilder;
+    /// let data = MapBuilder::new()
+    ///     .insert_int("age", 41)
+    ///     .build();
+    /// ```
+    #[inline]
+    pub fn insert_int<K, V>(self, key: K, value: V) -> MapBuilder
+    where
+        K: Into<String>,
+        V: Into<i64>,
+    {
+        let MapBuilder { mut data } = self;
+        data.insert(key.into(), Data::Int(value.into()));
+        MapBuilder { data: data }
+    }
+
+    /// Add a `u64` to the `MapBuilder`.
+    ///
+    /// ```rust
+    /// use mustache::MapBuilder;
+    /// let data = MapBuilder::new()
+    ///     .insert_uint("count", 3u32)
+    ///     .build();
+    /// ```
+    #[inline]
+    pub fn insert_uint<K, V>(self, key: K, value: V) -> MapBuilder
+    where
+        K: Into<String>,
+        V: Into<u64>,
+    {
+        let MapBuilder { mut data } = self;
+        data.insert(key.into(), Data::Uint(value.into()));
+        MapBuilder { data: data }
+    }
+
+    /// Add an `f64` to the `MapBuilder`.
+    ///
+    /// ```rust
+    /// use mustache::MapBuilder;
+    /// let data = MapBuilder::new()
+    ///     .insert_float("price", 9.99)
+    ///     .build();
+    /// ```
+    #[inline]
+    pub fn insert_float<K, V>(self, key: K, value: V) -> MapBuilder
+    where
+        K: Into<String>,
+        V: Into<f64>,
+    {
+        let MapBuilder { mut data } = self;
+        data.insert(key.into(), Data::Float(value.into()));
+        MapBuilder { data: data }
+    }
+
     /// Add a `Vec` to the `MapBuilder`.
     ///
     /// ```rust
@@ -146,7 +204,30 @@ impl MapBuilder {
     where F: FnMut(String) -> String + Send + 'static
     {
         let MapBuilder { mut data } = self;
-        data.insert(key.to_string(), Data::Fun(RefCell::new(Box::new(f))));
+        data.insert(key.to_string(), Data::Fun(Mutex::new(Box::new(f))));
+        MapBuilder { data: data }
+    }
+
+    /// Add a lambda to the `MapBuilder`, which is given a `LambdaContext` to
+    /// render text and look up values with, and may fail.
+    ///
+    /// ```rust
+    /// use mustache::MapBuilder;
+    /// let data = MapBuilder::new()
+    ///     .insert_str("name", "Jane")
+    ///     .insert_lambda("bold", |ctx| {
+    ///         let text = ctx.text().to_string();
+    ///         ctx.render(&text).map(|text| format!("<b>{}</b>", text))
+    ///     })
+    ///     .build();
+    /// ```
+    #[inline]
+    pub fn insert_lambda<K: ToString, F, E>(self, key: K, f: F) -> MapBuilder
+    where F: Fn(&mut LambdaContext) -> Result<String, E> + Send + Sync + 'static,
+          E: Into<Box<StdError + Send + Sync>>
+    {
+        let MapBuilder { mut data } = self;
+        data.insert(key.to_string(), lambda(f));
         MapBuilder { data: data }
     }
 
@@ -218,6 +299,51 @@ impl VecBuilder {
         VecBuilder { data: data }
     }
 
+    /// Add an `i64` to the `VecBuilder`.
+    ///
+    /// ```rust
+    /// use mustache::VecBuilder;
+    /// let data = VecBuilder::new()
+    ///     .push_int(-1)
+    ///     .build();
+    /// ```
+    #[inline]
+    pub fn push_int<T: Into<i64>>(self, value: T) -> VecBuilder {
+        let VecBuilder { mut data } = self;
+        data.push(Data::Int(value.into()));
+        VecBuilder { data: data }
+    }
+
+    /// Add a `u64` to the `VecBuilder`.
+    ///
+    /// ```rust
+    /// use mustache::VecBuilder;
+    /// let data = VecBuilder::new()
+    ///     .push_uint(3u32)
+    ///     .build();
+    /// ```
+    #[inline]
+    pub fn push_uint<T: Into<u64>>(self, value: T) -> VecBuilder {
+        let VecBuilder { mut data } = self;
+        data.push(Data::Uint(value.into()));
+        VecBuilder { data: data }
+    }
+
+    /// Add an `f64` to the `VecBuilder`.
+    ///
+    /// ```rust
+    /// use mustache::VecBuilder;
+    /// let data = VecBuilder::new()
+    ///     .push_float(0.5)
+    ///     .build();
+    /// ```
+    #[inline]
+    pub fn push_float<T: Into<f64>>(self, value: T) -> VecBuilder {
+        let VecBuilder { mut data } = self;
+        data.push(Data::Float(value.into()));
+        VecBuilder { data: data }
+    }
+
     /// Add a `Vec` to the `MapBuilder`.
     ///
     /// ```rust
@@ -284,7 +410,18 @@ impl VecBuilder {
     where F: FnMut(String) -> String + Send + 'static
     {
         let VecBuilder { mut data } = self;
-        data.push(Data::Fun(RefCell::new(Box::new(f))));
+        data.push(Data::Fun(Mutex::new(Box::new(f))));
+        VecBuilder { data: data }
+    }
+
+    /// Add a lambda to the `VecBuilder`, like `MapBuilder::insert_lambda`.
+    #[inline]
+    pub fn push_lambda<F, E>(self, f: F) -> VecBuilder
+    where F: Fn(&mut LambdaContext) -> Result<String, E> + Send + Sync + 'static,
+          E: Into<Box<StdError + Send + Sync>>
+    {
+        let VecBuilder { mut data } = self;
+        data.push(lambda(f));
         VecBuilder { data: data }
     }
 
@@ -293,3 +430,10 @@ impl VecBuilder {
         Data::Vec(self.data)
     }
 }
+
+fn lambda<F, E>(f: F) -> Data
+where F: Fn(&mut LambdaContext) -> Result<String, E> + Send + Sync + 'static,
+      E: Into<Box<StdError + Send + Sync>>
+{
+    Data::Lambda(Box::new(move |ctx: &mut LambdaContext| f(ctx).map_err(Into::into)))
+}
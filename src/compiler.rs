@@ -1,14 +1,15 @@
 use std::collections::HashMap;
-use std::io::ErrorKind::NotFound;
-use std::io::Read;
-use std::fs::File;
+use std::sync::Arc;
 
-use parser::{Parser, Token};
-use super::Context;
+use context::{self, Context};
+use parser::{Parser, Span, Token};
 
+use error::{self, Error, Location};
 use Result;
 
-pub type PartialsMap = HashMap<String, Vec<Token>>;
+/// Compiled partials by name. The tokens are shared, so templates that use the
+/// same partials don't each need their own copy.
+pub type PartialsMap = HashMap<String, Arc<Vec<Token>>>;
 
 /// `Compiler` is a object that compiles a string into a `Vec<Token>`.
 pub struct Compiler<T> {
@@ -50,58 +51,110 @@ impl<T: Iterator<Item = char>> Compiler<T> {
     /// Compiles a template into a series of tokens.
     pub fn compile(mut self) -> Result<(Vec<Token>, PartialsMap)> {
         let (tokens, partials) = {
-            let parser = Parser::new(&mut self.reader, &self.otag, &self.ctag);
+            let parser = Parser::new(&mut self.reader, &self.otag, &self.ctag)
+                .pipelines(self.ctx.pipelines);
             try!(parser.parse())
         };
 
         // Compile the partials if we haven't done so already.
         for name in partials.into_iter() {
-            let path =
-                self.ctx.template_path.join(&(name.clone() + "." + &self.ctx.template_extension));
-
-            if !self.partials.contains_key(&name) {
-                // Insert a placeholder so we don't recurse off to infinity.
-                self.partials.insert(name.to_string(), Vec::new());
-
-                match File::open(&path) {
-                    Ok(mut file) => {
-                        let mut string = String::new();
-                        try!(file.read_to_string(&mut string));
-
-                        let compiler = Compiler {
-                            ctx: self.ctx.clone(),
-                            reader: string.chars(),
-                            partials: self.partials.clone(),
-                            otag: "{{".to_string(),
-                            ctag: "}}".to_string(),
-                        };
+            let span = partial_span(&tokens, &name).unwrap_or_default();
+            try!(self.compile_partial(name, span));
+        }
+
+        let Compiler { partials, .. } = self;
+
+        Ok((tokens, partials))
+    }
+
+    fn compile_partial(&mut self, name: String, span: Span) -> Result<()> {
+        if !self.partials.contains_key(&name) {
+            // Insert a placeholder so we don't recurse off to infinity.
+            self.partials.insert(name.to_string(), Arc::new(Vec::new()));
+
+            let loader = context::partial_loader(&self.ctx);
 
-                        let (tokens, subpartials) = try!(compiler.compile());
+            match try!(loader.load(&name)) {
+                Some(string) => {
+                    let compiler = Compiler {
+                        ctx: self.ctx.clone(),
+                        reader: string.chars(),
+                        partials: self.partials.clone(),
+                        otag: "{{".to_string(),
+                        ctag: "}}".to_string(),
+                    };
 
-                        // Include subpartials
-                        self.partials.extend(subpartials.into_iter());
+                    let (tokens, subpartials) = try!(compiler.compile()
+                        .map_err(|err| error::in_file(err, &loader.path(&name))));
 
-                        // Set final compiled tokens for *this* partial
-                        self.partials.insert(name, tokens);
+                    // Include subpartials
+                    self.partials.extend(subpartials.into_iter());
+
+                    // Set final compiled tokens for *this* partial
+                    self.partials.insert(name, Arc::new(tokens));
+                }
+                // Ignore missing partials, unless we're being strict.
+                None => {
+                    if self.ctx.strict {
+                        let location = Location {
+                            file: None,
+                            span: span,
+                        };
+                        return Err(Error::MissingPartial(name, location));
                     }
-                    // Ignore missing files.
-                    Err(ref e) if e.kind() == NotFound => {},
-                    Err(e) => return Err(e.into()),
                 }
             }
         }
 
-        let Compiler { partials, .. } = self;
+        Ok(())
+    }
+}
 
-        Ok((tokens, partials))
+/// Lazily compiles a partial that wasn't known when the template was compiled,
+/// such as one named by a dynamic partial tag at `span`. Returns `partials`
+/// extended with the partial and any partials it uses.
+pub fn compile_partial(ctx: Context,
+                       name: &str,
+                       span: Span,
+                       partials: PartialsMap)
+                       -> Result<PartialsMap> {
+    let mut compiler = Compiler::new_with(ctx,
+                                          "".chars(),
+                                          partials,
+                                          "{{".to_string(),
+                                          "}}".to_string());
+    try!(compiler.compile_partial(name.to_string(), span));
+
+    let Compiler { partials, .. } = compiler;
+
+    Ok(partials)
+}
+
+/// Find the first tag that uses the partial `name`.
+fn partial_span(tokens: &[Token], name: &str) -> Option<Span> {
+    for token in tokens.iter() {
+        let span = match *token {
+            Token::Partial(ref partial, _, _, span) if partial == name => Some(span),
+            Token::Parent(ref parent, _, _, _, _, _, span) if parent == name => Some(span),
+            Token::Section(_, _, ref children, _, _, _, _, _, _) |
+            Token::Parent(_, _, ref children, _, _, _, _) => partial_span(children, name),
+            Token::Block(_, _, ref children, _, _, _, _) => partial_span(children, name),
+            _ => None,
+        };
+
+        if span.is_some() {
+            return span;
+        }
     }
+
+    None
 }
 
 #[cfg(test)]
 mod tests {
     use std::path::PathBuf;
 
-    use parser::Token;
+    use parser::{Position, Span, Token};
     use compiler::Compiler;
     use context::Context;
 
@@ -117,54 +170,58 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    fn span(start: usize, end: usize) -> Span {
+        Span::new(Position::new(1, start), Position::new(1, end))
+    }
+
     #[test]
     fn test_compile_texts() {
         check_tokens(compile_str("hello world"),
-                     &[Token::Text("hello world".to_string())]);
+                     &[Token::Text("hello world".to_string(), span(1, 12))]);
         check_tokens(compile_str("hello {world"),
-                     &[Token::Text("hello {world".to_string())]);
+                     &[Token::Text("hello {world".to_string(), span(1, 13))]);
         check_tokens(compile_str("hello world}"),
-                     &[Token::Text("hello world}".to_string())]);
+                     &[Token::Text("hello world}".to_string(), span(1, 13))]);
         check_tokens(compile_str("hello world}}"),
-                     &[Token::Text("hello world}}".to_string())]);
+                     &[Token::Text("hello world}}".to_string(), span(1, 14))]);
     }
 
     #[test]
     fn test_compile_etags() {
         check_tokens(compile_str("{{ name }}"),
-                     &[Token::EscapedTag(vec!["name".to_string()], "{{ name }}".to_string())]);
+                     &[Token::EscapedTag(vec!["name".to_string()], vec![], None, "{{ name }}".to_string(), span(1, 11))]);
 
         check_tokens(compile_str("before {{name}} after"),
-                     &[Token::Text("before ".to_string()),
-                       Token::EscapedTag(vec!["name".to_string()], "{{name}}".to_string()),
-                       Token::Text(" after".to_string())]);
+                     &[Token::Text("before ".to_string(), span(1, 8)),
+                       Token::EscapedTag(vec!["name".to_string()], vec![], None, "{{name}}".to_string(), span(8, 16)),
+                       Token::Text(" after".to_string(), span(16, 22))]);
 
         check_tokens(compile_str("before {{name}}"),
-                     &[Token::Text("before ".to_string()),
-                       Token::EscapedTag(vec!["name".to_string()], "{{name}}".to_string())]);
+                     &[Token::Text("before ".to_string(), span(1, 8)),
+                       Token::EscapedTag(vec!["name".to_string()], vec![], None, "{{name}}".to_string(), span(8, 16))]);
 
         check_tokens(compile_str("{{name}} after"),
-                     &[Token::EscapedTag(vec!["name".to_string()], "{{name}}".to_string()),
-                       Token::Text(" after".to_string())]);
+                     &[Token::EscapedTag(vec!["name".to_string()], vec![], None, "{{name}}".to_string(), span(1, 9)),
+                       Token::Text(" after".to_string(), span(9, 15))]);
     }
 
     #[test]
     fn test_compile_utags() {
         check_tokens(compile_str("{{{name}}}"),
-                     &[Token::UnescapedTag(vec!["name".to_string()], "{{{name}}}".to_string())]);
+                     &[Token::UnescapedTag(vec!["name".to_string()], vec![], None, "{{{name}}}".to_string(), span(1, 11))]);
 
         check_tokens(compile_str("before {{{name}}} after"),
-                     &[Token::Text("before ".to_string()),
-                       Token::UnescapedTag(vec!["name".to_string()], "{{{name}}}".to_string()),
-                       Token::Text(" after".to_string())]);
+                     &[Token::Text("before ".to_string(), span(1, 8)),
+                       Token::UnescapedTag(vec!["name".to_string()], vec![], None, "{{{name}}}".to_string(), span(8, 18)),
+                       Token::Text(" after".to_string(), span(18, 24))]);
 
         check_tokens(compile_str("before {{{name}}}"),
-                     &[Token::Text("before ".to_string()),
-                       Token::UnescapedTag(vec!["name".to_string()], "{{{name}}}".to_string())]);
+                     &[Token::Text("before ".to_string(), span(1, 8)),
+                       Token::UnescapedTag(vec!["name".to_string()], vec![], None, "{{{name}}}".to_string(), span(8, 18))]);
 
         check_tokens(compile_str("{{{name}}} after"),
-                     &[Token::UnescapedTag(vec!["name".to_string()], "{{{name}}}".to_string()),
-                       Token::Text(" after".to_string())]);
+                     &[Token::UnescapedTag(vec!["name".to_string()], vec![], None, "{{{name}}}".to_string(), span(1, 11)),
+                       Token::Text(" after".to_string(), span(11, 17))]);
     }
 
     #[test]
@@ -177,10 +234,11 @@ mod tests {
                                "{{# name}}".to_string(),
                                "".to_string(),
                                "{{/name}}".to_string(),
-                               "}}".to_string())]);
+                               "}}".to_string(),
+                               span(1, 20))]);
 
         check_tokens(compile_str("before {{^name}}{{/name}} after"),
-                     &[Token::Text("before ".to_string()),
+                     &[Token::Text("before ".to_string(), span(1, 8)),
                        Token::Section(vec!["name".to_string()],
                                true,
                                Vec::new(),
@@ -188,11 +246,12 @@ mod tests {
                                "{{^name}}".to_string(),
                                "".to_string(),
                                "{{/name}}".to_string(),
-                               "}}".to_string()),
-                       Token::Text(" after".to_string())]);
+                               "}}".to_string(),
+                               span(8, 26)),
+                       Token::Text(" after".to_string(), span(26, 32))]);
 
         check_tokens(compile_str("before {{#name}}{{/name}}"),
-                     &[Token::Text("before ".to_string()),
+                     &[Token::Text("before ".to_string(), span(1, 8)),
                        Token::Section(
                            vec!["name".to_string()],
                            false,
@@ -201,7 +260,8 @@ mod tests {
                            "{{#name}}".to_string(),
                            "".to_string(),
                            "{{/name}}".to_string(),
-                           "}}".to_string())]);
+                           "}}".to_string(),
+                           span(8, 26))]);
 
         check_tokens(compile_str("{{#name}}{{/name}} after"),
                      &[Token::Section(
@@ -212,60 +272,63 @@ mod tests {
                          "{{#name}}".to_string(),
                          "".to_string(),
                          "{{/name}}".to_string(),
-                         "}}".to_string()),
-                       Token::Text(" after".to_string())]);
+                         "}}".to_string(),
+                         span(1, 19)),
+                       Token::Text(" after".to_string(), span(19, 25))]);
 
         check_tokens(compile_str("before {{#a}} 1 {{^b}} 2 {{/b}} {{/a}} after"),
-                     &[Token::Text("before ".to_string()),
+                     &[Token::Text("before ".to_string(), span(1, 8)),
                        Token::Section(
                            vec!["a".to_string()],
                            false,
                            vec![
-                               Token::Text(" 1 ".to_string()),
+                               Token::Text(" 1 ".to_string(), span(14, 17)),
                                Token::Section(
                                    vec!["b".to_string()],
                                    true,
-                                   vec![Token::Text(" 2 ".to_string())],
+                                   vec![Token::Text(" 2 ".to_string(), span(23, 26))],
                                    "{{".to_string(),
                                    "{{^b}}".to_string(),
                                    " 2 ".to_string(),
                                    "{{/b}}".to_string(),
                                    "}}".to_string(),
+                                   span(17, 32),
                                 ),
-                                Token::Text(" ".to_string())
+                                Token::Text(" ".to_string(), span(32, 33))
                             ],
                             "{{".to_string(),
                             "{{#a}}".to_string(),
                             " 1 {{^b}} 2 {{/b}} ".to_string(),
                             "{{/a}}".to_string(),
-                            "}}".to_string()),
-                       Token::Text(" after".to_string())]);
+                            "}}".to_string(),
+                            span(8, 39)),
+                       Token::Text(" after".to_string(), span(39, 45))]);
     }
 
     #[test]
     fn test_compile_partials() {
         check_tokens(compile_str("{{> test}}"),
-                     &[Token::Partial("test".to_string(), "".to_string(), "{{> test}}".to_string())]);
+                     &[Token::Partial("test".to_string(), "".to_string(), "{{> test}}".to_string(), span(1, 11))]);
 
         check_tokens(compile_str("before {{>test}} after"),
-                     &[Token::Text("before ".to_string()),
-                       Token::Partial("test".to_string(), "".to_string(), "{{>test}}".to_string()),
-                       Token::Text(" after".to_string())]);
+                     &[Token::Text("before ".to_string(), span(1, 8)),
+                       Token::Partial("test".to_string(), "".to_string(), "{{>test}}".to_string(), span(8, 17)),
+                       Token::Text(" after".to_string(), span(17, 23))]);
 
         check_tokens(compile_str("before {{> test}}"),
-                     &[Token::Text("before ".to_string()),
-                       Token::Partial("test".to_string(), "".to_string(), "{{> test}}".to_string())]);
+                     &[Token::Text("before ".to_string(), span(1, 8)),
+                       Token::Partial("test".to_string(), "".to_string(), "{{> test}}".to_string(), span(8, 18))]);
 
         check_tokens(compile_str("{{>test}} after"),
-                     &[Token::Partial("test".to_string(), "".to_string(), "{{>test}}".to_string()),
-                       Token::Text(" after".to_string())]);
+                     &[Token::Partial("test".to_string(), "".to_string(), "{{>test}}".to_string(), span(1, 10)),
+                       Token::Text(" after".to_string(), span(10, 16))]);
     }
 
     #[test]
     fn test_compile_delimiters() {
         check_tokens(compile_str("before {{=<% %>=}}<%name%> after"),
-                     &[Token::Text("before ".to_string()),
-                       Token::EscapedTag(vec!["name".to_string()], "<%name%>".to_string()),
-                       Token::Text(" after".to_string())]);
+                     &[Token::Text("before ".to_string(), span(1, 8)),
+                       Token::EscapedTag(vec!["name".to_string()], vec![], None, "<%name%>".to_string(), span(19, 27)),
+                       Token::Text(" after".to_string(), span(27, 33))]);
     }
 }
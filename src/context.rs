@@ -0,0 +1,192 @@
+use template::{self, Template};
+use compiler;
+use error;
+use {Result, Error};
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use data::Data;
+use escape::{self, Escaper};
+use filter::{Arg, Filter};
+use loader::{FileLoader, PartialLoader};
+
+/// Represents the shared metadata needed to compile and render a mustache
+/// template.
+#[derive(Clone)]
+pub struct Context {
+    pub template_path: PathBuf,
+    pub template_extension: String,
+    /// How values in `{{name}}` tags are escaped. Defaults to `escape::Html`.
+    pub escaper: Arc<Escaper>,
+    /// In strict mode, rendering a variable or section that isn't in the data
+    /// and compiling a template that uses a partial that doesn't exist are
+    /// errors, instead of rendering nothing.
+    pub strict: bool,
+    /// Where partials are loaded from. When this is `None`, the partial `name`
+    /// is read from `name.template_extension` in `template_path`.
+    pub partial_loader: Option<Arc<PartialLoader>>,
+    /// Before every render, check whether the files of the template and its
+    /// partials were modified, and recompile the ones that were. This is meant
+    /// for development, so templates can be edited without a restart.
+    pub reload: bool,
+    /// Whether sections treat the numbers `0` and `0.0` like `false`, like the
+    /// reference JavaScript implementation. Defaults to `false`, so zero is
+    /// truthy like it was when numbers were rendered as strings.
+    pub zero_is_falsy: bool,
+    /// The filters tags can use, like `upper` in `{{name | upper}}`, by name.
+    pub filters: HashMap<String, Arc<Filter>>,
+    /// Whether `|` and `:` in interpolation tags start filters and a format
+    /// spec. Defaults to `true`; templates with names that have a `|` or `:`
+    /// in them can turn it off to keep looking those names up whole.
+    pub pipelines: bool,
+    /// How much work rendering may do before it fails with
+    /// `Error::LimitExceeded`.
+    pub limits: Limits,
+}
+
+/// Bounds on rendering, so templates and data from untrusted sources can't
+/// overflow the stack or use up all the memory. `None` means unlimited.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Limits {
+    /// How deep sections and partials may be nested, counting recursive
+    /// partials each time they're entered. Defaults to
+    /// `DEFAULT_MAX_DEPTH`, so a partial that includes itself fails instead
+    /// of overflowing the stack.
+    pub max_depth: Option<usize>,
+    /// How many bytes a render may write.
+    pub max_output: Option<usize>,
+    /// How many list items sections may go through, over the whole render.
+    pub max_iterations: Option<usize>,
+}
+
+/// The nesting depth `Limits` allow by default. It's far deeper than
+/// templates nest by hand, and shallow enough to fit on the stack of a thread
+/// spawned with the default size.
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_depth: Some(DEFAULT_MAX_DEPTH),
+            max_output: None,
+            max_iterations: None,
+        }
+    }
+}
+
+impl fmt::Debug for Context {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut filters: Vec<&String> = self.filters.keys().collect();
+        filters.sort();
+
+        f.debug_struct("Context")
+            .field("template_path", &self.template_path)
+            .field("template_extension", &self.template_extension)
+            .field("escaper", &"..")
+            .field("strict", &self.strict)
+            .field("partial_loader", &self.partial_loader.as_ref().map(|_| ".."))
+            .field("reload", &self.reload)
+            .field("zero_is_falsy", &self.zero_is_falsy)
+            .field("filters", &filters)
+            .field("pipelines", &self.pipelines)
+            .field("limits", &self.limits)
+            .finish()
+    }
+}
+
+/// The loader partials are looked up with. This is not part of the impl of
+/// Context so it is not exported outside of mustache.
+pub fn partial_loader(ctx: &Context) -> Arc<PartialLoader> {
+    match ctx.partial_loader {
+        Some(ref loader) => loader.clone(),
+        None => {
+            Arc::new(FileLoader::new(ctx.template_path.clone(), ctx.template_extension.clone()))
+        }
+    }
+}
+
+impl Context {
+    /// Configures a mustache context the specified path to the templates.
+    pub fn new(path: PathBuf) -> Context {
+        Context {
+            template_path: path,
+            template_extension: "mustache".to_string(),
+            escaper: Arc::new(escape::Html),
+            strict: false,
+            partial_loader: None,
+            reload: false,
+            zero_is_falsy: false,
+            filters: HashMap::new(),
+            pipelines: true,
+            limits: Limits::default(),
+        }
+    }
+
+    /// Register a filter under `name`, replacing any filter that was
+    /// registered with that name before. See the `filter` module.
+    pub fn register_filter<K, F>(&mut self, name: K, filter: F)
+    where K: Into<String>,
+          F: Fn(&Data, &[Arg]) -> Result<Data> + Send + Sync + 'static,
+    {
+        self.filters.insert(name.into(), Arc::new(filter));
+    }
+
+    /// Compiles a template from a string
+    pub fn compile<IT: Iterator<Item = char>>(&self, reader: IT) -> Result<Template> {
+        self.compile_file(reader, None)
+    }
+
+    /// Compiles a template from a path.
+    pub fn compile_path<U: AsRef<Path>>(&self, path: U) -> Result<Template> {
+        let mut path = self.template_path.join(path.as_ref());
+        path.set_extension(&self.template_extension);
+        let template = try!(read_file(&path));
+
+        self.compile_file(template.chars(), Some(path))
+    }
+
+    fn compile_file<IT: Iterator<Item = char>>(&self,
+                                               reader: IT,
+                                               file: Option<PathBuf>)
+                                               -> Result<Template> {
+        let compiler = compiler::Compiler::new(self.clone(), reader);
+        let (tokens, partials) = try!(compiler.compile().map_err(|err| {
+            match file {
+                Some(ref file) => error::in_file(err, file),
+                None => err,
+            }
+        }));
+
+        let mut template = template::new(self.clone(), Arc::new(tokens), Arc::new(partials));
+        if let Some(file) = file {
+            template::set_file(&mut template, file);
+        }
+
+        if self.reload {
+            template = template::reloading(template);
+        }
+
+        Ok(template)
+    }
+}
+
+/// Read a template from a file. This is not part of the impl of Context so it
+/// is not exported outside of mustache.
+pub fn read_file(path: &Path) -> Result<String> {
+    // FIXME(#6164): This should use the file decoding tools when they are
+    // written. For now we'll just read the file and treat it as UTF-8file.
+    let mut s = vec![];
+    let mut file = try!(File::open(path));
+    try!(file.read_to_end(&mut s));
+
+    // TODO: maybe allow UTF-16 as well?
+    match String::from_utf8(s) {
+        Ok(string) => Ok(string),
+        _ => Err(Error::InvalidStr),
+    }
+}
\ No newline at end of file
@@ -0,0 +1,67 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::sync::Mutex;
+
+use indexmap::IndexMap;
+
+use template::LambdaContext;
+
+/// The boxed closure of a `Data::Lambda`. It's shared by every render of the
+/// data, which may run on several threads at once.
+pub type Lambda = Fn(&mut LambdaContext) -> Result<String, Box<StdError + Send + Sync>> + Send + Sync;
+
+/// The map of a `Data::Map`. It keeps the order its entries were inserted in,
+/// so struct fields stay in the order they were declared, and the objects of
+/// the `json`, `toml` and `yaml` features in the order they were parsed in.
+pub type Map = IndexMap<String, Data>;
+
+pub enum Data {
+    Null,
+    String(String),
+    Bool(bool),
+    Int(i64),
+    Uint(u64),
+    Float(f64),
+    Vec(Vec<Data>),
+    Map(Map),
+    Fun(Mutex<Box<FnMut(String) -> String + Send>>),
+    /// A lambda that can render text and look up values with the
+    /// `LambdaContext` it's given, and fail.
+    Lambda(Box<Lambda>),
+}
+
+impl PartialEq for Data {
+    #[inline]
+    fn eq(&self, other: &Data) -> bool {
+        match (self, other) {
+            (&Data::Null, &Data::Null) => true,
+            (&Data::String(ref v0), &Data::String(ref v1)) => v0 == v1,
+            (&Data::Bool(ref v0), &Data::Bool(ref v1)) => v0 == v1,
+            (&Data::Int(ref v0), &Data::Int(ref v1)) => v0 == v1,
+            (&Data::Uint(ref v0), &Data::Uint(ref v1)) => v0 == v1,
+            (&Data::Float(ref v0), &Data::Float(ref v1)) => v0 == v1,
+            (&Data::Vec(ref v0), &Data::Vec(ref v1)) => v0 == v1,
+            (&Data::Map(ref v0), &Data::Map(ref v1)) => v0 == v1,
+            (&Data::Fun(_), &Data::Fun(_)) => bug!("Cannot compare closures"),
+            (&Data::Lambda(_), &Data::Lambda(_)) => bug!("Cannot compare closures"),
+            (_, _) => false,
+        }
+    }
+}
+
+impl fmt::Debug for Data {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Data::Null => write!(f, "Null"),
+            Data::String(ref v) => write!(f, "StrVal({})", v),
+            Data::Bool(v) => write!(f, "Bool({:?})", v),
+            Data::Int(v) => write!(f, "Int({:?})", v),
+            Data::Uint(v) => write!(f, "Uint({:?})", v),
+            Data::Float(v) => write!(f, "Float({:?})", v),
+            Data::Vec(ref v) => write!(f, "VecVal({:?})", v),
+            Data::Map(ref v) => write!(f, "Map({:?})", v),
+            Data::Fun(_) => write!(f, "Fun(...)"),
+            Data::Lambda(_) => write!(f, "Lambda(...)"),
+        }
+    }
+}
\ No newline at end of file
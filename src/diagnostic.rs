@@ -0,0 +1,276 @@
+use std::fmt::Write;
+use std::path::Path;
+
+use error::{Error, Location};
+use parser::{self, ErrorKind, Span};
+
+/// Everything needed to point a template author at an error: what went wrong,
+/// where, and what to do about it.
+pub struct Diagnostic<'a> {
+    message: String,
+    file: Option<&'a Path>,
+    span: Option<Span>,
+    label: Option<String>,
+    hint: Option<String>,
+}
+
+impl<'a> Diagnostic<'a> {
+    pub fn new(message: String) -> Diagnostic<'a> {
+        Diagnostic {
+            message: message,
+            file: None,
+            span: None,
+            label: None,
+            hint: None,
+        }
+    }
+
+    /// Describe a library error. Errors without a location only get the
+    /// message.
+    pub fn from_error(err: &'a Error) -> Diagnostic<'a> {
+        match *err {
+            Error::Parser(ref err) => Diagnostic::from_parser_error(err),
+            Error::MissingVariable(ref name, ref location) => {
+                let mut diagnostic = Diagnostic::at(format!("missing variable `{}`", name), location);
+                diagnostic.label = Some(format!("`{}` is not in the data", name));
+                diagnostic.hint = Some("strict mode requires every variable to be present; \
+                                        use null for values that are optional"
+                    .to_string());
+                diagnostic
+            }
+            Error::MissingSection(ref name, ref location) => {
+                let mut diagnostic = Diagnostic::at(format!("missing section `{}`", name), location);
+                diagnostic.label = Some(format!("`{}` is not in the data", name));
+                diagnostic.hint = Some("strict mode requires every section to be present; \
+                                        use null or false to skip it"
+                    .to_string());
+                diagnostic
+            }
+            Error::MissingPartial(ref name, ref location) => {
+                let mut diagnostic = Diagnostic::at(format!("missing partial `{}`", name), location);
+                diagnostic.label = Some(format!("no partial named `{}`", name));
+                diagnostic
+            }
+            Error::UnknownFilter(ref name, ref location) => {
+                let mut diagnostic = Diagnostic::at(format!("unknown filter `{}`", name), location);
+                diagnostic.label = Some(format!("no filter named `{}`", name));
+                diagnostic.hint = Some("register it with `Context::register_filter`".to_string());
+                diagnostic
+            }
+            Error::LimitExceeded(ref limit, ref location) => {
+                let mut diagnostic = Diagnostic::at(format!("exceeded the {}", limit), location);
+                diagnostic.label = Some("while rendering this".to_string());
+                diagnostic.hint = Some("the limits can be changed with `Context::limits`".to_string());
+                diagnostic
+            }
+            ref err => Diagnostic::new(err.to_string()),
+        }
+    }
+
+    fn at(message: String, location: &'a Location) -> Diagnostic<'a> {
+        let mut diagnostic = Diagnostic::new(message);
+        diagnostic.file = location.file.as_ref().map(|file| &**file);
+        diagnostic.span = Some(location.span);
+        diagnostic
+    }
+
+    fn from_parser_error(err: &'a parser::Error) -> Diagnostic<'a> {
+        let mut diagnostic = Diagnostic::new(err.kind().to_string());
+        diagnostic.file = err.file();
+        diagnostic.span = Some(err.span());
+
+        let (label, hint) = match *err.kind() {
+            ErrorKind::BadClosingTag(actual, expected) => {
+                (format!("expected `{}`, found `{}`", expected, actual), None)
+            }
+            ErrorKind::UnclosedTag => {
+                ("tag opened here, never closed".to_string(),
+                 Some("add the closing delimiter to finish the tag".to_string()))
+            }
+            ErrorKind::UnclosedSection(ref name) => {
+                (format!("section `{}` opened here, never closed", name),
+                 Some(format!("add `{{{{/{}}}}}` where the section should end", name)))
+            }
+            ErrorKind::UnbalancedUnescapeTag => {
+                ("unescaped tag is missing its closing `}`".to_string(), None)
+            }
+            ErrorKind::EmptyTag => {
+                ("tag has no name".to_string(), None)
+            }
+            ErrorKind::EarlySectionClose(ref name) => {
+                (format!("closes section `{}`, which was never opened", name),
+                 Some(format!("remove this tag, or open the section with `{{{{#{}}}}}`", name)))
+            }
+            ErrorKind::MissingSetDelimeterClosingTag |
+            ErrorKind::InvalidSetDelimeterSyntax => {
+                ("malformed set delimiter tag".to_string(),
+                 Some("set delimiters like this: `{{=<% %>=}}`".to_string()))
+            }
+            ErrorKind::InvalidFormat(ref spec) => {
+                (format!("`{}` is not a valid format spec", spec),
+                 Some("format specs look like `{{price:>10,.2}}`: \
+                       [[fill]align][sign]['0'][width][grouping]['.' precision]".to_string()))
+            }
+            ErrorKind::InvalidFilter(ref filter) => {
+                (format!("`{}` is not a valid filter", filter),
+                 Some("filters look like `{{name | truncate 20 \"...\"}}`".to_string()))
+            }
+            ErrorKind::__Nonexhaustive => unreachable!(),
+        };
+
+        diagnostic.label = Some(label);
+        diagnostic.hint = hint;
+        diagnostic
+    }
+
+    /// Render the diagnostic with the line of `source` it points at,
+    /// underlining the offending part, like so:
+    ///
+    /// ```text
+    /// error: found an unclosed section: "users"
+    ///  --> page.mustache:2:1
+    ///   |
+    /// 2 | {{#users}}
+    ///   | ^^^^^^^^^^ section `users` opened here, never closed
+    ///   |
+    ///   = hint: add `{{/users}}` where the section should end
+    /// ```
+    pub fn render(&self, source: &str) -> String {
+        let mut out = String::new();
+        writeln!(out, "error: {}", self.message).unwrap();
+
+        let span = match self.span {
+            Some(span) => span,
+            None => return out,
+        };
+
+        let line_number = span.start.line.to_string();
+        let gutter: String = line_number.chars().map(|_| ' ').collect();
+
+        match self.file {
+            Some(file) => {
+                writeln!(out, "{}--> {}:{}:{}", gutter, file.display(), span.start.line, span.start.col)
+                    .unwrap()
+            }
+            None => writeln!(out, "{}--> {}:{}", gutter, span.start.line, span.start.col).unwrap(),
+        }
+
+        // The error may point just past the end of the template, so a missing
+        // line is treated as empty.
+        let line = source.lines().nth(span.start.line - 1).unwrap_or("");
+        let line_len = line.chars().count();
+
+        // Underline up to the end of the span, or the end of the line if the
+        // span runs on past it. Always show at least one caret.
+        let start = span.start.col - 1;
+        let end = if span.end.line == span.start.line {
+            span.end.col - 1
+        } else {
+            line_len
+        };
+        let carets = if end > start { end - start } else { 1 };
+
+        // Keep tabs in the padding so the carets line up with the source.
+        let padding: String = line.chars()
+            .chain(::std::iter::repeat(' '))
+            .take(start)
+            .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+            .collect();
+        let underline: String = ::std::iter::repeat('^').take(carets).collect();
+
+        writeln!(out, "{} |", gutter).unwrap();
+        writeln!(out, "{} | {}", line_number, line).unwrap();
+        match self.label {
+            Some(ref label) => writeln!(out, "{} | {}{} {}", gutter, padding, underline, label).unwrap(),
+            None => writeln!(out, "{} | {}{}", gutter, padding, underline).unwrap(),
+        }
+
+        if let Some(ref hint) = self.hint {
+            writeln!(out, "{} |", gutter).unwrap();
+            writeln!(out, "{} = hint: {}", gutter, hint).unwrap();
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::path::PathBuf;
+
+    use compiler::Compiler;
+    use context::Context;
+    use data::{Data, Map};
+    use error::{self, Error};
+
+    fn compile_error(source: &str) -> Error {
+        let ctx = Context::new(PathBuf::from("."));
+        Compiler::new(ctx, source.chars()).compile().err().expect("Expected a compile error")
+    }
+
+    #[test]
+    fn test_bad_closing_tag() {
+        let source = "hello {{name}?";
+        assert_eq!(compile_error(source).render_diagnostic(source),
+                   "error: character '?' was unexpected in the closing tag, expected '}'\n \
+                    --> 1:14\n  \
+                     |\n\
+                    1 | hello {{name}?\n  \
+                     |              ^ expected `}`, found `?`\n");
+    }
+
+    #[test]
+    fn test_unclosed_tag_underlines_to_end_of_line() {
+        let source = "a\n  {{name\nb";
+        assert_eq!(compile_error(source).render_diagnostic(source),
+                   "error: found an unclosed tag\n \
+                    --> 2:3\n  \
+                     |\n\
+                    2 |   {{name\n  \
+                     |   ^^^^^^ tag opened here, never closed\n  \
+                     |\n  \
+                     = hint: add the closing delimiter to finish the tag\n");
+    }
+
+    #[test]
+    fn test_early_section_close_with_file() {
+        let source = "\n\n\n\n\n\n\n\n\n\t{{/items}}";
+        let err = error::in_file(compile_error(source), &PathBuf::from("list.mustache"));
+        assert_eq!(err.render_diagnostic(source),
+                   "error: found a closing tag for an unopened section \"items\"\n  \
+                      --> list.mustache:10:2\n   \
+                      |\n\
+                    10 | \t{{/items}}\n   \
+                      | \t^^^^^^^^^^ closes section `items`, which was never opened\n   \
+                      |\n   \
+                      = hint: remove this tag, or open the section with `{{#items}}`\n");
+    }
+
+    #[test]
+    fn test_missing_variable() {
+        let mut ctx = Context::new(PathBuf::from("."));
+        ctx.strict = true;
+        let source = "Dear {{user.nmae}},";
+        let template = ctx.compile(source.chars()).expect("Failed to compile");
+        let err = template.render_data(&mut vec![], &Data::Map(Map::new()))
+            .err()
+            .expect("Expected a render error");
+
+        assert_eq!(err.render_diagnostic(source),
+                   "error: missing variable `user.nmae`\n \
+                    --> 1:6\n  \
+                     |\n\
+                    1 | Dear {{user.nmae}},\n  \
+                     |      ^^^^^^^^^^^^^ `user.nmae` is not in the data\n  \
+                     |\n  \
+                     = hint: strict mode requires every variable to be present; \
+                    use null for values that are optional\n");
+    }
+
+    #[test]
+    fn test_error_without_location() {
+        let err = Error::from(io::Error::new(io::ErrorKind::Other, "disk on fire"));
+        assert_eq!(err.render_diagnostic("{{name}}"), format!("error: {}\n", err));
+    }
+}
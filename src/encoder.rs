@@ -1,11 +1,10 @@
-use std::collections::HashMap;
 use std::error;
 use std::fmt::{self, Display};
 use std::result;
 
 use serde::{self, Serialize, ser};
 
-use super::{Data, to_data};
+use super::{Data, Map, to_data};
 
 /// Error type to represent encoding failure.
 ///
@@ -84,43 +83,45 @@ impl serde::Serializer for Encoder {
     }
 
     fn serialize_u8(self, v: u8) -> Result<Data> {
-        Ok(Data::String(v.to_string()))
+        Ok(Data::Uint(v as u64))
     }
 
     fn serialize_i8(self, v: i8) -> Result<Data> {
-        Ok(Data::String(v.to_string()))
+        Ok(Data::Int(v as i64))
     }
 
     fn serialize_u16(self, v: u16) -> Result<Data> {
-        Ok(Data::String(v.to_string()))
+        Ok(Data::Uint(v as u64))
     }
 
     fn serialize_i16(self, v: i16) -> Result<Data> {
-        Ok(Data::String(v.to_string()))
+        Ok(Data::Int(v as i64))
     }
 
     fn serialize_u32(self, v: u32) -> Result<Data> {
-        Ok(Data::String(v.to_string()))
+        Ok(Data::Uint(v as u64))
     }
 
     fn serialize_i32(self, v: i32) -> Result<Data> {
-        Ok(Data::String(v.to_string()))
+        Ok(Data::Int(v as i64))
     }
 
     fn serialize_i64(self, v: i64) -> Result<Data> {
-        Ok(Data::String(v.to_string()))
+        Ok(Data::Int(v))
     }
 
     fn serialize_u64(self, v: u64) -> Result<Data> {
-        Ok(Data::String(v.to_string()))
+        Ok(Data::Uint(v))
     }
 
     fn serialize_f32(self, v: f32) -> Result<Data> {
-        Ok(Data::String(v.to_string()))
+        // Widening the float directly would render `0.1f32` as
+        // `0.10000000149011612`, so go through its shortest representation.
+        Ok(Data::Float(v.to_string().parse().unwrap_or(v as f64)))
     }
 
     fn serialize_f64(self, v: f64) -> Result<Data> {
-        Ok(Data::String(v.to_string()))
+        Ok(Data::Float(v))
     }
 
     fn serialize_str(self, v: &str) -> Result<Data> {
@@ -175,7 +176,7 @@ impl serde::Serializer for Encoder {
     ) -> Result<Self::SerializeStructVariant> {
         Ok(SerializeStructVariant {
             name: String::from(variant),
-            map: HashMap::with_capacity(len),
+            map: Map::with_capacity(len),
         })
     }
 
@@ -246,7 +247,7 @@ impl serde::Serializer for Encoder {
 
     fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap> {
         Ok(SerializeMap {
-            map: HashMap::with_capacity(len.unwrap_or(0)),
+            map: Map::with_capacity(len.unwrap_or(0)),
             next_key: None,
         })
     }
@@ -265,14 +266,14 @@ pub struct SerializeTupleVariant {
 
 #[doc(hidden)]
 pub struct SerializeMap {
-    map: HashMap<String, Data>,
+    map: Map,
     next_key: Option<String>,
 }
 
 #[doc(hidden)]
 pub struct SerializeStructVariant {
     name: String,
-    map: HashMap<String, Data>,
+    map: Map,
 }
 
 impl ser::SerializeSeq for SerializeVec {
@@ -333,7 +334,7 @@ impl ser::SerializeTupleVariant for SerializeTupleVariant {
     }
 
     fn end(self) -> Result<Data> {
-        let mut object = HashMap::new();
+        let mut object = Map::new();
 
         object.insert(self.name, Data::Vec(self.vec));
 
@@ -349,13 +350,17 @@ impl ser::SerializeMap for SerializeMap {
     where
         T: Serialize
     {
-        match to_data(key)? {
-            Data::String(s) => {
-                self.next_key = Some(s);
-                Ok(())
-            }
-            _ => Err(Error::KeyIsNotString),
-        }
+        // Numbers and bools were encoded as strings before they got variants
+        // of their own, so they still make fine keys.
+        self.next_key = Some(match to_data(key)? {
+            Data::String(s) => s,
+            Data::Int(n) => n.to_string(),
+            Data::Uint(n) => n.to_string(),
+            Data::Float(n) => n.to_string(),
+            Data::Bool(b) => b.to_string(),
+            _ => return Err(Error::KeyIsNotString),
+        });
+        Ok(())
     }
 
     fn serialize_value<T: ?Sized>(&mut self, value: &T) -> Result<()>
@@ -405,7 +410,7 @@ impl ser::SerializeStructVariant for SerializeStructVariant {
     }
 
     fn end(self) -> Result<Data> {
-        let mut object = HashMap::new();
+        let mut object = Map::new();
 
         object.insert(self.name, Data::Map(self.map));
 
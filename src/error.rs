@@ -1,9 +1,11 @@
 use std::error::Error as StdError;
 use std::fmt;
 use std::io::Error as StdIoError;
+use std::path::{Path, PathBuf};
 use std::result;
 
-use parser;
+use diagnostic::Diagnostic;
+use parser::{self, Span};
 use encoder;
 
 /// Error type for any error within this library.
@@ -15,8 +17,26 @@ pub enum Error {
     InvalidStr,
     NoFilename,
     Io(StdIoError),
+    /// A `fmt::Write` couldn't be written to.
+    Fmt(fmt::Error),
     Parser(parser::Error),
     Encoder(encoder::Error),
+    /// A variable that isn't in the data, found while rendering in strict mode.
+    MissingVariable(String, Location),
+    /// A section that isn't in the data, found while rendering in strict mode.
+    MissingSection(String, Location),
+    /// A partial that doesn't exist, found while compiling in strict mode.
+    MissingPartial(String, Location),
+    /// A `Registry` was asked for a template that isn't registered.
+    UnknownTemplate(String),
+    /// A tag used a filter that isn't registered on the `Context`.
+    UnknownFilter(String, Location),
+    /// A filter couldn't handle the value or arguments it was given.
+    Filter(String),
+    /// A `Data::Lambda` returned an error.
+    Lambda(Box<StdError + Send + Sync>, Location),
+    /// Rendering went past one of the `Limits` set on the `Context`.
+    LimitExceeded(Limit, Location),
 
     #[doc(hidden)]
     __Nonexhaustive,
@@ -24,9 +44,97 @@ pub enum Error {
 
 pub type Result<T> = result::Result<T, Error>;
 
+/// Where in a template something was referenced.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Location {
+    /// The file the template was read from, if it was read from a file.
+    pub file: Option<PathBuf>,
+    pub span: Span,
+}
+
+/// Which of the render `Limits` was exceeded, and what it was set to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Limit {
+    /// Sections and partials were nested deeper than this.
+    Depth(usize),
+    /// More than this many bytes were written.
+    Output(usize),
+    /// Sections went through more than this many list items in total.
+    Iterations(usize),
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Limit::Depth(max) => write!(f, "nesting depth limit of {}", max),
+            Limit::Output(max) => write!(f, "output limit of {} bytes", max),
+            Limit::Iterations(max) => write!(f, "iteration limit of {}", max),
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let start = self.span.start;
+        match self.file {
+            Some(ref file) => write!(f, "{}:{}:{}", file.display(), start.line, start.col),
+            None => write!(f, "{}:{}", start.line, start.col),
+        }
+    }
+}
+
+impl Error {
+    /// Renders the error in the style of rustc's diagnostics: the message,
+    /// followed by the offending line of `source` with the problem underlined
+    /// and a hint on how to fix it. `source` should be the template the error
+    /// came from, which for errors in partials is the partial named by the
+    /// location. Errors that aren't tied to a location render as just the
+    /// message.
+    ///
+    /// ```rust
+    /// let source = "<ul>\n{{#users}}\n  <li>{{name}}</li>\n</ul>\n";
+    /// let err = mustache::compile_str(source).unwrap_err();
+    ///
+    /// assert_eq!(err.render_diagnostic(source), "\
+    /// error: found an unclosed section: \"users\"
+    ///  --> 2:1
+    ///   |
+    /// 2 | {{#users}}
+    ///   | ^^^^^^^^^^ section `users` opened here, never closed
+    ///   |
+    ///   = hint: add `{{/users}}` where the section should end
+    /// ");
+    /// ```
+    pub fn render_diagnostic(&self, source: &str) -> String {
+        Diagnostic::from_error(self).render(source)
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self.description().fmt(f)
+        match *self {
+            // Parser errors know where they happened, so let them say so.
+            Error::Parser(ref err) => err.fmt(f),
+            Error::MissingVariable(ref name, ref location) => {
+                write!(f, "{}: missing variable `{}`", location, name)
+            }
+            Error::MissingSection(ref name, ref location) => {
+                write!(f, "{}: missing section `{}`", location, name)
+            }
+            Error::MissingPartial(ref name, ref location) => {
+                write!(f, "{}: missing partial `{}`", location, name)
+            }
+            Error::UnknownTemplate(ref name) => write!(f, "unknown template `{}`", name),
+            Error::UnknownFilter(ref name, ref location) => {
+                write!(f, "{}: unknown filter `{}`", location, name)
+            }
+            Error::Filter(ref msg) => write!(f, "filter failed: {}", msg),
+            Error::Lambda(ref err, ref location) => write!(f, "{}: lambda failed: {}", location, err),
+            Error::LimitExceeded(ref limit, ref location) => {
+                write!(f, "{}: exceeded the {}", location, limit)
+            }
+            _ => self.description().fmt(f),
+        }
     }
 }
 
@@ -36,19 +144,55 @@ impl StdError for Error {
             Error::InvalidStr => "invalid str",
             Error::NoFilename => "a filename must be provided",
             Error::Io(ref err) => err.description(),
+            Error::Fmt(..) => "failed to write to a formatter",
             Error::Parser(ref err) => err.description(),
             Error::Encoder(ref err) => err.description(),
+            Error::MissingVariable(..) => "missing variable",
+            Error::MissingSection(..) => "missing section",
+            Error::MissingPartial(..) => "missing partial",
+            Error::UnknownTemplate(..) => "unknown template",
+            Error::UnknownFilter(..) => "unknown filter",
+            Error::Filter(..) => "filter failed",
+            Error::Lambda(..) => "lambda failed",
+            Error::LimitExceeded(..) => "render limit exceeded",
             Error::__Nonexhaustive => unreachable!(),
         }
     }
 }
 
+/// Record the file a template was read from on an error that points into it,
+/// unless it already knows its file. This is not part of the impl of Error so
+/// it is not exported outside of mustache.
+pub fn in_file(mut err: Error, file: &Path) -> Error {
+    match err {
+        Error::Parser(ref mut err) => parser::set_file(err, file),
+        Error::MissingVariable(_, ref mut location) |
+        Error::MissingSection(_, ref mut location) |
+        Error::MissingPartial(_, ref mut location) |
+        Error::UnknownFilter(_, ref mut location) |
+        Error::Lambda(_, ref mut location) |
+        Error::LimitExceeded(_, ref mut location) => {
+            if location.file.is_none() {
+                location.file = Some(file.to_path_buf());
+            }
+        }
+        _ => {}
+    }
+    err
+}
+
 impl From<StdIoError> for Error {
     fn from(err: StdIoError) -> Error {
         Error::Io(err)
     }
 }
 
+impl From<fmt::Error> for Error {
+    fn from(err: fmt::Error) -> Error {
+        Error::Fmt(err)
+    }
+}
+
 impl From<parser::Error> for Error {
     fn from(err: parser::Error) -> Error {
         Error::Parser(err)
@@ -0,0 +1,201 @@
+//! Escaping strategies for `{{name}}` tags.
+//!
+//! A `Context` escapes values with `Html` by default, which is what you want
+//! for web pages. Other kinds of output need different escaping, so any of the
+//! escapers in this module, or your own `Escaper`, can be used instead:
+//!
+//! ```rust
+//! use std::sync::Arc;
+//! use std::path::PathBuf;
+//! use mustache::{Context, MapBuilder};
+//! use mustache::escape;
+//!
+//! let mut ctx = Context::new(PathBuf::from("."));
+//! ctx.escaper = Arc::new(escape::Json);
+//!
+//! let template = ctx.compile("{\"name\": \"{{name}}\"}".chars()).unwrap();
+//! let data = MapBuilder::new().insert_str("name", "\"Bobby\"\n").build();
+//!
+//! let mut out = vec![];
+//! template.render_data(&mut out, &data).unwrap();
+//! assert_eq!(String::from_utf8(out).unwrap(), r#"{"name": "\"Bobby\"\n"}"#);
+//! ```
+//!
+//! Triple mustaches and `{{&name}}` tags are never escaped.
+
+use std::fmt::Write;
+
+/// Escapes values interpolated with `{{name}}` tags.
+pub trait Escaper: Send + Sync {
+    /// Returns `s` escaped for the kind of document being rendered.
+    fn escape(&self, s: &str) -> String;
+}
+
+/// Escapes the HTML special characters `<`, `>`, `&`, `"` and `'` as entities.
+/// This is the default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Html;
+
+impl Escaper for Html {
+    fn escape(&self, s: &str) -> String {
+        let mut escaped = String::with_capacity(s.len());
+        for ch in s.chars() {
+            match ch {
+                '<' => escaped.push_str("&lt;"),
+                '>' => escaped.push_str("&gt;"),
+                '&' => escaped.push_str("&amp;"),
+                '"' => escaped.push_str("&quot;"),
+                '\'' => escaped.push_str("&#39;"),
+                _ => escaped.push(ch),
+            }
+        }
+        escaped
+    }
+}
+
+/// Leaves values untouched, for plain text output such as emails.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct None;
+
+impl Escaper for None {
+    fn escape(&self, s: &str) -> String {
+        s.to_string()
+    }
+}
+
+/// Escapes values for use inside a JSON string literal. The surrounding quotes
+/// are left to the template.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Json;
+
+impl Escaper for Json {
+    fn escape(&self, s: &str) -> String {
+        let mut escaped = String::with_capacity(s.len());
+        for ch in s.chars() {
+            match ch {
+                '"' => escaped.push_str("\\\""),
+                '\\' => escaped.push_str("\\\\"),
+                '\n' => escaped.push_str("\\n"),
+                '\r' => escaped.push_str("\\r"),
+                '\t' => escaped.push_str("\\t"),
+                '\u{8}' => escaped.push_str("\\b"),
+                '\u{c}' => escaped.push_str("\\f"),
+                // These are valid in JSON but not in JavaScript string
+                // literals, so escape them in case the output ends up in a
+                // script.
+                '\u{2028}' | '\u{2029}' => {
+                    write!(escaped, "\\u{:04x}", ch as u32).unwrap();
+                }
+                ch if ch < ' ' => {
+                    write!(escaped, "\\u{:04x}", ch as u32).unwrap();
+                }
+                _ => escaped.push(ch),
+            }
+        }
+        escaped
+    }
+}
+
+/// Percent-encodes everything but the unreserved characters of RFC 3986, for
+/// values used as a URL path segment or query parameter.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Url;
+
+impl Escaper for Url {
+    fn escape(&self, s: &str) -> String {
+        let mut escaped = String::with_capacity(s.len());
+        for &b in s.as_bytes() {
+            match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                    escaped.push(b as char);
+                }
+                _ => write!(escaped, "%{:02X}", b).unwrap(),
+            }
+        }
+        escaped
+    }
+}
+
+/// Quotes values as a single POSIX shell word, so `rm {{file}}` removes exactly
+/// one file whatever its name is.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Shell;
+
+impl Escaper for Shell {
+    fn escape(&self, s: &str) -> String {
+        // Nothing is special inside single quotes, except the single quote
+        // itself, which has to be closed, escaped and reopened.
+        let mut escaped = String::with_capacity(s.len() + 2);
+        escaped.push('\'');
+        for ch in s.chars() {
+            match ch {
+                '\'' => escaped.push_str("'\\''"),
+                _ => escaped.push(ch),
+            }
+        }
+        escaped.push('\'');
+        escaped
+    }
+}
+
+/// Escapes the LaTeX special characters so values are typeset literally.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Latex;
+
+impl Escaper for Latex {
+    fn escape(&self, s: &str) -> String {
+        let mut escaped = String::with_capacity(s.len());
+        for ch in s.chars() {
+            match ch {
+                '\\' => escaped.push_str("\\textbackslash{}"),
+                '~' => escaped.push_str("\\textasciitilde{}"),
+                '^' => escaped.push_str("\\textasciicircum{}"),
+                '{' | '}' | '$' | '&' | '#' | '_' | '%' => {
+                    escaped.push('\\');
+                    escaped.push(ch);
+                }
+                _ => escaped.push(ch),
+            }
+        }
+        escaped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Escaper, Html, Json, Latex, Shell, Url};
+
+    #[test]
+    fn test_html() {
+        assert_eq!(Html.escape("<a href=\"x\">Tom & Jerry's</a>"),
+                   "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;");
+    }
+
+    #[test]
+    fn test_none() {
+        assert_eq!(super::None.escape("<b>&</b>"), "<b>&</b>");
+    }
+
+    #[test]
+    fn test_json() {
+        assert_eq!(Json.escape("say \"hi\"\\\n\t\u{1}\u{2028}é"),
+                   "say \\\"hi\\\"\\\\\\n\\t\\u0001\\u2028é");
+    }
+
+    #[test]
+    fn test_url() {
+        assert_eq!(Url.escape("a b&c=d/é~_.-"), "a%20b%26c%3Dd%2F%C3%A9~_.-");
+    }
+
+    #[test]
+    fn test_shell() {
+        assert_eq!(Shell.escape(""), "''");
+        assert_eq!(Shell.escape("it's $HOME; rm -rf /"), "'it'\\''s $HOME; rm -rf /'");
+    }
+
+    #[test]
+    fn test_latex() {
+        assert_eq!(Latex.escape("50% of $x_1 & {y} #2 ~ ^ \\"),
+                   "50\\% of \\$x\\_1 \\& \\{y\\} \\#2 \\textasciitilde{} \\textasciicircum{} \\textbackslash{}");
+    }
+}
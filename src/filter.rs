@@ -0,0 +1,49 @@
+//! Filters transform a value before it's interpolated, like `upper` and
+//! `truncate` in `{{ name | upper | truncate 20 }}`. They're registered on the
+//! `Context` a template is compiled with, so presentation helpers don't have
+//! to be put into the data:
+//!
+//! ```rust
+//! use std::path::PathBuf;
+//! use mustache::{Context, Data, MapBuilder};
+//!
+//! let mut ctx = Context::new(PathBuf::from("."));
+//! ctx.register_filter("upper", |value, _| {
+//!     match *value {
+//!         Data::String(ref s) => Ok(Data::String(s.to_uppercase())),
+//!         _ => Err(mustache::Error::Filter("upper expects a string".to_string())),
+//!     }
+//! });
+//! ctx.register_filter("truncate", |value, args| {
+//!     let len = args.get(0).and_then(|arg| arg.as_int()).unwrap_or(10) as usize;
+//!     match *value {
+//!         Data::String(ref s) => Ok(Data::String(s.chars().take(len).collect())),
+//!         _ => Err(mustache::Error::Filter("truncate expects a string".to_string())),
+//!     }
+//! });
+//!
+//! let template = ctx.compile("{{ name | upper | truncate 5 }}".chars()).unwrap();
+//! let data = MapBuilder::new().insert_str("name", "Bobby Tables").build();
+//!
+//! let mut out = vec![];
+//! template.render_data(&mut out, &data).unwrap();
+//! assert_eq!(String::from_utf8(out).unwrap(), "BOBBY");
+//! ```
+//!
+//! Filters run from left to right, each receiving the output of the previous
+//! one, and a format spec like `{{ price | round :.2 }}` applies to the final
+//! value. A value missing from the data is given to the first filter as
+//! `Data::Null`, so filters can provide defaults.
+//!
+//! Arguments are separated by whitespace. Numbers are passed as
+//! `Arg::Int` or `Arg::Float`, and anything else as `Arg::String`; use double
+//! quotes for strings containing whitespace, `|` or `:`.
+
+use Result;
+use data::Data;
+
+pub use parser::Arg;
+
+/// A filter takes the value being interpolated and the arguments it was given
+/// in the tag, and returns the value to interpolate instead.
+pub type Filter = Fn(&Data, &[Arg]) -> Result<Data> + Send + Sync;
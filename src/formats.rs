@@ -0,0 +1,333 @@
+//! The values of serde_json, toml and serde_yaml, with the `json`, `toml` and
+//! `yaml` features.
+//!
+//! Templates can be rendered with them as they are, with `render_json`,
+//! `render_toml` and `render_yaml`, or they can be turned into `Data`. Either
+//! way integers stay integers, floats stay floats and arrays keep their order,
+//! instead of everything going through the `Encoder`.
+
+#[cfg(feature = "json")]
+mod json {
+    use std::io::Write;
+
+    use serde_json::{Number, Value};
+
+    use template::Template;
+    use typed::ToData;
+    use value::ContextValue;
+    use {Data, Result};
+
+    fn number(n: &Number) -> Data {
+        if let Some(n) = n.as_i64() {
+            Data::Int(n)
+        } else if let Some(n) = n.as_u64() {
+            Data::Uint(n)
+        } else {
+            Data::Float(n.as_f64().unwrap_or(0.0))
+        }
+    }
+
+    impl From<Value> for Data {
+        fn from(value: Value) -> Data {
+            match value {
+                Value::Null => Data::Null,
+                Value::Bool(b) => Data::Bool(b),
+                Value::Number(ref n) => number(n),
+                Value::String(s) => Data::String(s),
+                Value::Array(v) => Data::Vec(v.into_iter().map(Data::from).collect()),
+                Value::Object(m) => Data::Map(m.into_iter().map(|(k, v)| (k, Data::from(v))).collect()),
+            }
+        }
+    }
+
+    impl ToData for Value {
+        fn to_data(&self) -> Data {
+            match *self {
+                Value::Null => Data::Null,
+                Value::Bool(b) => Data::Bool(b),
+                Value::Number(ref n) => number(n),
+                Value::String(ref s) => Data::String(s.clone()),
+                Value::Array(ref v) => Data::Vec(v.iter().map(ToData::to_data).collect()),
+                Value::Object(ref m) => {
+                    Data::Map(m.iter().map(|(k, v)| (k.clone(), v.to_data())).collect())
+                }
+            }
+        }
+    }
+
+    impl ContextValue for Value {
+        fn get(&self, key: &str) -> Option<&ContextValue> {
+            match *self {
+                Value::Object(ref m) => m.get(key).map(|value| value as &ContextValue),
+                _ => None,
+            }
+        }
+
+        fn items<'a>(&'a self) -> Option<Box<Iterator<Item = &'a ContextValue> + Send + 'a>> {
+            match *self {
+                Value::Array(ref v) => Some(Box::new(v.iter().map(|value| value as &ContextValue))),
+                _ => None,
+            }
+        }
+
+        fn truthy(&self) -> bool {
+            match *self {
+                Value::Null => false,
+                Value::Bool(b) => b,
+                Value::Number(ref n) => n.as_f64().map_or(true, |n| n != 0.0),
+                Value::String(ref s) => !s.is_empty(),
+                Value::Array(ref v) => !v.is_empty(),
+                Value::Object(_) => true,
+            }
+        }
+
+        fn is_number(&self) -> bool {
+            Value::is_number(self)
+        }
+
+        fn write_to(&self, out: &mut String) {
+            match *self {
+                Value::String(ref s) => out.push_str(s),
+                Value::Number(ref n) => number(n).write_to(out),
+                Value::Bool(b) => b.write_to(out),
+                _ => {}
+            }
+        }
+    }
+
+    impl Template {
+        /// Renders the template with a `serde_json::Value`, without copying
+        /// it into `Data`. This needs the `json` feature.
+        pub fn render_json<W: Write>(&self, wr: &mut W, value: &Value) -> Result<()> {
+            self.render_value(wr, value)
+        }
+    }
+}
+
+#[cfg(feature = "toml")]
+mod toml {
+    use std::io::Write;
+
+    use toml::Value;
+
+    use template::Template;
+    use typed::ToData;
+    use value::ContextValue;
+    use {Data, Result};
+
+    /// Datetimes become strings, written the way they are in TOML.
+    impl From<Value> for Data {
+        fn from(value: Value) -> Data {
+            match value {
+                Value::String(s) => Data::String(s),
+                Value::Integer(n) => Data::Int(n),
+                Value::Float(n) => Data::Float(n),
+                Value::Boolean(b) => Data::Bool(b),
+                Value::Datetime(ref dt) => Data::String(dt.to_string()),
+                Value::Array(v) => Data::Vec(v.into_iter().map(Data::from).collect()),
+                Value::Table(m) => Data::Map(m.into_iter().map(|(k, v)| (k, Data::from(v))).collect()),
+            }
+        }
+    }
+
+    impl ToData for Value {
+        fn to_data(&self) -> Data {
+            match *self {
+                Value::String(ref s) => Data::String(s.clone()),
+                Value::Integer(n) => Data::Int(n),
+                Value::Float(n) => Data::Float(n),
+                Value::Boolean(b) => Data::Bool(b),
+                Value::Datetime(ref dt) => Data::String(dt.to_string()),
+                Value::Array(ref v) => Data::Vec(v.iter().map(ToData::to_data).collect()),
+                Value::Table(ref m) => {
+                    Data::Map(m.iter().map(|(k, v)| (k.clone(), v.to_data())).collect())
+                }
+            }
+        }
+    }
+
+    impl ContextValue for Value {
+        fn get(&self, key: &str) -> Option<&ContextValue> {
+            match *self {
+                Value::Table(ref m) => m.get(key).map(|value| value as &ContextValue),
+                _ => None,
+            }
+        }
+
+        fn items<'a>(&'a self) -> Option<Box<Iterator<Item = &'a ContextValue> + Send + 'a>> {
+            match *self {
+                Value::Array(ref v) => Some(Box::new(v.iter().map(|value| value as &ContextValue))),
+                _ => None,
+            }
+        }
+
+        fn truthy(&self) -> bool {
+            match *self {
+                Value::String(ref s) => !s.is_empty(),
+                Value::Integer(n) => n != 0,
+                Value::Float(n) => n != 0.0,
+                Value::Boolean(b) => b,
+                Value::Datetime(_) => true,
+                Value::Array(ref v) => !v.is_empty(),
+                Value::Table(_) => true,
+            }
+        }
+
+        fn is_number(&self) -> bool {
+            match *self {
+                Value::Integer(_) | Value::Float(_) => true,
+                _ => false,
+            }
+        }
+
+        fn write_to(&self, out: &mut String) {
+            match *self {
+                Value::String(ref s) => out.push_str(s),
+                Value::Integer(n) => n.write_to(out),
+                Value::Float(n) => n.write_to(out),
+                Value::Boolean(b) => b.write_to(out),
+                Value::Datetime(ref dt) => out.push_str(&dt.to_string()),
+                _ => {}
+            }
+        }
+    }
+
+    impl Template {
+        /// Renders the template with a `toml::Value`, without copying it into
+        /// `Data`. This needs the `toml` feature.
+        pub fn render_toml<W: Write>(&self, wr: &mut W, value: &Value) -> Result<()> {
+            self.render_value(wr, value)
+        }
+    }
+}
+
+#[cfg(feature = "yaml")]
+mod yaml {
+    use std::convert::TryFrom;
+    use std::io::Write;
+
+    use serde_yaml::{Number, Value};
+
+    use encoder::Error as EncoderError;
+    use template::Template;
+    use typed::ToData;
+    use value::ContextValue;
+    use {Data, Map, Result};
+
+    fn number(n: &Number) -> Data {
+        if let Some(n) = n.as_i64() {
+            Data::Int(n)
+        } else if let Some(n) = n.as_u64() {
+            Data::Uint(n)
+        } else {
+            Data::Float(n.as_f64().unwrap_or(0.0))
+        }
+    }
+
+    /// Fails with `KeyIsNotString` for a mapping with a key that isn't a
+    /// string, like the `Encoder` does. Tags are dropped.
+    impl TryFrom<Value> for Data {
+        type Error = EncoderError;
+
+        fn try_from(value: Value) -> ::std::result::Result<Data, EncoderError> {
+            Ok(match value {
+                Value::Null => Data::Null,
+                Value::Bool(b) => Data::Bool(b),
+                Value::Number(ref n) => number(n),
+                Value::String(s) => Data::String(s),
+                Value::Sequence(v) => {
+                    Data::Vec(try!(v.into_iter().map(Data::try_from).collect()))
+                }
+                Value::Mapping(m) => {
+                    let mut map = Map::with_capacity(m.len());
+                    for (k, v) in m.into_iter() {
+                        match k {
+                            Value::String(k) => {
+                                map.insert(k, try!(Data::try_from(v)));
+                            }
+                            _ => return Err(EncoderError::KeyIsNotString),
+                        }
+                    }
+                    Data::Map(map)
+                }
+                Value::Tagged(tagged) => try!(Data::try_from(tagged.value)),
+            })
+        }
+    }
+
+    /// Entries whose key isn't a string are left out, as tags can't name
+    /// them.
+    impl ToData for Value {
+        fn to_data(&self) -> Data {
+            match *self {
+                Value::Null => Data::Null,
+                Value::Bool(b) => Data::Bool(b),
+                Value::Number(ref n) => number(n),
+                Value::String(ref s) => Data::String(s.clone()),
+                Value::Sequence(ref v) => Data::Vec(v.iter().map(ToData::to_data).collect()),
+                Value::Mapping(ref m) => {
+                    Data::Map(m.iter()
+                        .filter_map(|(k, v)| k.as_str().map(|k| (k.to_string(), v.to_data())))
+                        .collect())
+                }
+                Value::Tagged(ref tagged) => tagged.value.to_data(),
+            }
+        }
+    }
+
+    impl ContextValue for Value {
+        fn get(&self, key: &str) -> Option<&ContextValue> {
+            match *self {
+                Value::Mapping(ref m) => m.get(key).map(|value| value as &ContextValue),
+                Value::Tagged(ref tagged) => ContextValue::get(&tagged.value, key),
+                _ => None,
+            }
+        }
+
+        fn items<'a>(&'a self) -> Option<Box<Iterator<Item = &'a ContextValue> + Send + 'a>> {
+            match *self {
+                Value::Sequence(ref v) => Some(Box::new(v.iter().map(|value| value as &ContextValue))),
+                Value::Tagged(ref tagged) => tagged.value.items(),
+                _ => None,
+            }
+        }
+
+        fn truthy(&self) -> bool {
+            match *self {
+                Value::Null => false,
+                Value::Bool(b) => b,
+                Value::Number(ref n) => n.as_f64().map_or(true, |n| n != 0.0),
+                Value::String(ref s) => !s.is_empty(),
+                Value::Sequence(ref v) => !v.is_empty(),
+                Value::Mapping(_) => true,
+                Value::Tagged(ref tagged) => tagged.value.truthy(),
+            }
+        }
+
+        fn is_number(&self) -> bool {
+            match *self {
+                Value::Number(_) => true,
+                Value::Tagged(ref tagged) => ContextValue::is_number(&tagged.value),
+                _ => false,
+            }
+        }
+
+        fn write_to(&self, out: &mut String) {
+            match *self {
+                Value::String(ref s) => out.push_str(s),
+                Value::Number(ref n) => number(n).write_to(out),
+                Value::Bool(b) => b.write_to(out),
+                Value::Tagged(ref tagged) => tagged.value.write_to(out),
+                _ => {}
+            }
+        }
+    }
+
+    impl Template {
+        /// Renders the template with a `serde_yaml::Value`, without copying
+        /// it into `Data`. This needs the `yaml` feature.
+        pub fn render_yaml<W: Write>(&self, wr: &mut W, value: &Value) -> Result<()> {
+            self.render_value(wr, value)
+        }
+    }
+}
@@ -0,0 +1,597 @@
+//! A standard library of filters for common formatting needs. This needs the
+//! `helpers` feature.
+//!
+//! ```rust
+//! use std::path::PathBuf;
+//! use mustache::{helpers, Context, MapBuilder};
+//!
+//! let mut ctx = Context::new(PathBuf::from("."));
+//! helpers::register(&mut ctx);
+//!
+//! let template = ctx.compile("{{name | title}} has {{count}} {{count | pluralize item items}}".chars())
+//!     .unwrap();
+//! let data = MapBuilder::new().insert_str("name", "jane austen").insert_int("count", 2).build();
+//!
+//! let mut out = vec![];
+//! template.render_data(&mut out, &data).unwrap();
+//! assert_eq!(String::from_utf8(out).unwrap(), "Jane Austen has 2 items");
+//! ```
+//!
+//! These filters are registered:
+//!
+//! * `upper`, `lower` and `title` change the case of a value.
+//! * `trim` removes leading and trailing whitespace.
+//! * `truncate n [end]` cuts a value down to `n` characters, followed by
+//!   `end`, which defaults to `...`, if anything was cut.
+//! * `wordwrap n` breaks lines between words so they're at most `n`
+//!   characters long, unless a single word is longer.
+//! * `json` encodes any value as JSON.
+//! * `urlencode` percent-encodes a value like `escape::Url`.
+//! * `default value` replaces missing values, `null`, empty strings and empty
+//!   lists with `value`.
+//! * `join [separator]` joins the items of a list with `separator`, which
+//!   defaults to `, `.
+//! * `pluralize [singular] plural` is `singular` for a count of 1, and
+//!   `plural` otherwise. The count is a number or the length of a list, and
+//!   with only the plural, or nothing at all, `singular` is empty and `plural`
+//!   defaults to `s`.
+//! * `date format` formats an RFC 3339 timestamp like `2017-06-01T12:30:00Z`
+//!   with `strftime` style directives: `%Y`, `%y`, `%m`, `%d`, `%e`, `%H`,
+//!   `%I`, `%M`, `%S`, `%p`, `%B`, `%b`, `%A`, `%a`, `%j`, `%z` and `%%`. The
+//!   format defaults to `%Y-%m-%d`.
+//!
+//! Values other than strings are converted to strings by the filters that
+//! expect one, and missing values stay missing.
+
+use std::fmt::Write;
+
+use context::Context;
+use escape::{self, Escaper};
+use filter::Arg;
+use typed::ToData;
+use {Data, Error, Result};
+
+macro_rules! try_opt {
+    ($expr:expr) => (match $expr {
+        Some(value) => value,
+        None => return None,
+    })
+}
+
+/// Register every filter of this module on `ctx`, replacing filters with the
+/// same names.
+pub fn register(ctx: &mut Context) {
+    ctx.register_filter("upper", |value, _| map_text(value, |s| s.to_uppercase()));
+    ctx.register_filter("lower", |value, _| map_text(value, |s| s.to_lowercase()));
+    ctx.register_filter("title", |value, _| map_text(value, title));
+    ctx.register_filter("trim", |value, _| map_text(value, |s| s.trim().to_string()));
+    ctx.register_filter("truncate", truncate);
+    ctx.register_filter("wordwrap", wordwrap);
+    ctx.register_filter("json", |value, _| Ok(Data::String(json(value))));
+    ctx.register_filter("urlencode", |value, _| map_text(value, |s| escape::Url.escape(s)));
+    ctx.register_filter("default", default);
+    ctx.register_filter("join", join);
+    ctx.register_filter("pluralize", pluralize);
+    ctx.register_filter("date", date);
+}
+
+/// The value as a string, for the scalars that can be interpolated.
+fn text(value: &Data) -> Option<String> {
+    match *value {
+        Data::String(ref s) => Some(s.clone()),
+        Data::Bool(b) => Some(b.to_string()),
+        Data::Int(n) => Some(n.to_string()),
+        Data::Uint(n) => Some(n.to_string()),
+        Data::Float(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+fn map_text<F: Fn(&str) -> String>(value: &Data, f: F) -> Result<Data> {
+    match *value {
+        Data::Null => Ok(Data::Null),
+        _ => {
+            match text(value) {
+                Some(s) => Ok(Data::String(f(&s))),
+                None => Err(Error::Filter(format!("expected a string, found {:?}", value))),
+            }
+        }
+    }
+}
+
+fn arg_int(args: &[Arg], i: usize, filter: &str) -> Result<usize> {
+    match args.get(i).and_then(|arg| arg.as_int()) {
+        Some(n) if n >= 0 => Ok(n as usize),
+        _ => Err(Error::Filter(format!("{} expects a length", filter))),
+    }
+}
+
+fn title(s: &str) -> String {
+    let mut titled = String::with_capacity(s.len());
+    let mut word_start = true;
+    for ch in s.chars() {
+        if word_start {
+            titled.extend(ch.to_uppercase());
+        } else {
+            titled.extend(ch.to_lowercase());
+        }
+        word_start = ch.is_whitespace() || ch == '-';
+    }
+    titled
+}
+
+fn truncate(value: &Data, args: &[Arg]) -> Result<Data> {
+    let len = try!(arg_int(args, 0, "truncate"));
+    let end = args.get(1).map(|arg| arg.to_string()).unwrap_or_else(|| "...".to_string());
+
+    map_text(value, |s| {
+        if s.chars().count() > len {
+            s.chars().take(len).collect::<String>() + &end
+        } else {
+            s.to_string()
+        }
+    })
+}
+
+fn wordwrap(value: &Data, args: &[Arg]) -> Result<Data> {
+    let width = try!(arg_int(args, 0, "wordwrap"));
+
+    map_text(value, |s| {
+        let mut wrapped = String::with_capacity(s.len());
+        for (i, line) in s.lines().enumerate() {
+            if i > 0 {
+                wrapped.push('\n');
+            }
+
+            let mut len = 0;
+            for word in line.split_whitespace() {
+                let word_len = word.chars().count();
+                if len > 0 && len + 1 + word_len > width {
+                    wrapped.push('\n');
+                    len = 0;
+                } else if len > 0 {
+                    wrapped.push(' ');
+                    len += 1;
+                }
+                wrapped.push_str(word);
+                len += word_len;
+            }
+        }
+        wrapped
+    })
+}
+
+fn json(value: &Data) -> String {
+    let mut out = String::new();
+    write_json(&mut out, value);
+    out
+}
+
+fn write_json(out: &mut String, value: &Data) {
+    match *value {
+        Data::Null | Data::Fun(_) | Data::Lambda(_) => out.push_str("null"),
+        Data::String(ref s) => {
+            out.push('"');
+            out.push_str(&escape::Json.escape(s));
+            out.push('"');
+        }
+        Data::Bool(b) => write!(out, "{}", b).unwrap(),
+        Data::Int(n) => write!(out, "{}", n).unwrap(),
+        Data::Uint(n) => write!(out, "{}", n).unwrap(),
+        // JSON has no infinities or NaN.
+        Data::Float(n) if !n.is_finite() => out.push_str("null"),
+        Data::Float(n) => write!(out, "{}", n).unwrap(),
+        Data::Vec(ref items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_json(out, item);
+            }
+            out.push(']');
+        }
+        Data::Map(ref map) => {
+            out.push('{');
+            for (i, (key, value)) in map.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_json(out, &Data::String(key.clone()));
+                out.push(':');
+                write_json(out, value);
+            }
+            out.push('}');
+        }
+    }
+}
+
+fn default(value: &Data, args: &[Arg]) -> Result<Data> {
+    let empty = match *value {
+        Data::Null => true,
+        Data::String(ref s) => s.is_empty(),
+        Data::Vec(ref items) => items.is_empty(),
+        _ => false,
+    };
+
+    // Anything else is interpolated, so only a number or text can be passed
+    // through.
+    if !empty {
+        return match *value {
+            Data::Int(_) | Data::Uint(_) | Data::Float(_) => Ok(value.to_data()),
+            _ => {
+                match text(value) {
+                    Some(s) => Ok(Data::String(s)),
+                    None => {
+                        Err(Error::Filter(format!("default expects a string or a number, \
+                                                   found {:?}",
+                                                  value)))
+                    }
+                }
+            }
+        };
+    }
+
+    match args.get(0) {
+        Some(&Arg::String(ref s)) => Ok(Data::String(s.clone())),
+        Some(&Arg::Int(n)) => Ok(Data::Int(n)),
+        Some(&Arg::Float(n)) => Ok(Data::Float(n)),
+        None => Err(Error::Filter("default expects a value".to_string())),
+    }
+}
+
+fn join(value: &Data, args: &[Arg]) -> Result<Data> {
+    let separator = args.get(0).map(|arg| arg.to_string()).unwrap_or_else(|| ", ".to_string());
+
+    match *value {
+        Data::Null => Ok(Data::Null),
+        Data::Vec(ref items) => {
+            let mut parts = Vec::with_capacity(items.len());
+            for item in items.iter() {
+                match text(item) {
+                    Some(s) => parts.push(s),
+                    None => {
+                        return Err(Error::Filter(format!("join expects a list of strings, \
+                                                          found {:?}",
+                                                         item)))
+                    }
+                }
+            }
+            Ok(Data::String(parts.join(&separator)))
+        }
+        _ => Err(Error::Filter(format!("join expects a list, found {:?}", value))),
+    }
+}
+
+fn pluralize(value: &Data, args: &[Arg]) -> Result<Data> {
+    let one = match *value {
+        Data::Int(n) => n == 1,
+        Data::Uint(n) => n == 1,
+        Data::Float(n) => n == 1.0,
+        Data::Vec(ref items) => items.len() == 1,
+        Data::String(ref s) => s.trim() == "1",
+        _ => return Err(Error::Filter(format!("pluralize expects a count, found {:?}", value))),
+    };
+
+    let (singular, plural) = match args.len() {
+        0 => (String::new(), "s".to_string()),
+        1 => (String::new(), args[0].to_string()),
+        _ => (args[0].to_string(), args[1].to_string()),
+    };
+
+    Ok(Data::String(if one { singular } else { plural }))
+}
+
+const MONTHS: [&'static str; 12] = ["January", "February", "March", "April", "May", "June",
+                                    "July", "August", "September", "October", "November",
+                                    "December"];
+
+const WEEKDAYS: [&'static str; 7] = ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday",
+                                     "Saturday", "Sunday"];
+
+/// The parts of an RFC 3339 timestamp. The offset is in minutes east of UTC.
+struct DateTime {
+    year: i64,
+    month: usize,
+    day: usize,
+    hour: usize,
+    minute: usize,
+    second: usize,
+    offset: Option<i64>,
+}
+
+fn date(value: &Data, args: &[Arg]) -> Result<Data> {
+    let format = args.get(0).map(|arg| arg.to_string()).unwrap_or_else(|| "%Y-%m-%d".to_string());
+
+    let s = match *value {
+        Data::Null => return Ok(Data::Null),
+        Data::String(ref s) => s,
+        _ => return Err(Error::Filter(format!("date expects a timestamp, found {:?}", value))),
+    };
+
+    match parse_rfc3339(s) {
+        Some(datetime) => Ok(Data::String(try!(format_date(&datetime, &format)))),
+        None => Err(Error::Filter(format!("{:?} is not an RFC 3339 timestamp", s))),
+    }
+}
+
+fn parse_rfc3339(s: &str) -> Option<DateTime> {
+    fn number(s: &str, start: usize, len: usize) -> Option<usize> {
+        let digits = match s.get(start..start + len) {
+            Some(digits) => digits,
+            None => return None,
+        };
+        if digits.chars().all(|ch| ch.is_digit(10)) {
+            digits.parse().ok()
+        } else {
+            None
+        }
+    }
+
+    fn byte_is(s: &str, i: usize, expected: &[u8]) -> bool {
+        s.as_bytes().get(i).map_or(false, |b| expected.contains(b))
+    }
+
+    let year = try_opt!(number(s, 0, 4));
+    let month = try_opt!(number(s, 5, 2));
+    let day = try_opt!(number(s, 8, 2));
+    if !byte_is(s, 4, b"-") || !byte_is(s, 7, b"-") || month < 1 || month > 12 || day < 1 ||
+       day > days_in_month(year as i64, month) {
+        return None;
+    }
+
+    let mut datetime = DateTime {
+        year: year as i64,
+        month: month,
+        day: day,
+        hour: 0,
+        minute: 0,
+        second: 0,
+        offset: None,
+    };
+
+    // A date on its own is fine too.
+    if s.len() == 10 {
+        return Some(datetime);
+    }
+
+    if !byte_is(s, 10, b"Tt ") || !byte_is(s, 13, b":") || !byte_is(s, 16, b":") {
+        return None;
+    }
+    datetime.hour = try_opt!(number(s, 11, 2));
+    datetime.minute = try_opt!(number(s, 14, 2));
+    datetime.second = try_opt!(number(s, 17, 2));
+    if datetime.hour > 23 || datetime.minute > 59 || datetime.second > 60 {
+        return None;
+    }
+
+    // Skip the fraction of a second.
+    let mut i = 19;
+    if byte_is(s, i, b".") {
+        i += 1;
+        while byte_is(s, i, b"0123456789") {
+            i += 1;
+        }
+    }
+
+    match s.get(i..) {
+        Some("Z") | Some("z") => datetime.offset = Some(0),
+        Some(offset) if offset.len() == 6 && byte_is(offset, 3, b":") => {
+            let sign = match offset.as_bytes()[0] {
+                b'+' => 1,
+                b'-' => -1,
+                _ => return None,
+            };
+            let hours = try_opt!(number(offset, 1, 2)) as i64;
+            let minutes = try_opt!(number(offset, 4, 2)) as i64;
+            datetime.offset = Some(sign * (hours * 60 + minutes));
+        }
+        _ => return None,
+    }
+
+    Some(datetime)
+}
+
+fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i64, month: usize) -> usize {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Monday is 0.
+fn weekday(year: i64, month: usize, day: usize) -> usize {
+    // Days since 1970-01-01, which was a Thursday, from Howard Hinnant's
+    // `days_from_civil`.
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 +
+                      day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+
+    (days + 3).rem_euclid(7) as usize
+}
+
+fn format_date(datetime: &DateTime, format: &str) -> Result<String> {
+    let mut out = String::with_capacity(format.len() * 2);
+    let mut chars = format.chars();
+
+    while let Some(ch) = chars.next() {
+        if ch != '%' {
+            out.push(ch);
+            continue;
+        }
+
+        match chars.next() {
+            Some('Y') => write!(out, "{}", datetime.year).unwrap(),
+            Some('y') => write!(out, "{:02}", datetime.year.rem_euclid(100)).unwrap(),
+            Some('m') => write!(out, "{:02}", datetime.month).unwrap(),
+            Some('d') => write!(out, "{:02}", datetime.day).unwrap(),
+            Some('e') => write!(out, "{:2}", datetime.day).unwrap(),
+            Some('H') => write!(out, "{:02}", datetime.hour).unwrap(),
+            Some('I') => write!(out, "{:02}", (datetime.hour + 11) % 12 + 1).unwrap(),
+            Some('M') => write!(out, "{:02}", datetime.minute).unwrap(),
+            Some('S') => write!(out, "{:02}", datetime.second).unwrap(),
+            Some('p') => out.push_str(if datetime.hour < 12 { "AM" } else { "PM" }),
+            Some('B') => out.push_str(MONTHS[datetime.month - 1]),
+            Some('b') => out.push_str(&MONTHS[datetime.month - 1][..3]),
+            Some('A') => {
+                out.push_str(WEEKDAYS[weekday(datetime.year, datetime.month, datetime.day)])
+            }
+            Some('a') => {
+                out.push_str(&WEEKDAYS[weekday(datetime.year, datetime.month, datetime.day)][..3])
+            }
+            Some('j') => {
+                let day = (1..datetime.month)
+                    .map(|month| days_in_month(datetime.year, month))
+                    .sum::<usize>() + datetime.day;
+                write!(out, "{:03}", day).unwrap();
+            }
+            Some('z') => {
+                if let Some(offset) = datetime.offset {
+                    let sign = if offset < 0 { '-' } else { '+' };
+                    write!(out, "{}{:02}{:02}", sign, offset.abs() / 60, offset.abs() % 60)
+                        .unwrap();
+                }
+            }
+            Some('%') => out.push('%'),
+            Some(other) => {
+                return Err(Error::Filter(format!("unknown date directive `%{}`", other)));
+            }
+            None => return Err(Error::Filter("date format ends with `%`".to_string())),
+        }
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use context::Context;
+    use error::Error;
+    use filter::Arg;
+    use {Data, Map};
+
+    fn apply(name: &str, value: Data, args: &[Arg]) -> Data {
+        let mut ctx = Context::new(PathBuf::from("."));
+        super::register(&mut ctx);
+        let filter = ctx.filters[name].clone();
+        filter(&value, args).unwrap()
+    }
+
+    fn string(s: &str) -> Data {
+        Data::String(s.to_string())
+    }
+
+    fn arg(s: &str) -> Arg {
+        Arg::String(s.to_string())
+    }
+
+    #[test]
+    fn test_case() {
+        assert_eq!(apply("upper", string("straße"), &[]), string("STRASSE"));
+        assert_eq!(apply("lower", string("ÉCOLE"), &[]), string("école"));
+        assert_eq!(apply("title", string("jean-luc PICARD"), &[]), string("Jean-Luc Picard"));
+        assert_eq!(apply("upper", Data::Null, &[]), Data::Null);
+    }
+
+    #[test]
+    fn test_trim_truncate_wordwrap() {
+        assert_eq!(apply("trim", string("  hi \n"), &[]), string("hi"));
+        assert_eq!(apply("truncate", string("abcdef"), &[Arg::Int(3)]), string("abc..."));
+        assert_eq!(apply("truncate", string("abcdef"), &[Arg::Int(3), arg("…")]), string("abc…"));
+        assert_eq!(apply("truncate", string("abc"), &[Arg::Int(3)]), string("abc"));
+        assert_eq!(apply("wordwrap", string("the quick brown fox jumps"), &[Arg::Int(10)]),
+                   string("the quick\nbrown fox\njumps"));
+        assert_eq!(apply("wordwrap", string("extraordinary day"), &[Arg::Int(5)]),
+                   string("extraordinary\nday"));
+    }
+
+    #[test]
+    fn test_json_and_urlencode() {
+        let mut map = Map::new();
+        map.insert("b".to_string(), Data::Vec(vec![Data::Int(1), Data::Float(2.5), Data::Null]));
+        map.insert("a".to_string(), string("say \"hi\""));
+
+        // Keys are written in the order they were inserted in.
+        assert_eq!(apply("json", Data::Map(map), &[]),
+                   string(r#"{"b":[1,2.5,null],"a":"say \"hi\""}"#));
+        assert_eq!(apply("urlencode", string("a b&c"), &[]), string("a%20b%26c"));
+    }
+
+    #[test]
+    fn test_default_and_join() {
+        assert_eq!(apply("default", Data::Null, &[arg("n/a")]), string("n/a"));
+        assert_eq!(apply("default", string(""), &[Arg::Int(0)]), Data::Int(0));
+        assert_eq!(apply("default", string("x"), &[arg("n/a")]), string("x"));
+        assert_eq!(apply("default", Data::Bool(true), &[arg("n/a")]), string("true"));
+
+        let list = Data::Vec(vec![string("a"), Data::Int(2), Data::Bool(true)]);
+        assert_eq!(apply("join", list, &[]), string("a, 2, true"));
+        assert_eq!(apply("join", Data::Vec(vec![string("a"), string("b")]), &[arg("/")]),
+                   string("a/b"));
+    }
+
+    #[test]
+    fn test_default_renders_non_scalars() {
+        let mut ctx = Context::new(PathBuf::from("."));
+        super::register(&mut ctx);
+        let template = ctx.compile("{{value | default \"none\"}}".chars()).unwrap();
+
+        let mut data = Map::new();
+        data.insert("value".to_string(), Data::Bool(true));
+        assert_eq!(template.render_data_to_string(&Data::Map(data)).unwrap(), "true");
+
+        let mut data = Map::new();
+        data.insert("value".to_string(), Data::Vec(vec![string("a")]));
+        match template.render_data_to_string(&Data::Map(data)) {
+            Err(Error::Filter(ref msg)) => assert!(msg.starts_with("default expects"), "{}", msg),
+            result => panic!("expected a filter error, found {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_pluralize() {
+        assert_eq!(apply("pluralize", Data::Int(1), &[]), string(""));
+        assert_eq!(apply("pluralize", Data::Uint(2), &[]), string("s"));
+        assert_eq!(apply("pluralize", Data::Int(0), &[arg("es")]), string("es"));
+        assert_eq!(apply("pluralize", Data::Vec(vec![Data::Null]), &[arg("child"), arg("children")]),
+                   string("child"));
+    }
+
+    #[test]
+    fn test_date() {
+        let timestamp = "2016-02-29T18:05:09.123+02:00";
+        assert_eq!(apply("date", string(timestamp), &[]), string("2016-02-29"));
+        assert_eq!(apply("date", string(timestamp), &[arg("%A %e %B %Y, %I:%M:%S %p %z")]),
+                   string("Monday 29 February 2016, 06:05:09 PM +0200"));
+        assert_eq!(apply("date", string("1999-12-31"), &[arg("%a %b %d '%y, day %j")]),
+                   string("Fri Dec 31 '99, day 365"));
+        assert_eq!(apply("date", string("2001-01-01T00:00:00Z"), &[arg("%A %H%%")]),
+                   string("Monday 00%"));
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut ctx = Context::new(PathBuf::from("."));
+        super::register(&mut ctx);
+
+        assert!(ctx.filters["truncate"](&string("abc"), &[]).is_err());
+        assert!(ctx.filters["date"](&string("2016-02-30"), &[]).is_err());
+        assert!(ctx.filters["date"](&string("yesterday"), &[]).is_err());
+        assert!(ctx.filters["date"](&string("2016-01-01"), &[arg("%Q")]).is_err());
+        assert!(ctx.filters["join"](&string("abc"), &[]).is_err());
+        assert!(ctx.filters["upper"](&Data::Vec(vec![]), &[]).is_err());
+    }
+}
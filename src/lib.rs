@@ -1,31 +1,137 @@
+extern crate indexmap;
 extern crate log;
+#[macro_use]
+extern crate mustache_parser;
 extern crate serde;
+#[cfg(feature = "macros")]
+extern crate mustache_macros;
+#[cfg(feature = "async")]
+extern crate tokio;
+#[cfg(feature = "json")]
+extern crate serde_json;
+#[cfg(feature = "yaml")]
+extern crate serde_yaml;
+#[cfg(feature = "toml")]
+extern crate toml;
 
 use std::str;
 use std::path::{PathBuf, Path};
 use std::result;
 
-#[macro_use]
-mod macros;
+use mustache_parser::{format, parser};
 
+#[cfg(feature = "async")]
+mod async_io;
 mod builder;
 mod compiler;
 mod context;
 mod data;
+mod diagnostic;
 mod encoder;
 mod error;
-mod parser;
+pub mod escape;
+pub mod filter;
+#[cfg(feature = "helpers")]
+pub mod helpers;
+mod formats;
+mod loader;
+mod registry;
 mod template;
+mod typed;
+mod value;
 
+#[cfg(feature = "async")]
+pub use async_io::RenderAsync;
 pub use builder::{MapBuilder, VecBuilder};
-pub use context::Context;
-pub use data::Data;
+pub use context::{Context, Limits, DEFAULT_MAX_DEPTH};
+pub use data::{Data, Map};
 pub use encoder::Encoder;
 pub use encoder::Error as EncoderError;
 pub use encoder::{SerializeVec, SerializeTupleVariant, SerializeMap, SerializeStructVariant};
-pub use error::{Error, Result};
+pub use error::{Error, Limit, Location, Result};
+pub use escape::Escaper;
+pub use filter::{Arg, Filter};
+pub use loader::{ChainLoader, FileLoader, PartialLoader};
 pub use parser::Error as ParserError;
-pub use template::Template;
+pub use parser::ErrorKind as ParserErrorKind;
+pub use parser::{Position, Span};
+pub use registry::Registry;
+pub use template::{Bound, LambdaContext, Stream, Template};
+pub use typed::{MustacheTemplate, ToData};
+pub use value::ContextValue;
+
+/// Parses a template and all of its partials at build time, and expands to the
+/// compiled `Template`. The path is relative to the crate's `Cargo.toml`, and
+/// partials are looked up next to the template with the same extension. Syntax
+/// errors in any of them are compile errors, and the files don't need to be
+/// around when the program runs.
+///
+/// The template has no directory to load files from at runtime: partials that
+/// weren't there at build time, and dynamic partials that aren't one of the
+/// embedded ones, render as nothing, unless a `partial_loader` is set on its
+/// context. Errors point at the path the macro was given.
+///
+/// ```rust,ignore
+/// let template = mustache::include_template!("templates/page.mustache");
+/// ```
+///
+/// This needs the `macros` feature.
+#[cfg(feature = "macros")]
+pub use mustache_macros::include_template;
+
+/// `#[derive(MustacheTemplate)]`, `#[derive(ToData)]` and
+/// `#[derive(ContextValue)]`, see the traits of the same name. This needs the
+/// `macros` feature.
+#[cfg(feature = "macros")]
+pub use mustache_macros::{ContextValue, MustacheTemplate, ToData};
+
+/// Used by the code generated by the macros of `mustache_macros`. Not public API.
+#[doc(hidden)]
+pub mod __private {
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+
+    use context::Context;
+    use loader::ChainLoader;
+    use template::{self, Template};
+    use value::ContextValue;
+
+    pub use format::Format;
+    pub use parser::{Position, Span, Token};
+
+    /// What the derived `ContextValue::get` returns. Derives can't spell out
+    /// the trait object themselves, as `dyn` doesn't parse the same in every
+    /// edition.
+    pub type ContextValueRef<'a> = &'a (ContextValue + 'a);
+
+    pub fn format(spec: &str) -> Option<Format> {
+        Format::parse(spec)
+    }
+
+    pub fn span(start_line: usize, start_col: usize, end_line: usize, end_col: usize) -> Span {
+        Span::new(Position::new(start_line, start_col), Position::new(end_line, end_col))
+    }
+
+    /// An embedded template, whose `file` is the path given to the macro.
+    /// It has no template path, and a loader that doesn't find anything, so
+    /// rendering it never reads the files of the machine it runs on.
+    pub fn template(file: &str, tokens: Vec<Token>, partials: Vec<(&str, Vec<Token>)>) -> Template {
+        let file = Path::new(file);
+        let mut ctx = Context::new(PathBuf::new());
+        ctx.partial_loader = Some(Arc::new(ChainLoader::new()));
+        if let Some(extension) = file.extension().and_then(|ext| ext.to_str()) {
+            ctx.template_extension = extension.to_string();
+        }
+
+        let partials = partials.into_iter()
+            .map(|(name, tokens)| (name.to_string(), Arc::new(tokens)))
+            .collect();
+
+        let mut template = template::new(ctx, Arc::new(tokens), Arc::new(partials));
+        template::set_file(&mut template, file.to_path_buf());
+        template
+    }
+}
 
 pub fn to_data<T>(value: T) -> result::Result<Data, encoder::Error>
 where
@@ -51,10 +157,8 @@ pub fn compile_path<U: AsRef<Path>>(path: U) -> Result<Template> {
             // the extension is not utf8 :(
             let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("mustache");
 
-            let context = Context {
-                template_path: template_dir.to_path_buf(),
-                template_extension: extension.to_string(),
-            };
+            let mut context = Context::new(template_dir.to_path_buf());
+            context.template_extension = extension.to_string();
             context.compile_path(filename)
         }
         None => Err(Error::NoFilename),
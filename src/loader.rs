@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::ErrorKind::NotFound;
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use Result;
+
+/// A source of partials. `{{> name}}` tags look up `name` with the loader of
+/// the `Context` the template was compiled with.
+pub trait PartialLoader: Send + Sync {
+    /// Returns the source of the partial `name`, or `None` if there is no
+    /// such partial.
+    fn load(&self, name: &str) -> Result<Option<String>>;
+
+    /// Where the partial `name` comes from, so errors can point at it.
+    /// Defaults to the name itself.
+    fn path(&self, name: &str) -> PathBuf {
+        PathBuf::from(name)
+    }
+}
+
+impl<L: PartialLoader + ?Sized> PartialLoader for Arc<L> {
+    fn load(&self, name: &str) -> Result<Option<String>> {
+        (**self).load(name)
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        (**self).path(name)
+    }
+}
+
+/// Loads the partial `name` from the file `name.extension` in a directory.
+/// This is what a `Context` uses unless it's given another loader.
+#[derive(Clone, Debug)]
+pub struct FileLoader {
+    dir: PathBuf,
+    extension: String,
+}
+
+impl FileLoader {
+    pub fn new(dir: PathBuf, extension: String) -> FileLoader {
+        FileLoader {
+            dir: dir,
+            extension: extension,
+        }
+    }
+}
+
+impl PartialLoader for FileLoader {
+    fn load(&self, name: &str) -> Result<Option<String>> {
+        match File::open(self.path(name)) {
+            Ok(mut file) => {
+                let mut string = String::new();
+                try!(file.read_to_string(&mut string));
+                Ok(Some(string))
+            }
+            Err(ref e) if e.kind() == NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(&(name.to_string() + "." + &self.extension))
+    }
+}
+
+/// Partials held in memory, keyed by name.
+impl PartialLoader for HashMap<String, String> {
+    fn load(&self, name: &str) -> Result<Option<String>> {
+        Ok(self.get(name).cloned())
+    }
+}
+
+/// Tries a list of loaders in order, using the first that has the partial.
+#[derive(Clone, Default)]
+pub struct ChainLoader {
+    loaders: Vec<Arc<PartialLoader>>,
+}
+
+impl ChainLoader {
+    pub fn new() -> ChainLoader {
+        ChainLoader { loaders: Vec::new() }
+    }
+
+    /// Add a loader to try after the ones already in the chain.
+    pub fn push<L: PartialLoader + 'static>(mut self, loader: L) -> ChainLoader {
+        self.loaders.push(Arc::new(loader));
+        self
+    }
+}
+
+impl PartialLoader for ChainLoader {
+    fn load(&self, name: &str) -> Result<Option<String>> {
+        for loader in self.loaders.iter() {
+            if let Some(src) = try!(loader.load(name)) {
+                return Ok(Some(src));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        // This is only needed to report errors, so it's fine to load the
+        // partial again to find out which loader it came from.
+        for loader in self.loaders.iter() {
+            if let Ok(Some(_)) = loader.load(name) {
+                return loader.path(name);
+            }
+        }
+
+        PathBuf::from(name)
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::str;
+use std::sync::{Arc, Mutex};
+use serde::Serialize;
+
+use compiler::{Compiler, PartialsMap};
+use context::{self, Context};
+use error;
+use loader::{ChainLoader, PartialLoader};
+use template::{self, lock, Template};
+
+use {Data, Error, Result};
+
+/// A collection of named templates that can use each other as partials.
+///
+/// Templates are compiled the first time they're used, and every template and
+/// partial is only compiled once, no matter how many templates use it. The
+/// compiled partials are shared between all the templates of the registry.
+///
+/// ```rust
+/// use mustache::{MapBuilder, Registry};
+///
+/// let mut registry = Registry::new();
+/// registry.register_str("layout/header", "<h1>{{title}}</h1>");
+/// registry.register_str("page/home", "{{> layout/header}}<p>Welcome!</p>");
+///
+/// let data = MapBuilder::new().insert_str("title", "Home").build();
+///
+/// let mut out = vec![];
+/// registry.render_data("page/home", &data, &mut out).unwrap();
+/// assert_eq!(String::from_utf8(out).unwrap(), "<h1>Home</h1><p>Welcome!</p>");
+/// ```
+#[derive(Debug)]
+pub struct Registry {
+    ctx: Context,
+    sources: Arc<Sources>,
+    templates: Mutex<HashMap<String, Arc<Template>>>,
+    // Replaced rather than changed when a template is compiled, so the
+    // templates compiled before can keep sharing the old one.
+    partials: Mutex<Arc<PartialsMap>>,
+}
+
+/// The source of the registered templates, which also serve as partials.
+#[derive(Clone, Debug, Default)]
+struct Sources {
+    templates: HashMap<String, (String, Option<PathBuf>)>,
+}
+
+impl PartialLoader for Sources {
+    fn load(&self, name: &str) -> Result<Option<String>> {
+        Ok(self.templates.get(name).map(|&(ref src, _)| src.clone()))
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        match self.templates.get(name) {
+            Some(&(_, Some(ref file))) => file.clone(),
+            _ => PathBuf::from(name),
+        }
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Registry {
+        Registry::new()
+    }
+}
+
+impl Registry {
+    /// Create an empty registry. Partials that aren't registered are read from
+    /// the current directory.
+    pub fn new() -> Registry {
+        Registry::with_context(Context::new(PathBuf::from(".")))
+    }
+
+    /// Create an empty registry that compiles templates with `ctx`. Partials
+    /// that aren't registered are loaded with the context's partial loader.
+    pub fn with_context(ctx: Context) -> Registry {
+        Registry {
+            ctx: ctx,
+            sources: Arc::new(Sources::default()),
+            templates: Mutex::new(HashMap::new()),
+            partials: Mutex::new(Arc::new(HashMap::new())),
+        }
+    }
+
+    /// Register a template under `name`, replacing any template that was
+    /// registered with that name before.
+    pub fn register_str<K: Into<String>>(&mut self, name: K, source: &str) {
+        self.register(name.into(), source.to_string(), None);
+    }
+
+    /// Register every template in `dir` and its subdirectories with the
+    /// context's template extension. Templates are named after their path
+    /// relative to `dir` without the extension, so `dir/page/home.mustache`
+    /// becomes `page/home`.
+    pub fn register_dir<P: AsRef<Path>>(&mut self, dir: P) -> Result<()> {
+        let dir = dir.as_ref();
+        let mut names = Vec::new();
+        self.register_subdir(dir, &mut names)
+    }
+
+    fn register_subdir(&mut self, dir: &Path, names: &mut Vec<String>) -> Result<()> {
+        for entry in try!(fs::read_dir(dir)) {
+            let path = try!(entry).path();
+
+            // FIXME: Should work with OsStrings, this will skip templates with
+            // names that aren't utf8 :(
+            let stem = match path.file_stem().and_then(|stem| stem.to_str()) {
+                Some(stem) => stem.to_string(),
+                None => continue,
+            };
+
+            if path.is_dir() {
+                let file_name = match path.file_name().and_then(|name| name.to_str()) {
+                    Some(file_name) => file_name.to_string(),
+                    None => continue,
+                };
+
+                names.push(file_name);
+                try!(self.register_subdir(&path, names));
+                names.pop();
+            } else if path.extension().and_then(|ext| ext.to_str()) ==
+                      Some(&*self.ctx.template_extension) {
+                let mut s = vec![];
+                let mut file = try!(File::open(&path));
+                try!(file.read_to_end(&mut s));
+
+                let source = match str::from_utf8(&*s) {
+                    Ok(string) => string.to_string(),
+                    _ => {
+                        return Err(Error::InvalidStr);
+                    }
+                };
+
+                names.push(stem);
+                let name = names.join("/");
+                names.pop();
+
+                self.register(name, source, Some(path));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn register(&mut self, name: String, source: String, file: Option<PathBuf>) {
+        Arc::make_mut(&mut self.sources).templates.insert(name, (source, file));
+
+        // Anything compiled so far may have used an older version of the
+        // template, or found it missing.
+        lock(&self.templates).clear();
+        *lock(&self.partials) = Arc::new(HashMap::new());
+    }
+
+    /// Get the template registered under `name`, compiling it if it hasn't
+    /// been used yet.
+    pub fn get(&self, name: &str) -> Result<Arc<Template>> {
+        if let Some(template) = lock(&self.templates).get(name) {
+            return Ok(template.clone());
+        }
+
+        let (source, file) = match self.sources.templates.get(name) {
+            Some(&(ref source, ref file)) => (source, file),
+            None => return Err(Error::UnknownTemplate(name.to_string())),
+        };
+
+        // Registered templates take precedence over the context's partials.
+        let mut ctx = self.ctx.clone();
+        let loader = ChainLoader::new()
+            .push(self.sources.clone())
+            .push(context::partial_loader(&self.ctx));
+        ctx.partial_loader = Some(Arc::new(loader));
+
+        let mut partials = lock(&self.partials);
+
+        // The template may have been compiled already as a partial of another.
+        let tokens = match partials.get(name) {
+            Some(tokens) => tokens.clone(),
+            None => {
+                let compiler = Compiler::new_with(ctx.clone(),
+                                                  source.chars(),
+                                                  (**partials).clone(),
+                                                  "{{".to_string(),
+                                                  "}}".to_string());
+
+                let (tokens, mut compiled) = try!(compiler.compile().map_err(|err| {
+                    match *file {
+                        Some(ref file) => error::in_file(err, file),
+                        None => err,
+                    }
+                }));
+
+                let tokens = Arc::new(tokens);
+                compiled.insert(name.to_string(), tokens.clone());
+                *partials = Arc::new(compiled);
+                tokens
+            }
+        };
+
+        let mut template = template::new(ctx, tokens, partials.clone());
+        if let Some(ref file) = *file {
+            template::set_file(&mut template, file.clone());
+        }
+
+        let template = Arc::new(template);
+        lock(&self.templates).insert(name.to_string(), template.clone());
+
+        Ok(template)
+    }
+
+    /// Renders the template registered under `name` with the `Serialize` data.
+    pub fn render<T, W>(&self, name: &str, data: &T, wr: &mut W) -> Result<()>
+    where T: Serialize,
+          W: Write,
+    {
+        let template = try!(self.get(name));
+        template.render(wr, data)
+    }
+
+    /// Renders the template registered under `name` with the `Data`.
+    pub fn render_data<W: Write>(&self, name: &str, data: &Data, wr: &mut W) -> Result<()> {
+        let template = try!(self.get(name));
+        template.render_data(wr, data)
+    }
+}
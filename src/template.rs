@@ -1,32 +1,124 @@
+use std::fmt;
 use std::io::Write;
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::mem;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
 use std::str;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::SystemTime;
 use serde::Serialize;
 
-use compiler::Compiler;
-use parser::Token;
+use compiler::{self, Compiler, PartialsMap};
+use context;
+use data::Lambda;
+use error::{self, Limit, Location};
+use escape::Escaper;
+use filter::Arg;
+use format::Format;
+use parser::{Parser, Span, Token};
 
-use super::{Context, Data, Result, to_data};
+use super::{Context, ContextValue, Data, Error, Limits, Result, to_data};
 
 /// `Template` represents a compiled mustache file.
 #[derive(Debug, Clone)]
 pub struct Template {
     ctx: Context,
-    tokens: Vec<Token>,
-    partials: HashMap<String, Vec<Token>>,
+    // The tokens and partials are shared with the clones of the template, and
+    // with the other templates of a `Registry`.
+    tokens: Arc<Vec<Token>>,
+    partials: Arc<PartialsMap>,
+    lazy_partials: Arc<Mutex<PartialsMap>>,
+    // When reloading, the modification times of the lazy partials when they
+    // were compiled.
+    lazy_modified: Arc<Mutex<HashMap<String, Option<SystemTime>>>>,
+    file: Option<PathBuf>,
+    reloader: Option<Arc<Mutex<Reloader>>>,
+    lambda_cache: Arc<Mutex<LambdaCache>>,
+}
+
+/// How many lambda results are kept compiled. Lambdas that return something
+/// different every time would otherwise grow the cache without bound.
+const LAMBDA_CACHE_SIZE: usize = 1024;
+
+/// The compiled text lambdas returned, by the text and the delimiters it was
+/// compiled with. When it's full, the entry that was used the longest ago
+/// makes room for the new one, so the texts lambdas return over and over stay
+/// compiled even when others only come up once.
+#[derive(Debug)]
+struct LambdaCache {
+    entries: HashMap<(String, String, String), (Arc<Vec<Token>>, u64)>,
+    capacity: usize,
+    // Counts the lookups, to tell when each entry was last used.
+    clock: u64,
+}
+
+impl LambdaCache {
+    fn new(capacity: usize) -> LambdaCache {
+        LambdaCache {
+            entries: HashMap::new(),
+            capacity: capacity,
+            clock: 0,
+        }
+    }
+
+    fn get(&mut self, key: &(String, String, String)) -> Option<Arc<Vec<Token>>> {
+        self.clock += 1;
+        let clock = self.clock;
+        self.entries.get_mut(key).map(|entry| {
+            entry.1 = clock;
+            entry.0.clone()
+        })
+    }
+
+    fn insert(&mut self, key: (String, String, String), tokens: Arc<Vec<Token>>) {
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            // Finding the oldest entry takes a pass over all of them, but
+            // that only happens when the text has to be compiled anyway.
+            let oldest = self.entries
+                .iter()
+                .min_by_key(|&(_, &(_, used))| used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+
+        self.entries.insert(key, (tokens, self.clock));
+    }
 }
 
 /// Construct a `Template`. This is not part of the impl of Template so it is
 /// not exported outside of mustache.
-pub fn new(ctx: Context, tokens: Vec<Token>, partials: HashMap<String, Vec<Token>>) -> Template {
+pub fn new(ctx: Context, tokens: Arc<Vec<Token>>, partials: Arc<PartialsMap>) -> Template {
     Template {
         ctx: ctx,
         tokens: tokens,
         partials: partials,
+        lazy_partials: Arc::new(Mutex::new(HashMap::new())),
+        lazy_modified: Arc::new(Mutex::new(HashMap::new())),
+        file: None,
+        reloader: None,
+        lambda_cache: Arc::new(Mutex::new(LambdaCache::new(LAMBDA_CACHE_SIZE))),
     }
 }
 
+/// Make a `Template` recompile whatever changed on disk before it renders.
+/// This is not part of the impl of Template so it is not exported outside of
+/// mustache.
+pub fn reloading(template: Template) -> Template {
+    let reloader = Reloader::new(Arc::new(template.clone()));
+    Template { reloader: Some(Arc::new(Mutex::new(reloader))), ..template }
+}
+
+/// Record the file a `Template` was read from, so errors can point into it.
+/// This is not part of the impl of Template so it is not exported outside of
+/// mustache.
+pub fn set_file(template: &mut Template, file: PathBuf) {
+    template.file = Some(file);
+}
+
 impl Template {
     /// Renders the template with the `Encodable` data.
     pub fn render<W, T>(&self, wr: &mut W, data: &T) -> Result<()>
@@ -37,12 +129,708 @@ impl Template {
         self.render_data(wr, &data)
     }
 
+    /// Sets how values in `{{name}}` tags are escaped, for this template and
+    /// the partials it renders.
+    pub fn set_escaper<E: Escaper + 'static>(&mut self, escaper: E) {
+        self.ctx.escaper = Arc::new(escaper);
+    }
+
+    /// Sets how much work rendering this template may do, like
+    /// `Context::limits`.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.ctx.limits = limits;
+    }
+
+    /// Registers a filter for this template and the partials it renders, like
+    /// `Context::register_filter`.
+    pub fn register_filter<K, F>(&mut self, name: K, filter: F)
+    where K: Into<String>,
+          F: Fn(&Data, &[Arg]) -> Result<Data> + Send + Sync + 'static,
+    {
+        self.ctx.register_filter(name, filter);
+    }
+
     /// Renders the template with the `Data`.
     pub fn render_data<W: Write>(&self, wr: &mut W, data: &Data) -> Result<()> {
+        self.render_output(&mut IoOutput(wr), data)
+    }
+
+    /// Renders the template with a `ContextValue`, looking values up in it as
+    /// they're needed instead of copying it into `Data` first.
+    ///
+    /// ```rust
+    /// use std::collections::HashMap;
+    ///
+    /// let template = mustache::compile_str("{{#names}}<{{.}}>{{/names}}").unwrap();
+    /// let mut data = HashMap::new();
+    /// data.insert("names", vec!["a", "b"]);
+    ///
+    /// let mut out = Vec::new();
+    /// template.render_value(&mut out, &data).unwrap();
+    /// assert_eq!(out, b"<a><b>");
+    /// ```
+    pub fn render_value<W: Write>(&self, wr: &mut W, value: &ContextValue) -> Result<()> {
+        self.render_output(&mut IoOutput(wr), value)
+    }
+
+    /// Renders the template with the `Encodable` data to a `String`.
+    pub fn render_to_string<T: Serialize>(&self, data: &T) -> Result<String> {
+        let data = to_data(data)?;
+        self.render_data_to_string(&data)
+    }
+
+    /// Renders the template with the `Data` to a `String`.
+    pub fn render_data_to_string(&self, data: &Data) -> Result<String> {
+        let mut out = String::new();
+        try!(self.render_output(&mut out, data));
+        Ok(out)
+    }
+
+    /// Renders the template with the `Encodable` data to a `fmt::Write`, such
+    /// as a `String` or a `fmt::Formatter`.
+    pub fn render_fmt<W, T>(&self, wr: &mut W, data: &T) -> Result<()>
+    where W: fmt::Write,
+          T: Serialize,
+    {
+        let data = to_data(data)?;
+        self.render_output(&mut FmtOutput(wr), &data)
+    }
+
+    /// Pairs the template with the `Encodable` data, in a value that renders
+    /// them when it's displayed, so it can be used with `format!` or logged.
+    /// Errors can't be told apart from each other through `Display`, so
+    /// they're all `fmt::Error`.
+    ///
+    /// ```rust
+    /// let template = mustache::compile_str("Hello {{name}}!").unwrap();
+    /// let data = vec![("name", "world")].into_iter().collect::<std::collections::HashMap<_, _>>();
+    /// assert_eq!(format!("<{}>", template.bind(&data)), "<Hello world!>");
+    /// ```
+    pub fn bind<'a, T: Serialize>(&'a self, data: &'a T) -> Bound<'a, T> {
+        Bound {
+            template: self,
+            data: data,
+        }
+    }
+
+    /// Renders the template with the `Data` a piece at a time, as the
+    /// returned iterator is advanced, so the output doesn't need to be held
+    /// in memory all at once. Text from the template is passed through
+    /// without being copied when it's long enough to be a chunk by itself.
+    ///
+    /// Sections, inverted sections and partials the template was compiled
+    /// with are streamed. Anything else, such as a lambda, a parent template
+    /// or a partial that's compiled while rendering, is rendered whole into
+    /// the chunk it's in. A template that reloads is always rendered whole.
+    ///
+    /// ```rust
+    /// use mustache::MapBuilder;
+    ///
+    /// let template = mustache::compile_str("{{#items}}<li>{{.}}</li>{{/items}}").unwrap();
+    /// let data = MapBuilder::new()
+    ///     .insert_vec("items", |b| b.push_str("a").push_str("b"))
+    ///     .build();
+    ///
+    /// let mut out = String::new();
+    /// for chunk in template.stream(&data) {
+    ///     out.push_str(&chunk.unwrap());
+    /// }
+    /// assert_eq!(out, "<li>a</li><li>b</li>");
+    /// ```
+    pub fn stream<'a>(&'a self, data: &'a Data) -> Stream<'a> {
+        self.stream_value(data)
+    }
+
+    /// Like `stream`, for anything that implements `ContextValue`.
+    pub fn stream_value<'a>(&'a self, data: &'a ContextValue) -> Stream<'a> {
+        let rendered = match self.reloader {
+            Some(_) => {
+                let mut out = String::new();
+                Some(self.render_output(&mut out, data).map(|_| out))
+            }
+            None => None,
+        };
+
+        Stream {
+            render_ctx: RenderContext::new(self),
+            stack: vec![data],
+            frames: vec![Frame::new(&self.tokens)],
+            chunk: String::new(),
+            rendered: rendered,
+            done: false,
+        }
+    }
+
+    fn render_output<W: Output>(&self, out: &mut W, data: &ContextValue) -> Result<()> {
+        if let Some(ref reloader) = self.reloader {
+            // Escapers, limits and filters may have been set on this template
+            // since the reloader was made, so render with its context.
+            let current = try!(lock(reloader).refresh(&self.ctx));
+            let template = Template { ctx: self.ctx.clone(), ..(*current).clone() };
+            return template.render_output(out, data);
+        }
+
         let mut render_ctx = RenderContext::new(self);
         let mut stack = vec![data];
 
-        render_ctx.render(wr, &mut stack, &self.tokens)
+        render_ctx.render(out, &mut stack, &self.tokens)
+    }
+
+    /// Compile the text a lambda returned, reusing the tokens from the last
+    /// time it returned the same text. Partials the text uses are looked up
+    /// when they're rendered, so they don't need to be compiled here.
+    fn compile_lambda(&self, src: &str, otag: &str, ctag: &str) -> Result<Arc<Vec<Token>>> {
+        let key = (src.to_string(), otag.to_string(), ctag.to_string());
+
+        if let Some(tokens) = lock(&self.lambda_cache).get(&key) {
+            return Ok(tokens.clone());
+        }
+
+        let tokens = {
+            let mut reader = src.chars();
+            let parser = Parser::new(&mut reader, otag, ctag).pipelines(self.ctx.pipelines);
+            let (tokens, _) = try!(parser.parse());
+            Arc::new(tokens)
+        };
+
+        lock(&self.lambda_cache).insert(key, tokens.clone());
+
+        Ok(tokens)
+    }
+
+    /// Look up a partial that wasn't known when the template was compiled,
+    /// compiling it the first time it's used.
+    fn lazy_partial(&self, name: &str, span: Span) -> Result<Arc<Vec<Token>>> {
+        let mut lazy_partials = lock(&self.lazy_partials);
+
+        if let Some(tokens) = lazy_partials.get(name) {
+            return Ok(tokens.clone());
+        }
+
+        let partials = try!(compiler::compile_partial(self.ctx.clone(),
+                                                      name,
+                                                      span,
+                                                      (*self.partials).clone()));
+
+        let loader = context::partial_loader(&self.ctx);
+        for (partial_name, tokens) in partials.into_iter() {
+            if !self.partials.contains_key(&partial_name) {
+                if self.ctx.reload {
+                    let time = modified(&loader.path(&partial_name));
+                    lock(&self.lazy_modified).entry(partial_name.clone()).or_insert(time);
+                }
+                lazy_partials.entry(partial_name).or_insert(tokens);
+            }
+        }
+
+        match lazy_partials.get(name) {
+            Some(tokens) => Ok(tokens.clone()),
+            None => bug!("lazy_partial: {:?} was not compiled", name),
+        }
+    }
+}
+
+/// Keeps a template up to date with the files it was compiled from, by
+/// comparing their modification times before every render.
+#[derive(Debug)]
+struct Reloader {
+    current: Arc<Template>,
+    modified: Option<SystemTime>,
+    // The modification times of the partials, by name.
+    partials: HashMap<String, Option<SystemTime>>,
+}
+
+impl Reloader {
+    fn new(current: Arc<Template>) -> Reloader {
+        let mut reloader = Reloader {
+            current: current,
+            modified: None,
+            partials: HashMap::new(),
+        };
+        reloader.track();
+        reloader
+    }
+
+    // Record the modification times of the current template and everything
+    // it uses.
+    fn track(&mut self) {
+        let loader = context::partial_loader(&self.current.ctx);
+
+        self.modified = self.current.file.as_ref().and_then(|file| modified(file));
+        self.partials = self.current
+            .partials
+            .keys()
+            .map(|name| (name.clone(), modified(&loader.path(name))))
+            .collect();
+    }
+
+    // Recompile the template itself and the partials that changed since the
+    // last render, if any did, with `ctx`.
+    fn refresh(&mut self, ctx: &Context) -> Result<Arc<Template>> {
+        let loader = context::partial_loader(ctx);
+
+        // Partials compiled lazily by the last render weren't known when we
+        // started tracking, so start now.
+        for (name, &time) in lock(&self.current.lazy_modified).iter() {
+            self.partials.entry(name.clone()).or_insert(time);
+        }
+
+        let root_changed = match self.current.file {
+            Some(ref file) => modified(file) != self.modified,
+            None => false,
+        };
+        let changed: Vec<String> = self.partials
+            .iter()
+            .filter(|&(name, &time)| modified(&loader.path(name)) != time)
+            .map(|(name, _)| name.clone())
+            .collect();
+
+        if !root_changed && changed.is_empty() {
+            return Ok(self.current.clone());
+        }
+
+        // Keep everything that didn't change, so only the changes get compiled.
+        let mut partials = (*self.current.partials).clone();
+        partials.extend(lock(&self.current.lazy_partials).iter().map(|(name, tokens)| {
+            (name.clone(), tokens.clone())
+        }));
+        for name in changed.iter() {
+            partials.remove(name);
+        }
+
+        let (tokens, partials) = match self.current.file {
+            Some(ref file) if root_changed => {
+                let src = try!(context::read_file(file));
+                let compiler = Compiler::new_with(ctx.clone(),
+                                                  src.chars(),
+                                                  partials,
+                                                  "{{".to_string(),
+                                                  "}}".to_string());
+                let (tokens, partials) = try!(compiler.compile()
+                    .map_err(|err| error::in_file(err, file)));
+                (Arc::new(tokens), partials)
+            }
+            _ => {
+                for name in changed.iter() {
+                    partials = try!(compiler::compile_partial(ctx.clone(),
+                                                              name,
+                                                              Span::default(),
+                                                              partials));
+                }
+                (self.current.tokens.clone(), partials)
+            }
+        };
+
+        let mut template = new(ctx.clone(), tokens, Arc::new(partials));
+        template.file = self.current.file.clone();
+
+        self.current = Arc::new(template);
+        self.track();
+
+        Ok(self.current.clone())
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+fn number_to_string(value: &Data, format: Option<&Format>) -> String {
+    match (value, format) {
+        (&Data::Int(n), None) => n.to_string(),
+        (&Data::Uint(n), None) => n.to_string(),
+        (&Data::Float(n), None) => n.to_string(),
+        (&Data::Int(n), Some(format)) => format.format_int(n),
+        (&Data::Uint(n), Some(format)) => format.format_uint(n),
+        (&Data::Float(n), Some(format)) => format.format_float(n),
+        (value, _) => bug!("number_to_string: unexpected value {:?}", value),
+    }
+}
+
+/// Lock `mutex`, even if a thread panicked while holding it. Nothing is left
+/// half updated by a panic, so the data is still good.
+pub fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+/// A `Template` with the data to render it with, from `Template::bind`.
+pub struct Bound<'a, T: 'a> {
+    template: &'a Template,
+    data: &'a T,
+}
+
+impl<'a, T: Serialize> fmt::Display for Bound<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.template.render_fmt(f, self.data).map_err(|_| fmt::Error)
+    }
+}
+
+/// How much output `Stream` collects before it yields it.
+const CHUNK_SIZE: usize = 8 * 1024;
+
+/// The iterator returned by `Template::stream`, which renders the template as
+/// it's advanced and yields the output in chunks. It stops after the first
+/// error.
+pub struct Stream<'a> {
+    render_ctx: RenderContext<'a>,
+    stack: Vec<&'a ContextValue>,
+    // The tokens being rendered, innermost last.
+    frames: Vec<Frame<'a>>,
+    // The output that hasn't been yielded yet.
+    chunk: String,
+    // The whole output of a template that reloads.
+    rendered: Option<Result<String>>,
+    done: bool,
+}
+
+/// A list of tokens `Stream` is going through, and what to undo when it's
+/// done with them.
+struct Frame<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    // The rest of the list a section is rendering its tokens for.
+    items: Option<Box<Iterator<Item = &'a ContextValue> + Send + 'a>>,
+    // Whether a value was pushed onto the stack for the tokens.
+    pushed: bool,
+    // Whether the frame counts towards the depth limit.
+    nested: bool,
+    // The section or partial tag the tokens are from.
+    span: Span,
+    // The indent and partial to go back to.
+    restore: Option<(String, Option<String>)>,
+}
+
+impl<'a> Frame<'a> {
+    fn new(tokens: &'a [Token]) -> Frame<'a> {
+        Frame {
+            tokens: tokens,
+            pos: 0,
+            items: None,
+            pushed: false,
+            nested: false,
+            span: Span::default(),
+            restore: None,
+        }
+    }
+}
+
+impl<'a> Iterator for Stream<'a> {
+    type Item = Result<Cow<'a, str>>;
+
+    fn next(&mut self) -> Option<Result<Cow<'a, str>>> {
+        if let Some(rendered) = self.rendered.take() {
+            self.done = true;
+            return Some(rendered.map(Cow::Owned));
+        }
+
+        while !self.done {
+            if self.chunk.len() >= CHUNK_SIZE {
+                return Some(Ok(Cow::Owned(self.take_chunk())));
+            }
+
+            match self.step() {
+                Ok(Some(chunk)) => return Some(Ok(chunk)),
+                Ok(None) => {}
+                Err(err) => {
+                    self.done = true;
+                    self.chunk.clear();
+                    return Some(Err(err));
+                }
+            }
+        }
+
+        if self.chunk.is_empty() {
+            None
+        } else {
+            Some(Ok(Cow::Owned(self.take_chunk())))
+        }
+    }
+}
+
+impl<'a> Stream<'a> {
+    /// Renders the next token, returning a chunk if one is ready before the
+    /// output has grown to `CHUNK_SIZE`.
+    fn step(&mut self) -> Result<Option<Cow<'a, str>>> {
+        let token = match self.frames.last_mut() {
+            None => {
+                self.done = true;
+                return Ok(None);
+            }
+            Some(frame) => {
+                let tokens = frame.tokens;
+                match tokens.get(frame.pos) {
+                    Some(token) => {
+                        frame.pos += 1;
+                        token
+                    }
+                    None => {
+                        try!(self.next_item());
+                        return Ok(None);
+                    }
+                }
+            }
+        };
+
+        match *token {
+            // Long text goes out as it is, after what came before it.
+            Token::Text(ref value, span) if value.len() >= CHUNK_SIZE &&
+                                            self.render_ctx.indent.is_empty() => {
+                if !self.chunk.is_empty() {
+                    self.frames.last_mut().unwrap().pos -= 1;
+                    return Ok(Some(Cow::Owned(self.take_chunk())));
+                }
+
+                self.render_ctx.span = span;
+                try!(self.render_ctx.count_written(value.len()));
+                self.render_ctx.track_newlines(value);
+                Ok(Some(Cow::Borrowed(value)))
+            }
+            Token::Section(ref path, inverted, ref children, ..) => {
+                let strict = self.render_ctx.template.ctx.strict;
+
+                match (inverted, self.render_ctx.find(path, &mut self.stack)) {
+                    // The renderer takes care of lambdas, and of the error
+                    // for a missing section.
+                    (_, None) if strict => self.render_token(token),
+
+                    (false, None) => Ok(None),
+                    (false, Some(value)) => {
+                        match value.as_data() {
+                            Some(&Data::Fun(_)) |
+                            Some(&Data::Lambda(_)) => self.render_token(token),
+                            Some(&Data::Bool(true)) => self.enter(token, children, None),
+                            _ => {
+                                match value.items() {
+                                    Some(items) => {
+                                        if value.truthy() {
+                                            try!(self.enter(token, children, None));
+                                            let frame = self.frames.last_mut().unwrap();
+                                            // Start at the end of the tokens, so the
+                                            // first item is pushed like the others.
+                                            frame.pos = children.len();
+                                            frame.items = Some(items);
+                                        }
+                                        Ok(None)
+                                    }
+                                    None if self.render_ctx.is_falsy(value) => Ok(None),
+                                    None => self.enter(token, children, Some(value)),
+                                }
+                            }
+                        }
+                    }
+
+                    (true, None) => self.enter(token, children, None),
+                    (true, Some(value)) => {
+                        if self.render_ctx.is_falsy(value) {
+                            self.enter(token, children, None)
+                        } else {
+                            Ok(None)
+                        }
+                    }
+                }
+            }
+            Token::Partial(ref name, ref indent, _, _) => self.enter_partial(token, name, indent),
+            Token::DynamicPartial(ref path, ref indent, _, _) => {
+                match self.render_ctx.find(path, &mut self.stack).and_then(partial_name) {
+                    Some(ref name) if is_relative_name(name) => {
+                        self.enter_partial(token, name, indent)
+                    }
+                    _ => self.render_token(token),
+                }
+            }
+            _ => self.render_token(token),
+        }
+    }
+
+    /// Renders a token whole into the chunk.
+    fn render_token(&mut self, token: &Token) -> Result<Option<Cow<'a, str>>> {
+        try!(self.render_ctx.render_token(&mut self.chunk, &mut self.stack, token));
+        Ok(None)
+    }
+
+    /// Starts going through the `tokens` of a section or partial, with
+    /// `value` on the stack.
+    fn enter(&mut self,
+             token: &Token,
+             tokens: &'a [Token],
+             value: Option<&'a ContextValue>)
+             -> Result<Option<Cow<'a, str>>> {
+        if let Some(max) = self.render_ctx.template.ctx.limits.max_depth {
+            if self.render_ctx.depth >= max {
+                let location = self.render_ctx.location(token.span());
+                return Err(Error::LimitExceeded(Limit::Depth(max), location));
+            }
+        }
+        self.render_ctx.depth += 1;
+
+        if let Some(value) = value {
+            self.stack.push(value);
+        }
+
+        let mut frame = Frame::new(tokens);
+        frame.pushed = value.is_some();
+        frame.nested = true;
+        frame.span = token.span();
+        self.frames.push(frame);
+
+        Ok(None)
+    }
+
+    fn enter_partial(&mut self,
+                     token: &Token,
+                     name: &str,
+                     indent: &str)
+                     -> Result<Option<Cow<'a, str>>> {
+        // Partials that weren't compiled with the template are left to the
+        // renderer, which compiles them.
+        let template = self.render_ctx.template;
+        let tokens = match template.partials.get(name) {
+            Some(tokens) => &***tokens,
+            None => return self.render_token(token),
+        };
+
+        try!(self.enter(token, tokens, None));
+
+        let indent = self.render_ctx.indent.clone() + indent;
+        let indent = mem::replace(&mut self.render_ctx.indent, indent);
+        let partial = mem::replace(&mut self.render_ctx.partial, Some(name.to_string()));
+        self.frames.last_mut().unwrap().restore = Some((indent, partial));
+
+        Ok(None)
+    }
+
+    /// Moves on from a frame whose tokens are done, to the next item of its
+    /// list, or back to the frame it's in.
+    fn next_item(&mut self) -> Result<()> {
+        let mut frame = self.frames.pop().unwrap();
+
+        if frame.pushed {
+            self.stack.pop();
+            frame.pushed = false;
+        }
+
+        let item = frame.items.as_mut().and_then(|items| items.next());
+        if let Some(item) = item {
+            self.render_ctx.iterations += 1;
+            if let Some(max) = self.render_ctx.template.ctx.limits.max_iterations {
+                if self.render_ctx.iterations > max {
+                    return Err(Error::LimitExceeded(Limit::Iterations(max),
+                                                    self.render_ctx.location(frame.span)));
+                }
+            }
+
+            self.stack.push(item);
+            frame.pushed = true;
+            frame.pos = 0;
+            self.frames.push(frame);
+            return Ok(());
+        }
+
+        if let Some((indent, partial)) = frame.restore {
+            self.render_ctx.indent = indent;
+            self.render_ctx.partial = partial;
+        }
+        if frame.nested {
+            self.render_ctx.depth -= 1;
+        }
+
+        Ok(())
+    }
+
+    fn take_chunk(&mut self) -> String {
+        mem::replace(&mut self.chunk, String::new())
+    }
+}
+
+/// Where rendered text is written. Everything is rendered as `str`, so it
+/// can go straight into a `String` or `fmt::Write` without being checked to
+/// be UTF-8 again.
+trait Output {
+    fn write_str(&mut self, s: &str) -> Result<()>;
+}
+
+impl Output for String {
+    fn write_str(&mut self, s: &str) -> Result<()> {
+        self.push_str(s);
+        Ok(())
+    }
+}
+
+struct IoOutput<'a, W: Write + 'a>(&'a mut W);
+
+impl<'a, W: Write> Output for IoOutput<'a, W> {
+    fn write_str(&mut self, s: &str) -> Result<()> {
+        try!(self.0.write_all(s.as_bytes()));
+        Ok(())
+    }
+}
+
+struct FmtOutput<'a, W: fmt::Write + 'a>(&'a mut W);
+
+impl<'a, W: fmt::Write> Output for FmtOutput<'a, W> {
+    fn write_str(&mut self, s: &str) -> Result<()> {
+        try!(self.0.write_str(s));
+        Ok(())
+    }
+}
+
+/// What a `Data::Lambda` is given to work with: the text of the section it's
+/// called for, and the means to render text and look up values like the
+/// template would at this point.
+pub struct LambdaContext<'a, 'b: 'a, 'c: 'a> {
+    render_ctx: &'a mut RenderContext<'b>,
+    stack: &'a mut Vec<&'c ContextValue>,
+    text: &'a str,
+    otag: &'a str,
+    ctag: &'a str,
+}
+
+impl<'a, 'b, 'c> LambdaContext<'a, 'b, 'c> {
+    /// The unrendered text of the section the lambda was called for, or an
+    /// empty string for `{{lambda}}` tags.
+    pub fn text(&self) -> &str {
+        self.text
+    }
+
+    /// The delimiters in effect where the lambda was called.
+    pub fn delimiters(&self) -> (&str, &str) {
+        (self.otag, self.ctag)
+    }
+
+    /// Renders `src` with the current delimiters and data, as if it was in
+    /// the template in place of the lambda.
+    pub fn render(&mut self, src: &str) -> Result<String> {
+        let tokens = try!(self.render_ctx.template.compile_lambda(src, self.otag, self.ctag));
+
+        // The output is written out by the caller, which takes care of the
+        // indentation.
+        let indent = mem::replace(&mut self.render_ctx.indent, String::new());
+        let line_start = self.render_ctx.line_start;
+        let written = self.render_ctx.written;
+
+        let mut out = String::new();
+        let result = self.render_ctx.render(&mut out, self.stack, &tokens);
+
+        self.render_ctx.indent = indent;
+        self.render_ctx.line_start = line_start;
+        self.render_ctx.written = written;
+
+        try!(result);
+        Ok(out)
+    }
+
+    /// Looks up a value like a tag would, with a dotted `name` such as
+    /// `user.name`, or `.` for the current value.
+    pub fn lookup(&mut self, name: &str) -> Option<&'c ContextValue> {
+        let path: Vec<String> = if name == "." {
+            Vec::new()
+        } else {
+            name.split_terminator('.').map(|part| part.to_string()).collect()
+        };
+        self.render_ctx.find(&path, self.stack)
     }
 }
 
@@ -50,6 +838,15 @@ struct RenderContext<'a> {
     template: &'a Template,
     indent: String,
     line_start: bool,
+    blocks: Vec<HashMap<String, (Arc<Vec<Token>>, Option<String>)>>,
+    // The partial being rendered, or `None` for the template itself.
+    partial: Option<String>,
+    // The token being rendered.
+    span: Span,
+    // What counts towards the limits.
+    depth: usize,
+    written: usize,
+    iterations: usize,
 }
 
 impl<'a> RenderContext<'a> {
@@ -58,10 +855,16 @@ impl<'a> RenderContext<'a> {
             template: template,
             indent: "".to_string(),
             line_start: true,
+            blocks: Vec::new(),
+            partial: None,
+            span: Span::default(),
+            depth: 0,
+            written: 0,
+            iterations: 0,
         }
     }
 
-    fn render<W: Write>(&mut self, wr: &mut W, stack: &mut Vec<&Data>, tokens: &[Token]) -> Result<()> {
+    fn render<W: Output>(&mut self, wr: &mut W, stack: &mut Vec<&ContextValue>, tokens: &[Token]) -> Result<()> {
         for token in tokens.iter() {
             try!(self.render_token(wr, stack, token));
         }
@@ -69,52 +872,123 @@ impl<'a> RenderContext<'a> {
         Ok(())
     }
 
-    fn render_token<W: Write>(&mut self, wr: &mut W, stack: &mut Vec<&Data>, token: &Token) -> Result<()> {
+    fn render_token<W: Output>(&mut self, wr: &mut W, stack: &mut Vec<&ContextValue>, token: &Token) -> Result<()> {
+        // Sections and partials are where rendering recurses, so they're
+        // what the depth limit counts.
+        let nested = match *token {
+            Token::Section(..) | Token::Partial(..) | Token::DynamicPartial(..) | Token::Parent(..) => true,
+            _ => false,
+        };
+
+        if nested {
+            if let Some(max) = self.template.ctx.limits.max_depth {
+                if self.depth >= max {
+                    return Err(Error::LimitExceeded(Limit::Depth(max), self.location(token.span())));
+                }
+            }
+            self.depth += 1;
+        }
+
+        let span = mem::replace(&mut self.span, token.span());
+        let result = self.render_token_kind(wr, stack, token);
+        self.span = span;
+
+        if nested {
+            self.depth -= 1;
+        }
+
+        result
+    }
+
+    fn render_token_kind<W: Output>(&mut self,
+                                   wr: &mut W,
+                                   stack: &mut Vec<&ContextValue>,
+                                   token: &Token)
+                                   -> Result<()> {
         match *token {
-            Token::Text(ref value) => {
+            Token::Text(ref value, _) => {
                 self.render_text(wr, value)
             }
-            Token::EscapedTag(ref path, _) => {
-                self.render_etag(wr, stack, path)
+            Token::EscapedTag(ref path, ref filters, ref format, _, span) => {
+                self.render_etag(wr, stack, path, filters, format.as_ref(), span)
+            }
+            Token::UnescapedTag(ref path, ref filters, ref format, _, span) => {
+                self.render_utag(wr, stack, path, filters, format.as_ref(), span)
+            }
+            Token::Section(ref path, true, ref children, _, _, _, _, _, span) => {
+                self.render_inverted_section(wr, stack, path, children, span)
+            }
+            Token::Section(ref path, false, ref children, ref otag, _, ref src, _, ref ctag, span) => {
+                self.render_section(wr, stack, path, children, src, otag, ctag, span)
             }
-            Token::UnescapedTag(ref path, _) => {
-                self.render_utag(wr, stack, path)
+            Token::Partial(ref name, ref indent, _, span) => {
+                self.render_partial(wr, stack, name, indent, span)
             }
-            Token::Section(ref path, true, ref children, _, _, _, _, _) => {
-                self.render_inverted_section(wr, stack, path, children)
+            Token::DynamicPartial(ref path, ref indent, _, span) => {
+                self.render_dynamic_partial(wr, stack, path, indent, span)
             }
-            Token::Section(ref path, false, ref children, ref otag, _, ref src, _, ref ctag) => {
-                self.render_section(wr, stack, path, children, src, otag, ctag)
+            Token::Block(ref name, ref indent, ref children, _, _, _, _) => {
+                self.render_block(wr, stack, name, indent, children)
             }
-            Token::Partial(ref name, ref indent, _) => {
-                self.render_partial(wr, stack, name, indent)
+            Token::Parent(ref name, ref indent, ref children, _, _, _, span) => {
+                self.render_parent(wr, stack, name, indent, children, span)
             }
-            Token::IncompleteSection(..) => {
+            Token::IncompleteSection(..) |
+            Token::IncompleteBlock(..) |
+            Token::IncompleteParent(..) => {
                 bug!("render_token should not encounter IncompleteSections")
             }
         }
     }
 
-    fn write_tracking_newlines<W: Write>(&mut self, wr: &mut W, value: &str) -> Result<()> {
-        try!(wr.write_all(value.as_bytes()));
+    /// Fails if writing `len` more bytes would go past `max_output`.
+    fn check_output(&self, len: usize) -> Result<()> {
+        if let Some(max) = self.template.ctx.limits.max_output {
+            if self.written.saturating_add(len) > max {
+                return Err(Error::LimitExceeded(Limit::Output(max), self.location(self.span)));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn count_written(&mut self, len: usize) -> Result<()> {
+        try!(self.check_output(len));
+        self.written += len;
+
+        Ok(())
+    }
+
+    fn write_str<W: Output>(&mut self, wr: &mut W, value: &str) -> Result<()> {
+        try!(self.count_written(value.len()));
+        wr.write_str(value)
+    }
+
+    fn track_newlines(&mut self, value: &str) {
         self.line_start = match value.chars().last() {
             None => self.line_start, // None == ""
             Some('\n') => true,
             _ => false,
         };
+    }
+
+    fn write_tracking_newlines<W: Output>(&mut self, wr: &mut W, value: &str) -> Result<()> {
+        try!(self.write_str(wr, value));
+        self.track_newlines(value);
 
         Ok(())
     }
 
-    fn write_indent<W: Write>(&mut self, wr: &mut W) -> Result<()> {
+    fn write_indent<W: Output>(&mut self, wr: &mut W) -> Result<()> {
         if self.line_start {
-            try!(wr.write_all(self.indent.as_bytes()));
+            let indent = self.indent.clone();
+            try!(self.write_str(wr, &indent));
         }
 
         Ok(())
     }
 
-    fn render_text<W: Write>(&mut self, wr: &mut W, value: &str) -> Result<()> {
+    fn render_text<W: Output>(&mut self, wr: &mut W, value: &str) -> Result<()> {
         // Indent the lines.
         if self.indent.is_empty() {
             return self.write_tracking_newlines(wr, value);
@@ -148,83 +1022,253 @@ impl<'a> RenderContext<'a> {
         Ok(())
     }
 
-    fn render_etag<W: Write>(&mut self, wr: &mut W, stack: &mut Vec<&Data>, path: &[String]) -> Result<()> {
-        let mut bytes = vec![];
+    fn render_etag<W: Output>(&mut self,
+                             wr: &mut W,
+                             stack: &mut Vec<&ContextValue>,
+                             path: &[String],
+                             filters: &[(String, Vec<Arg>)],
+                             format: Option<&Format>,
+                             span: Span) -> Result<()> {
+        let escaper = self.template.ctx.escaper.clone();
+        self.render_value(wr, stack, path, filters, format, Some(&*escaper), span)
+    }
 
-        try!(self.render_utag(&mut bytes, stack, path));
+    fn render_utag<W: Output>(&mut self,
+                             wr: &mut W,
+                             stack: &mut Vec<&ContextValue>,
+                             path: &[String],
+                             filters: &[(String, Vec<Arg>)],
+                             format: Option<&Format>,
+                             span: Span) -> Result<()> {
+        self.render_value(wr, stack, path, filters, format, None, span)
+    }
 
-        for b in bytes {
-            match b {
-                b'<' => {
-                    try!(wr.write_all(b"&lt;"));
-                }
-                b'>' => {
-                    try!(wr.write_all(b"&gt;"));
-                }
-                b'&' => {
-                    try!(wr.write_all(b"&amp;"));
-                }
-                b'"' => {
-                    try!(wr.write_all(b"&quot;"));
-                }
-                b'\'' => {
-                    try!(wr.write_all(b"&#39;"));
+    fn render_value<W: Output>(&mut self,
+                              wr: &mut W,
+                              stack: &mut Vec<&ContextValue>,
+                              path: &[String],
+                              filters: &[(String, Vec<Arg>)],
+                              format: Option<&Format>,
+                              escaper: Option<&Escaper>,
+                              span: Span) -> Result<()> {
+        let mut value = self.find(path, stack);
+
+        if value.is_none() && self.template.ctx.strict {
+            return Err(Error::MissingVariable(path.join("."), self.location(span)));
+        }
+
+        let filtered;
+        if !filters.is_empty() {
+            // Filters work on `Data`, so anything else is copied for them.
+            let copied;
+            let input = match value {
+                Some(value) => {
+                    match value.as_data() {
+                        Some(data) => data,
+                        None => {
+                            copied = value.to_data();
+                            &copied
+                        }
+                    }
                 }
-                _ => {
-                    try!(wr.write_all(&[b]));
+                None => {
+                    copied = Data::Null;
+                    &copied
                 }
-            }
+            };
+            filtered = try!(self.apply_filters(input, filters, span));
+            value = Some(&filtered);
         }
 
-        Ok(())
-    }
-
-    fn render_utag<W: Write>(&mut self, wr: &mut W, stack: &mut Vec<&Data>, path: &[String]) -> Result<()> {
-        match self.find(path, stack) {
+        match value {
             None => {}
             Some(value) => {
                 try!(self.write_indent(wr));
 
-                // Currently this doesn't allow Option<Option<Foo>>, which
-                // would be un-nameable in the view anyway, so I'm unsure if it's
-                // a real problem. Having {{foo}} render only when `foo = Some(Some(val))`
-                // seems unintuitive and may be surprising in practice.
-                if let Data::Null = *value {
-                    return Ok(());
+                match value.as_data() {
+                    Some(data) => try!(self.render_data_value(wr, stack, data, format, escaper, span)),
+                    // Format specs work on `Data` too.
+                    None if format.is_some() => {
+                        let data = value.to_data();
+                        try!(self.render_data_value(wr, stack, &data, format, escaper, span));
+                    }
+                    None => {
+                        let mut written = String::new();
+                        value.write_to(&mut written);
+
+                        match escaper {
+                            Some(escaper) => {
+                                try!(self.write_tracking_newlines(wr, &escaper.escape(&written)));
+                            }
+                            None => try!(self.write_tracking_newlines(wr, &written)),
+                        }
+                    }
                 }
+            }
+        };
 
-                match *value {
-                    Data::String(ref value) => {
+        Ok(())
+    }
+
+    fn render_data_value<W: Output>(&mut self,
+                                   wr: &mut W,
+                                   stack: &mut Vec<&ContextValue>,
+                                   value: &Data,
+                                   format: Option<&Format>,
+                                   escaper: Option<&Escaper>,
+                                   span: Span) -> Result<()> {
+        // Currently this doesn't allow Option<Option<Foo>>, which
+        // would be un-nameable in the view anyway, so I'm unsure if it's
+        // a real problem. Having {{foo}} render only when `foo = Some(Some(val))`
+        // seems unintuitive and may be surprising in practice.
+        if let Data::Null = *value {
+            return Ok(());
+        }
+
+        // Don't pad a value that can't be written anyway.
+        if let Some(format) = format {
+            try!(self.check_output(format.min_len()));
+        }
+
+        match *value {
+            Data::String(ref value) => {
+                let formatted;
+                let value = match format {
+                    Some(format) => {
+                        formatted = format.format_str(value);
+                        &formatted
+                    }
+                    None => value,
+                };
+
+                match escaper {
+                    Some(escaper) => {
+                        try!(self.write_tracking_newlines(wr, &escaper.escape(value)));
+                    }
+                    None => {
                         try!(self.write_tracking_newlines(wr, value));
                     }
+                }
+            }
+
+            Data::Int(_) | Data::Uint(_) | Data::Float(_) => {
+                let value = number_to_string(value, format);
+                match escaper {
+                    Some(escaper) => {
+                        try!(self.write_tracking_newlines(wr, &escaper.escape(&value)));
+                    }
+                    None => try!(self.write_tracking_newlines(wr, &value)),
+                }
+            }
+
+            // etags and utags use the default delimiter.
+            Data::Fun(ref f) => {
+                let tokens = try!(self.render_fun("", "{{", "}}", f));
 
-                    // etags and utags use the default delimiter.
-                    Data::Fun(ref fcell) => {
-                        let f = &mut *fcell.borrow_mut();
-                        let tokens = try!(self.render_fun("", "{{", "}}", f));
+                match escaper {
+                    // The rendered lambda is escaped as a whole, like
+                    // any other value.
+                    Some(escaper) => {
+                        let written = self.written;
+                        let mut value = String::new();
+                        try!(self.render(&mut value, stack, &tokens));
+                        self.written = written;
+
+                        try!(self.write_str(wr, &escaper.escape(&value)));
+                    }
+                    None => {
                         try!(self.render(wr, stack, &tokens));
                     }
+                }
+            }
+
+            Data::Lambda(ref lambda) => {
+                let value = try!(self.render_lambda(stack, lambda, "", "{{", "}}", span));
+                let value = match format {
+                    Some(format) => format.format_str(&value),
+                    None => value,
+                };
+
+                match escaper {
+                    Some(escaper) => {
+                        try!(self.write_tracking_newlines(wr, &escaper.escape(&value)));
+                    }
+                    None => try!(self.write_tracking_newlines(wr, &value)),
+                }
+            }
 
-                    ref value => {
-                        bug!("render_utag: unexpected value {:?}", value);
+            Data::Bool(b) => {
+                let value = if b { "true" } else { "false" };
+                match escaper {
+                    Some(escaper) => {
+                        try!(self.write_tracking_newlines(wr, &escaper.escape(value)));
                     }
+                    None => try!(self.write_tracking_newlines(wr, value)),
                 }
             }
-        };
+
+            // Lists and maps have nothing to write, like any other
+            // `ContextValue` that isn't a scalar.
+            Data::Null | Data::Vec(_) | Data::Map(_) => {}
+        }
 
         Ok(())
     }
 
-    fn render_inverted_section<W: Write>(&mut self,
+    /// Runs `value` through the filters of a tag, one after the other.
+    fn apply_filters(&self,
+                     value: &Data,
+                     filters: &[(String, Vec<Arg>)],
+                     span: Span) -> Result<Data> {
+        let mut filtered = None;
+
+        for &(ref name, ref args) in filters.iter() {
+            let filter = match self.template.ctx.filters.get(name) {
+                Some(filter) => filter.clone(),
+                None => return Err(Error::UnknownFilter(name.clone(), self.location(span))),
+            };
+
+            let output = try!(filter(filtered.as_ref().map_or(value, |&(_, ref output)| output), args));
+            filtered = Some((name, output));
+        }
+
+        // Filters can pass anything on to each other, but what the last one
+        // returns is interpolated.
+        match filtered {
+            Some((name, Data::Vec(_))) => {
+                Err(Error::Filter(format!("`{}` returned a list, which can't be interpolated", name)))
+            }
+            Some((name, Data::Map(_))) => {
+                Err(Error::Filter(format!("`{}` returned a map, which can't be interpolated", name)))
+            }
+            Some((_, output)) => Ok(output),
+            None => Ok(Data::Null),
+        }
+    }
+
+    /// Whether sections skip `value`, and inverted sections are rendered for
+    /// it. Zero is only falsy with `Context::zero_is_falsy`.
+    fn is_falsy(&self, value: &ContextValue) -> bool {
+        if value.is_number() {
+            self.template.ctx.zero_is_falsy && !value.truthy()
+        } else {
+            !value.truthy()
+        }
+    }
+
+    fn render_inverted_section<W: Output>(&mut self,
                                          wr: &mut W,
-                                         stack: &mut Vec<&Data>,
+                                         stack: &mut Vec<&ContextValue>,
                                          path: &[String],
-                                         children: &[Token]) -> Result<()> {
+                                         children: &[Token],
+                                         span: Span) -> Result<()> {
         match self.find(path, stack) {
-            None => {}
-            Some(&Data::Null) => {}
-            Some(&Data::Bool(false)) => {}
-            Some(&Data::Vec(ref xs)) if xs.is_empty() => {}
+            None => {
+                if self.template.ctx.strict {
+                    return Err(Error::MissingSection(path.join("."), self.location(span)));
+                }
+            }
+            Some(value) if self.is_falsy(value) => {}
             Some(_) => {
                 return Ok(());
             }
@@ -233,49 +1277,60 @@ impl<'a> RenderContext<'a> {
         self.render(wr, stack, children)
     }
 
-    fn render_section<W: Write>(&mut self,
+    fn render_section<W: Output>(&mut self,
                                 wr: &mut W,
-                                stack: &mut Vec<&Data>,
+                                stack: &mut Vec<&ContextValue>,
                                 path: &[String],
                                 children: &[Token],
                                 src: &str,
                                 otag: &str,
-                                ctag: &str) -> Result<()> {
+                                ctag: &str,
+                                span: Span) -> Result<()> {
         match self.find(path, stack) {
-            None => {}
+            None => {
+                if self.template.ctx.strict {
+                    return Err(Error::MissingSection(path.join("."), self.location(span)));
+                }
+            }
             Some(value) => {
-                match *value {
-                    Data::Null => {
-                        // do nothing
-                    }
-                    Data::Bool(true) => {
+                match value.as_data() {
+                    // `true` renders the section without changing the context.
+                    Some(&Data::Bool(true)) => {
                         try!(self.render(wr, stack, children));
                     }
-                    Data::Bool(false) => {}
-                    Data::String(ref val) => {
-                        if !val.is_empty() {
-                            stack.push(value);
-                            try!(self.render(wr, stack, children));
-                            stack.pop();
-                        }
-                    }
-                    Data::Vec(ref vs) => {
-                        for v in vs.iter() {
-                            stack.push(v);
-                            try!(self.render(wr, stack, children));
-                            stack.pop();
-                        }
-                    }
-                    Data::Map(_) => {
-                        stack.push(value);
-                        try!(self.render(wr, stack, children));
-                        stack.pop();
-                    }
-                    Data::Fun(ref fcell) => {
-                        let f = &mut *fcell.borrow_mut();
+                    Some(&Data::Fun(ref f)) => {
                         let tokens = try!(self.render_fun(src, otag, ctag, f));
                         try!(self.render(wr, stack, &tokens));
                     }
+                    Some(&Data::Lambda(ref lambda)) => {
+                        let value = try!(self.render_lambda(stack, lambda, src, otag, ctag, span));
+                        try!(self.write_tracking_newlines(wr, &value));
+                    }
+                    _ => {
+                        match value.items() {
+                            Some(items) => {
+                                for v in items {
+                                    self.iterations += 1;
+                                    if let Some(max) = self.template.ctx.limits.max_iterations {
+                                        if self.iterations > max {
+                                            return Err(Error::LimitExceeded(Limit::Iterations(max),
+                                                                            self.location(span)));
+                                        }
+                                    }
+
+                                    stack.push(v);
+                                    try!(self.render(wr, stack, children));
+                                    stack.pop();
+                                }
+                            }
+                            None if self.is_falsy(value) => {}
+                            None => {
+                                stack.push(value);
+                                try!(self.render(wr, stack, children));
+                                stack.pop();
+                            }
+                        }
+                    }
                 }
             }
         };
@@ -283,44 +1338,178 @@ impl<'a> RenderContext<'a> {
         Ok(())
     }
 
-    fn render_partial<W: Write>(&mut self,
+    fn render_partial<W: Output>(&mut self,
                                 wr: &mut W,
-                                stack: &mut Vec<&Data>,
+                                stack: &mut Vec<&ContextValue>,
                                 name: &str,
-                                indent: &str) -> Result<()> {
-        match self.template.partials.get(name) {
-            None => {}
-            Some(ref tokens) => {
-                let mut indent = self.indent.clone() + indent;
+                                indent: &str,
+                                span: Span) -> Result<()> {
+        let template = self.template;
+        let lazy_tokens;
 
-                mem::swap(&mut self.indent, &mut indent);
-                try!(self.render(wr, stack, &tokens));
-                mem::swap(&mut self.indent, &mut indent);
+        let tokens = match template.partials.get(name) {
+            Some(tokens) => &**tokens,
+            None => {
+                lazy_tokens = try!(template.lazy_partial(name, span).map_err(|err| {
+                    match self.file() {
+                        Some(file) => error::in_file(err, &file),
+                        None => err,
+                    }
+                }));
+                &*lazy_tokens
             }
         };
 
-        Ok(())
+        let mut indent = self.indent.clone() + indent;
+        let mut partial = Some(name.to_string());
+
+        mem::swap(&mut self.indent, &mut indent);
+        mem::swap(&mut self.partial, &mut partial);
+        let result = self.render(wr, stack, tokens);
+        mem::swap(&mut self.partial, &mut partial);
+        mem::swap(&mut self.indent, &mut indent);
+
+        result
+    }
+
+    fn render_dynamic_partial<W: Output>(&mut self,
+                                        wr: &mut W,
+                                        stack: &mut Vec<&ContextValue>,
+                                        path: &[String],
+                                        indent: &str,
+                                        span: Span) -> Result<()> {
+        let value = self.find(path, stack);
+        let name = match value.and_then(partial_name) {
+            Some(name) => name,
+            None if value.is_none() && self.template.ctx.strict => {
+                return Err(Error::MissingVariable(path.join("."), self.location(span)));
+            }
+            _ => return Ok(()),
+        };
+
+        // The name comes from the data, so it mustn't reach outside of where
+        // the partials are loaded from.
+        if !is_relative_name(&name) {
+            if self.template.ctx.strict {
+                return Err(Error::MissingPartial(name, self.location(span)));
+            }
+            return Ok(());
+        }
+
+        self.render_partial(wr, stack, &name, indent, span)
+    }
+
+    fn render_block<W: Output>(&mut self,
+                              wr: &mut W,
+                              stack: &mut Vec<&ContextValue>,
+                              name: &str,
+                              indent: &str,
+                              children: &[Token]) -> Result<()> {
+        // The outermost override wins, so that the template a render started
+        // from takes precedence over the parents it extends.
+        let tokens = self.blocks.iter().filter_map(|blocks| blocks.get(name)).next().cloned();
+
+        let mut indent = self.indent.clone() + indent;
+
+        mem::swap(&mut self.indent, &mut indent);
+        let result = match tokens {
+            // Overrides come from the template that extended us, so errors in
+            // them should point there.
+            Some((tokens, mut partial)) => {
+                mem::swap(&mut self.partial, &mut partial);
+                let result = self.render(wr, stack, &tokens);
+                mem::swap(&mut self.partial, &mut partial);
+                result
+            }
+            None => self.render(wr, stack, children),
+        };
+        mem::swap(&mut self.indent, &mut indent);
+
+        result
+    }
+
+    fn render_parent<W: Output>(&mut self,
+                               wr: &mut W,
+                               stack: &mut Vec<&ContextValue>,
+                               name: &str,
+                               indent: &str,
+                               children: &[Token],
+                               span: Span) -> Result<()> {
+        let mut blocks = HashMap::new();
+        for child in children.iter() {
+            if let Token::Block(ref name, _, ref tokens, _, _, _, _) = *child {
+                blocks.insert(name.clone(), (tokens.clone(), self.partial.clone()));
+            }
+        }
+
+        self.blocks.push(blocks);
+        let result = self.render_partial(wr, stack, name, indent, span);
+        self.blocks.pop();
+
+        result
     }
 
     fn render_fun(&self,
                   src: &str,
                   otag: &str,
                   ctag: &str,
-                  f: &mut Box<FnMut(String) -> String + Send + 'static>)
-                  -> Result<Vec<Token>> {
-        let src = f(src.to_string());
+                  f: &Mutex<Box<FnMut(String) -> String + Send + 'static>>)
+                  -> Result<Arc<Vec<Token>>> {
+        // The lock is only held for the call, so the text the lambda returns
+        // can use the lambda again.
+        let src = (&mut *lock(f))(src.to_string());
 
-        let compiler = Compiler::new_with(self.template.ctx.clone(),
-                                          src.chars(),
-                                          self.template.partials.clone(),
-                                          otag.to_string(),
-                                          ctag.to_string());
+        self.template.compile_lambda(&src, otag, ctag)
+    }
 
-        let (tokens, _) = try!(compiler.compile());
-        Ok(tokens)
+    fn render_lambda(&mut self,
+                     stack: &mut Vec<&ContextValue>,
+                     lambda: &Lambda,
+                     src: &str,
+                     otag: &str,
+                     ctag: &str,
+                     span: Span)
+                     -> Result<String> {
+        let result = {
+            let mut ctx = LambdaContext {
+                render_ctx: self,
+                stack: stack,
+                text: src,
+                otag: otag,
+                ctag: ctag,
+            };
+            lambda(&mut ctx)
+        };
+
+        result.map_err(|err| {
+            // Errors from rendering in the lambda already know where they are.
+            match err.downcast::<Error>() {
+                Ok(err) => *err,
+                Err(err) => Error::Lambda(err, self.location(span)),
+            }
+        })
+    }
+
+    // The file being rendered. Partials only look theirs up when it's
+    // needed for an error, as a loader may have to do some work to find it.
+    fn file(&self) -> Option<PathBuf> {
+        match self.partial {
+            Some(ref name) => Some(context::partial_loader(&self.template.ctx).path(name)),
+            None => self.template.file.clone(),
+        }
+    }
+
+    fn location(&self, span: Span) -> Location {
+        Location {
+            file: self.file(),
+            span: span,
+        }
     }
 
-    fn find<'c>(&self, path: &[String], stack: &mut Vec<&'c Data>) -> Option<&'c Data> {
+    fn find<'c>(&self,
+                path: &[String],
+                stack: &mut Vec<&'c ContextValue>)
+                -> Option<&'c ContextValue> {
         // If we have an empty path, we just want the top value in our stack.
         if path.is_empty() {
             match stack.last() {
@@ -336,15 +1525,10 @@ impl<'a> RenderContext<'a> {
         // Otherwise, find the stack that has the first part of our path.
         let mut value = None;
 
-        for data in stack.iter().rev() {
-            match **data {
-                Data::Map(ref m) => {
-                    if let Some(v) = m.get(&path[0]) {
-                        value = Some(v);
-                        break;
-                    }
-                }
-                _ => { /* continue searching the stack */ },
+        for &data in stack.iter().rev() {
+            if let Some(v) = data.get(&path[0]) {
+                value = Some(v);
+                break;
             }
         }
 
@@ -357,18 +1541,11 @@ impl<'a> RenderContext<'a> {
         };
 
         for part in path[1..].iter() {
-            match *value {
-                Data::Map(ref m) => {
-                    match m.get(part) {
-                        Some(v) => {
-                            value = v;
-                        }
-                        None => {
-                            return None;
-                        }
-                    }
+            match value.get(part) {
+                Some(v) => {
+                    value = v;
                 }
-                _ => {
+                None => {
                     return None;
                 }
             }
@@ -377,3 +1554,58 @@ impl<'a> RenderContext<'a> {
         Some(value)
     }
 }
+
+/// The name of the partial a dynamic partial tag with `value` renders, if
+/// `value` is a string.
+fn partial_name(value: &ContextValue) -> Option<String> {
+    match value.as_data() {
+        Some(&Data::String(ref name)) => Some(name.clone()),
+        Some(_) => None,
+        None => {
+            match value.to_data() {
+                Data::String(name) => Some(name),
+                _ => None,
+            }
+        }
+    }
+}
+
+/// Whether `name` is a relative path that stays below the directory it's
+/// joined to, with no root or `..` components.
+fn is_relative_name(name: &str) -> bool {
+    Path::new(name).components().all(|component| {
+        match component {
+            Component::Normal(_) | Component::CurDir => true,
+            _ => false,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::LambdaCache;
+
+    fn key(src: &str) -> (String, String, String) {
+        (src.to_string(), "{{".to_string(), "}}".to_string())
+    }
+
+    #[test]
+    fn test_lambda_cache_evicts_least_recently_used() {
+        let mut cache = LambdaCache::new(2);
+        cache.insert(key("a"), Arc::new(vec![]));
+        cache.insert(key("b"), Arc::new(vec![]));
+        assert!(cache.get(&key("a")).is_some());
+
+        cache.insert(key("c"), Arc::new(vec![]));
+        assert_eq!(cache.entries.len(), 2);
+        assert!(cache.get(&key("b")).is_none());
+        assert!(cache.get(&key("a")).is_some());
+        assert!(cache.get(&key("c")).is_some());
+
+        // Inserting an entry that's already there doesn't evict another.
+        cache.insert(key("c"), Arc::new(vec![]));
+        assert!(cache.get(&key("a")).is_some());
+    }
+}
@@ -0,0 +1,195 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+
+use template::Template;
+use value::ContextValue;
+use {Data, Result};
+
+/// Converts a value to `Data` directly, without going through `serde`. It's
+/// needed by `ContextValue`, and structs can derive it with the `macros`
+/// feature.
+pub trait ToData {
+    fn to_data(&self) -> Data;
+}
+
+impl ToData for Data {
+    fn to_data(&self) -> Data {
+        match *self {
+            Data::Null => Data::Null,
+            Data::String(ref s) => Data::String(s.clone()),
+            Data::Bool(b) => Data::Bool(b),
+            Data::Int(n) => Data::Int(n),
+            Data::Uint(n) => Data::Uint(n),
+            Data::Float(n) => Data::Float(n),
+            Data::Vec(ref v) => Data::Vec(v.iter().map(ToData::to_data).collect()),
+            Data::Map(ref m) => Data::Map(m.iter().map(|(k, v)| (k.clone(), v.to_data())).collect()),
+            // Closures can't be copied.
+            Data::Fun(_) | Data::Lambda(_) => Data::Null,
+        }
+    }
+}
+
+impl ToData for String {
+    fn to_data(&self) -> Data {
+        Data::String(self.clone())
+    }
+}
+
+impl ToData for str {
+    fn to_data(&self) -> Data {
+        Data::String(self.to_string())
+    }
+}
+
+impl ToData for bool {
+    fn to_data(&self) -> Data {
+        Data::Bool(*self)
+    }
+}
+
+impl ToData for () {
+    fn to_data(&self) -> Data {
+        Data::Null
+    }
+}
+
+impl ToData for char {
+    fn to_data(&self) -> Data {
+        Data::String(self.to_string())
+    }
+}
+
+macro_rules! to_data_number {
+    ($variant:ident, $as_ty:ty, $($ty:ty),*) => {
+        $(
+            impl ToData for $ty {
+                fn to_data(&self) -> Data {
+                    Data::$variant(*self as $as_ty)
+                }
+            }
+        )*
+    }
+}
+
+to_data_number!(Int, i64, i8, i16, i32, i64, isize);
+to_data_number!(Uint, u64, u8, u16, u32, u64, usize);
+to_data_number!(Float, f64, f64);
+
+impl ToData for f32 {
+    fn to_data(&self) -> Data {
+        // Like the `Encoder`, keep the shortest representation of the float.
+        Data::Float(self.to_string().parse().unwrap_or(*self as f64))
+    }
+}
+
+impl<'a, T: ToData + ?Sized> ToData for &'a T {
+    fn to_data(&self) -> Data {
+        (**self).to_data()
+    }
+}
+
+impl<T: ToData + ?Sized> ToData for Box<T> {
+    fn to_data(&self) -> Data {
+        (**self).to_data()
+    }
+}
+
+impl<T: ToData> ToData for Option<T> {
+    fn to_data(&self) -> Data {
+        match *self {
+            Some(ref value) => value.to_data(),
+            None => Data::Null,
+        }
+    }
+}
+
+impl<T: ToData> ToData for [T] {
+    fn to_data(&self) -> Data {
+        Data::Vec(self.iter().map(ToData::to_data).collect())
+    }
+}
+
+impl<T: ToData> ToData for Vec<T> {
+    fn to_data(&self) -> Data {
+        self[..].to_data()
+    }
+}
+
+impl<K: ToString, V: ToData> ToData for HashMap<K, V> {
+    fn to_data(&self) -> Data {
+        Data::Map(self.iter().map(|(k, v)| (k.to_string(), v.to_data())).collect())
+    }
+}
+
+impl<K: ToString, V: ToData> ToData for BTreeMap<K, V> {
+    fn to_data(&self) -> Data {
+        Data::Map(self.iter().map(|(k, v)| (k.to_string(), v.to_data())).collect())
+    }
+}
+
+/// A struct rendered with a template that was checked against its fields at
+/// build time. Use `#[derive(MustacheTemplate)]` with the `macros` feature to
+/// implement it. The struct is rendered through its `ContextValue` impl, which
+/// the derive doesn't write, so derive `ContextValue` and `ToData` too:
+///
+/// ```rust,ignore
+/// #[derive(MustacheTemplate, ContextValue, ToData)]
+/// #[template(path = "templates/user.mustache")]
+/// struct UserPage {
+///     name: String,
+///     admin: bool,
+/// }
+///
+/// let mut out = vec![];
+/// UserPage { name: "Bob".to_string(), admin: false }.render(&mut out).unwrap();
+/// ```
+///
+/// The derive fails to compile when the template uses a name that isn't a
+/// field of the struct. **Only the names rendered with the struct as the
+/// context are checked**: those outside of sections, and inside inverted
+/// sections and sections on `bool` fields. Inside `{{#friends}}..{{/friends}}`
+/// a name can be a field of each friend, so a typo there renders nothing at
+/// runtime, like it would without the derive.
+pub trait MustacheTemplate: ContextValue {
+    /// The template, compiled the first time it's used.
+    fn template() -> &'static Template;
+
+    /// Renders the template with the fields of `self`, without copying them
+    /// into `Data`.
+    fn render<W: Write>(&self, wr: &mut W) -> Result<()>
+        where Self: Sized
+    {
+        Self::template().render_value(wr, self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::ToData;
+    use {Data, Map};
+
+    #[test]
+    fn test_to_data_scalars() {
+        assert_eq!("a".to_data(), Data::String("a".to_string()));
+        assert_eq!(true.to_data(), Data::Bool(true));
+        assert_eq!(42u32.to_data(), Data::Uint(42));
+        assert_eq!((-1i8).to_data(), Data::Int(-1));
+        assert_eq!(0.1f32.to_data(), Data::Float(0.1));
+        assert_eq!(None::<String>.to_data(), Data::Null);
+    }
+
+    #[test]
+    fn test_to_data_collections() {
+        assert_eq!(vec![Some(1), None].to_data(),
+                   Data::Vec(vec![Data::Int(1), Data::Null]));
+
+        let mut map = HashMap::new();
+        map.insert("a", vec!["b"]);
+
+        let mut expected = Map::new();
+        expected.insert("a".to_string(), Data::Vec(vec![Data::String("b".to_string())]));
+        assert_eq!(map.to_data(), Data::Map(expected));
+    }
+}
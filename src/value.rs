@@ -0,0 +1,318 @@
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+use typed::ToData;
+use Data;
+
+/// A value templates can be rendered with as it is, with
+/// `Template::render_value`, instead of being copied into `Data` first.
+///
+/// Tags look names up with `get`, sections go through `items` or
+/// check `truthy`, and `{{name}}` tags write the value with `write_to`. Values
+/// are only copied with `to_data` to run them through filters and format
+/// specs, or when a lambda asks for them.
+///
+/// Values are `Sync`, so that renders, like the future of `render_async`, can
+/// move between threads.
+///
+/// It's implemented for `Data`, for the standard types `ToData` is, and for
+/// the values of serde_json, toml and serde_yaml with the `json`, `toml` and
+/// `yaml` features. Structs can derive it with the `macros` feature, next to
+/// `ToData`:
+///
+/// ```rust,ignore
+/// #[derive(ToData, ContextValue)]
+/// struct User {
+///     name: String,
+///     roles: Vec<String>,
+/// }
+///
+/// template.render_value(&mut out, &user)?;
+/// ```
+pub trait ContextValue: ToData + Sync {
+    /// The field `key` of a map or struct, or `None` if there isn't one.
+    fn get(&self, _key: &str) -> Option<&ContextValue> {
+        None
+    }
+
+    /// The items of a list, which sections are rendered for one after the
+    /// other, or `None` if the value isn't a list.
+    fn items<'a>(&'a self) -> Option<Box<Iterator<Item = &'a ContextValue> + Send + 'a>> {
+        None
+    }
+
+    /// Whether sections are rendered for the value, and inverted sections
+    /// aren't. This isn't used for lists, which are falsy when they're empty.
+    fn truthy(&self) -> bool;
+
+    /// Whether the value is a number, so that sections can treat zero like
+    /// `Context::zero_is_falsy` says.
+    fn is_number(&self) -> bool {
+        false
+    }
+
+    /// Writes the value like `{{name}}` does, before it's escaped.
+    fn write_to(&self, out: &mut String);
+
+    /// The value as `Data`, if it's already `Data`. Lambdas and the other
+    /// things only `Data` can hold are rendered through this.
+    fn as_data(&self) -> Option<&Data> {
+        None
+    }
+}
+
+impl ContextValue for Data {
+    fn get(&self, key: &str) -> Option<&ContextValue> {
+        match *self {
+            Data::Map(ref m) => m.get(key).map(|value| value as &ContextValue),
+            _ => None,
+        }
+    }
+
+    fn items<'a>(&'a self) -> Option<Box<Iterator<Item = &'a ContextValue> + Send + 'a>> {
+        match *self {
+            Data::Vec(ref v) => Some(Box::new(v.iter().map(|value| value as &ContextValue))),
+            _ => None,
+        }
+    }
+
+    fn truthy(&self) -> bool {
+        match *self {
+            Data::Null | Data::Bool(false) => false,
+            Data::String(ref s) => !s.is_empty(),
+            Data::Int(n) => n != 0,
+            Data::Uint(n) => n != 0,
+            Data::Float(n) => n != 0.0,
+            Data::Vec(ref v) => !v.is_empty(),
+            _ => true,
+        }
+    }
+
+    fn is_number(&self) -> bool {
+        match *self {
+            Data::Int(_) | Data::Uint(_) | Data::Float(_) => true,
+            _ => false,
+        }
+    }
+
+    fn write_to(&self, out: &mut String) {
+        match *self {
+            Data::String(ref s) => out.push_str(s),
+            Data::Int(n) => out.push_str(&n.to_string()),
+            Data::Uint(n) => out.push_str(&n.to_string()),
+            Data::Float(n) => out.push_str(&n.to_string()),
+            _ => {}
+        }
+    }
+
+    fn as_data(&self) -> Option<&Data> {
+        Some(self)
+    }
+}
+
+impl ContextValue for String {
+    fn truthy(&self) -> bool {
+        !self.is_empty()
+    }
+
+    fn write_to(&self, out: &mut String) {
+        out.push_str(self);
+    }
+}
+
+impl ContextValue for str {
+    fn truthy(&self) -> bool {
+        !self.is_empty()
+    }
+
+    fn write_to(&self, out: &mut String) {
+        out.push_str(self);
+    }
+}
+
+impl ContextValue for char {
+    fn truthy(&self) -> bool {
+        true
+    }
+
+    fn write_to(&self, out: &mut String) {
+        out.push(*self);
+    }
+}
+
+impl ContextValue for bool {
+    fn truthy(&self) -> bool {
+        *self
+    }
+
+    fn write_to(&self, out: &mut String) {
+        out.push_str(if *self { "true" } else { "false" });
+    }
+}
+
+impl ContextValue for () {
+    fn truthy(&self) -> bool {
+        false
+    }
+
+    fn write_to(&self, _out: &mut String) {}
+}
+
+macro_rules! context_value_number {
+    ($zero:expr, $($ty:ty),*) => {
+        $(
+            impl ContextValue for $ty {
+                fn truthy(&self) -> bool {
+                    *self != $zero
+                }
+
+                fn is_number(&self) -> bool {
+                    true
+                }
+
+                fn write_to(&self, out: &mut String) {
+                    // Formatted like the `Data` the number would become.
+                    self.to_data().write_to(out);
+                }
+            }
+        )*
+    }
+}
+
+context_value_number!(0, i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+context_value_number!(0.0, f32, f64);
+
+impl<'b, T: ContextValue + ?Sized> ContextValue for &'b T {
+    fn get(&self, key: &str) -> Option<&ContextValue> {
+        (**self).get(key)
+    }
+
+    fn items<'a>(&'a self) -> Option<Box<Iterator<Item = &'a ContextValue> + Send + 'a>> {
+        (**self).items()
+    }
+
+    fn truthy(&self) -> bool {
+        (**self).truthy()
+    }
+
+    fn is_number(&self) -> bool {
+        (**self).is_number()
+    }
+
+    fn write_to(&self, out: &mut String) {
+        (**self).write_to(out)
+    }
+
+    fn as_data(&self) -> Option<&Data> {
+        (**self).as_data()
+    }
+}
+
+impl<T: ContextValue + ?Sized> ContextValue for Box<T> {
+    fn get(&self, key: &str) -> Option<&ContextValue> {
+        (**self).get(key)
+    }
+
+    fn items<'a>(&'a self) -> Option<Box<Iterator<Item = &'a ContextValue> + Send + 'a>> {
+        (**self).items()
+    }
+
+    fn truthy(&self) -> bool {
+        (**self).truthy()
+    }
+
+    fn is_number(&self) -> bool {
+        (**self).is_number()
+    }
+
+    fn write_to(&self, out: &mut String) {
+        (**self).write_to(out)
+    }
+
+    fn as_data(&self) -> Option<&Data> {
+        (**self).as_data()
+    }
+}
+
+impl<T: ContextValue> ContextValue for Option<T> {
+    fn get(&self, key: &str) -> Option<&ContextValue> {
+        self.as_ref().and_then(|value| value.get(key))
+    }
+
+    fn items<'a>(&'a self) -> Option<Box<Iterator<Item = &'a ContextValue> + Send + 'a>> {
+        self.as_ref().and_then(|value| value.items())
+    }
+
+    fn truthy(&self) -> bool {
+        self.as_ref().map_or(false, |value| value.truthy())
+    }
+
+    fn is_number(&self) -> bool {
+        self.as_ref().map_or(false, |value| value.is_number())
+    }
+
+    fn write_to(&self, out: &mut String) {
+        if let Some(ref value) = *self {
+            value.write_to(out);
+        }
+    }
+
+    fn as_data(&self) -> Option<&Data> {
+        self.as_ref().and_then(|value| value.as_data())
+    }
+}
+
+impl<T: ContextValue> ContextValue for [T] {
+    fn items<'a>(&'a self) -> Option<Box<Iterator<Item = &'a ContextValue> + Send + 'a>> {
+        Some(Box::new(self.iter().map(|value| value as &ContextValue)))
+    }
+
+    fn truthy(&self) -> bool {
+        !self.is_empty()
+    }
+
+    fn write_to(&self, _out: &mut String) {}
+}
+
+impl<T: ContextValue> ContextValue for Vec<T> {
+    fn items<'a>(&'a self) -> Option<Box<Iterator<Item = &'a ContextValue> + Send + 'a>> {
+        self[..].items()
+    }
+
+    fn truthy(&self) -> bool {
+        !self.is_empty()
+    }
+
+    fn write_to(&self, _out: &mut String) {}
+}
+
+impl<K, V> ContextValue for HashMap<K, V>
+where K: Borrow<str> + Hash + Eq + ToString + Sync,
+      V: ContextValue,
+{
+    fn get(&self, key: &str) -> Option<&ContextValue> {
+        HashMap::get(self, key).map(|value| value as &ContextValue)
+    }
+
+    fn truthy(&self) -> bool {
+        true
+    }
+
+    fn write_to(&self, _out: &mut String) {}
+}
+
+impl<K, V> ContextValue for BTreeMap<K, V>
+where K: Borrow<str> + Ord + ToString + Sync,
+      V: ContextValue,
+{
+    fn get(&self, key: &str) -> Option<&ContextValue> {
+        BTreeMap::get(self, key).map(|value| value as &ContextValue)
+    }
+
+    fn truthy(&self) -> bool {
+        true
+    }
+
+    fn write_to(&self, _out: &mut String) {}
+}
@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::task::{Context as TaskContext, Poll};
+
+use mustache::{Context, Error, MapBuilder};
+use tokio::io::{self, AsyncReadExt, AsyncWrite};
+
+#[tokio::test]
+async fn test_render_async() {
+    let template = mustache::compile_str("{{#names}}Hello {{.}}!\n{{/names}}").unwrap();
+    let mut data = HashMap::new();
+    data.insert("names", vec!["Harry", "Samantha"]);
+
+    // The duplex buffer is smaller than the output, so the writer has to wait
+    // for the reader.
+    let (mut client, mut server) = io::duplex(4);
+
+    let render = async {
+        let result = template.render_async(&mut client, &data).await;
+        drop(client);
+        result
+    };
+    let read = async {
+        let mut out = String::new();
+        server.read_to_string(&mut out).await.map(|_| out)
+    };
+
+    let (rendered, out) = tokio::join!(render, read);
+    rendered.unwrap();
+    assert_eq!(out.unwrap(), "Hello Harry!\nHello Samantha!\n");
+}
+
+#[tokio::test]
+async fn test_render_data_async() {
+    let template = mustache::compile_str("{{name}}").unwrap();
+    let data = MapBuilder::new().insert_str("name", "<Jane>").build();
+
+    let (mut client, mut server) = io::duplex(64);
+    template.render_data_async(&mut client, &data).await.unwrap();
+    drop(client);
+
+    let mut out = String::new();
+    server.read_to_string(&mut out).await.unwrap();
+    assert_eq!(out, "&lt;Jane&gt;");
+}
+
+#[tokio::test]
+async fn test_render_async_error() {
+    let mut ctx = Context::new(PathBuf::from("."));
+    ctx.strict = true;
+    let template = ctx.compile("{{missing}}".chars()).unwrap();
+    let data = MapBuilder::new().build();
+
+    let (mut client, _server) = io::duplex(64);
+    match template.render_data_async(&mut client, &data).await {
+        Err(Error::MissingVariable(ref name, _)) => assert_eq!(name, "missing"),
+        result => panic!("expected a missing variable, got {:?}", result),
+    }
+}
+
+#[tokio::test]
+async fn test_render_async_closed() {
+    let template = mustache::compile_str("{{name}}").unwrap();
+    let data = MapBuilder::new().insert_str("name", "Jane").build();
+
+    let (mut client, server) = io::duplex(64);
+    drop(server);
+    match template.render_data_async(&mut client, &data).await {
+        Err(Error::Io(_)) => {}
+        result => panic!("expected an I/O error, got {:?}", result),
+    }
+}
+
+/// Takes at most `limit` bytes per write, and is only ready every other time
+/// it's polled.
+struct Trickle {
+    out: Vec<u8>,
+    limit: usize,
+    ready: bool,
+    writes: usize,
+}
+
+impl AsyncWrite for Trickle {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut TaskContext, buf: &[u8])
+                  -> Poll<io::Result<usize>> {
+        self.ready = !self.ready;
+        if !self.ready {
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+
+        let n = buf.len().min(self.limit);
+        self.out.extend_from_slice(&buf[..n]);
+        self.writes += 1;
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut TaskContext) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut TaskContext) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[tokio::test]
+async fn test_render_async_partial_writes() {
+    let template = mustache::compile_str("{{#items}}<li>{{.}}</li>\n{{/items}}").unwrap();
+    let items: Vec<String> = (0..2000).map(|i| i.to_string()).collect();
+    let mut data = HashMap::new();
+    data.insert("items", items);
+
+    let mut wr = Trickle { out: vec![], limit: 7, ready: false, writes: 0 };
+    template.render_async(&mut wr, &data).await.unwrap();
+
+    let mut expected = vec![];
+    template.render_value(&mut expected, &data).unwrap();
+    assert!(expected.len() > 16 * 1024);
+    assert_eq!(wr.out, expected);
+    assert!(wr.writes >= (expected.len() + 6) / 7);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_render_async_spawned() {
+    let template = mustache::compile_str("{{#names}}<{{.}}>{{/names}}").unwrap();
+    let mut data = HashMap::new();
+    data.insert("names", vec!["Harry", "Samantha"]);
+
+    let (mut client, mut server) = io::duplex(4);
+
+    let render = tokio::spawn(async move {
+        let result = template.render_async(&mut client, &data).await;
+        drop(client);
+        result
+    });
+
+    let mut out = String::new();
+    server.read_to_string(&mut out).await.unwrap();
+    render.await.unwrap().unwrap();
+    assert_eq!(out, "<Harry><Samantha>");
+}
@@ -1,26 +1,24 @@
-use std::collections::HashMap;
-
-use mustache::Data;
+use mustache::{Data, Map};
 use mustache::{MapBuilder, VecBuilder};
 
 #[test]
 fn test_empty_builders() {
-    assert_eq!(MapBuilder::new().build(), Data::Map(HashMap::new()));
+    assert_eq!(MapBuilder::new().build(), Data::Map(Map::new()));
 
     assert_eq!(VecBuilder::new().build(), Data::Vec(Vec::new()));
 }
 
 #[test]
 fn test_builders() {
-    let mut pride_and_prejudice = HashMap::new();
+    let mut pride_and_prejudice = Map::new();
     pride_and_prejudice.insert("title".to_string(),
                                Data::String("Pride and Prejudice".to_string()));
-    pride_and_prejudice.insert("publish_date".to_string(), Data::String("1813".to_string()));
+    pride_and_prejudice.insert("publish_date".to_string(), Data::Uint(1813));
 
-    let mut m = HashMap::new();
+    let mut m = Map::new();
     m.insert("first_name".to_string(), Data::String("Jane".to_string()));
     m.insert("last_name".to_string(), Data::String("Austen".to_string()));
-    m.insert("age".to_string(), Data::String("41".to_string()));
+    m.insert("age".to_string(), Data::Uint(41));
     m.insert("died".to_string(), Data::Bool(true));
     m.insert("works".to_string(),
              Data::Vec(vec![Data::String("Sense and Sensibility".to_string()),
@@ -41,6 +39,19 @@ fn test_builders() {
                Data::Map(m));
 }
 
+#[test]
+fn test_map_builder_keeps_order() {
+    let data = MapBuilder::new()
+        .insert_str("zebra", "z")
+        .insert_str("apple", "a")
+        .insert_bool("mango", true)
+        .build();
+
+    assert_let!(Data::Map(m) = data => {
+        assert_eq!(m.keys().collect::<Vec<_>>(), ["zebra", "apple", "mango"]);
+    });
+}
+
 #[test]
 fn test_map_fn_builder() {
     // We can't directly compare closures, so just make sure we thread
@@ -56,7 +67,7 @@ fn test_map_fn_builder() {
 
     assert_let!(Data::Map(m) = data => {
         assert_let!(Some(&Data::Fun(ref f)) = m.get("count") => {
-            let f = &mut *f.borrow_mut();
+            let f = &mut *f.lock().unwrap();
             assert_eq!((*f)("count: ".to_string()), "count: 1".to_string());
             assert_eq!((*f)("count: ".to_string()), "count: 2".to_string());
             assert_eq!((*f)("count: ".to_string()), "count: 3".to_string());
@@ -81,7 +92,7 @@ fn test_vec_fn_builder() {
         let mut iter = vs.iter();
 
         assert_let!(Some(&Data::Fun(ref f)) = iter.next() => {
-            let f = &mut *f.borrow_mut();
+            let f = &mut *f.lock().unwrap();
             assert_eq!((*f)("count: ".to_string()), "count: 1".to_string());
             assert_eq!((*f)("count: ".to_string()), "count: 2".to_string());
             assert_eq!((*f)("count: ".to_string()), "count: 3".to_string());
@@ -0,0 +1,139 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use tempdir::TempDir;
+
+use mustache::{Context, Error, MapBuilder, Registry};
+
+#[derive(Serialize)]
+struct Page {
+    title: String,
+    items: Vec<String>,
+}
+
+fn write_template(dir: &Path, name: &str, src: &str) {
+    let path = dir.join(name.to_string() + ".mustache");
+    fs::create_dir_all(path.parent().unwrap()).expect("Failed to create template dir");
+    File::create(&path)
+        .and_then(|mut f| f.write_all(src.as_bytes()))
+        .expect("Failed to generate template");
+}
+
+fn render(registry: &Registry, name: &str) -> String {
+    let page = Page {
+        title: "Home".to_string(),
+        items: vec!["a".to_string(), "b".to_string()],
+    };
+
+    let mut bytes = vec![];
+    registry.render(name, &page, &mut bytes).expect("Failed to render");
+    String::from_utf8(bytes).expect("Failed to encode String")
+}
+
+#[test]
+fn test_register_str() {
+    let mut registry = Registry::new();
+    registry.register_str("item", "<li>{{.}}</li>");
+    registry.register_str("page/home", "<h1>{{title}}</h1>{{#items}}{{> item}}{{/items}}");
+
+    assert_eq!(render(&registry, "page/home"), "<h1>Home</h1><li>a</li><li>b</li>");
+}
+
+#[test]
+fn test_register_dir() {
+    let tmpdir = TempDir::new("").expect("Failed to make tempdir");
+    write_template(tmpdir.path(), "layout/header", "<h1>{{title}}</h1>");
+    write_template(tmpdir.path(), "page/home", "{{> layout/header}}{{#items}}{{.}}{{/items}}");
+    File::create(tmpdir.path().join("page/notes.txt")).expect("Failed to create file");
+
+    let mut registry = Registry::new();
+    registry.register_dir(tmpdir.path()).expect("Failed to register templates");
+
+    assert_eq!(render(&registry, "page/home"), "<h1>Home</h1>ab");
+    assert_eq!(render(&registry, "layout/header"), "<h1>Home</h1>");
+    assert_let!(Err(Error::UnknownTemplate(ref name)) = registry.get("page/notes") => {
+        assert_eq!(name, "page/notes");
+    });
+}
+
+#[test]
+fn test_register_replaces_templates() {
+    let mut registry = Registry::new();
+    registry.register_str("page", "[{{> title}}]");
+    assert_eq!(render(&registry, "page"), "[]");
+
+    registry.register_str("title", "{{title}}");
+    assert_eq!(render(&registry, "page"), "[Home]");
+
+    registry.register_str("title", "{{title}}!");
+    assert_eq!(render(&registry, "page"), "[Home!]");
+}
+
+#[test]
+fn test_templates_are_compiled_once() {
+    let mut registry = Registry::new();
+    registry.register_str("page", "{{title}}");
+
+    let first = registry.get("page").expect("Failed to compile");
+    let second = registry.get("page").expect("Failed to compile");
+    assert!(Arc::ptr_eq(&first, &second));
+}
+
+#[test]
+fn test_templates_compiled_as_partials() {
+    let mut registry = Registry::default();
+    registry.register_str("item", "<li>{{.}}</li>");
+    registry.register_str("list", "{{#items}}{{> item}}{{/items}}");
+
+    // `item` was compiled as a partial of `list`, so it's not compiled again.
+    assert_eq!(render(&registry, "list"), "<li>a</li><li>b</li>");
+    assert_eq!(render(&registry, "item"), "<li></li>");
+}
+
+#[test]
+fn test_unknown_template() {
+    let registry = Registry::new();
+    let err = registry.render_data("nope", &MapBuilder::new().build(), &mut vec![])
+        .err()
+        .expect("Expected an error");
+    assert_let!(Error::UnknownTemplate(ref name) = err => {
+        assert_eq!(name, "nope");
+    });
+    assert_eq!(err.to_string(), "unknown template `nope`");
+}
+
+#[test]
+fn test_errors_point_at_registered_files() {
+    let tmpdir = TempDir::new("").expect("Failed to make tempdir");
+    write_template(tmpdir.path(), "page", "{{> broken}}");
+    write_template(tmpdir.path(), "broken", "\n{{#title}}");
+
+    let mut ctx = Context::new(tmpdir.path().to_path_buf());
+    ctx.strict = true;
+    let mut registry = Registry::with_context(ctx);
+    registry.register_dir(tmpdir.path()).expect("Failed to register templates");
+
+    assert_let!(Err(Error::Parser(ref err)) = registry.get("page") => {
+        assert_eq!(err.file(), Some(&*tmpdir.path().join("broken.mustache")));
+        assert_eq!(err.line(), 2);
+    });
+}
+
+#[test]
+fn test_render_from_threads() {
+    let mut registry = Registry::new();
+    registry.register_str("item", "{{.}}");
+    registry.register_str("page", "{{title}}:{{#items}}{{> item}}{{/items}}");
+    let registry = Arc::new(registry);
+
+    let threads: Vec<_> = (0..4).map(|_| {
+        let registry = registry.clone();
+        thread::spawn(move || render(&registry, "page"))
+    }).collect();
+
+    for thread in threads {
+        assert_eq!(thread.join().expect("Failed to render"), "Home:ab");
+    }
+}
@@ -1,13 +1,13 @@
-use std::cell::RefCell;
 use tempdir::TempDir;
 use std::fmt::Debug;
 use std::fs::File;
 use std::io::Write;
 use std::path::{PathBuf, Path};
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 
-use mustache::{self, Data, Error, to_data};
-use mustache::{Context, Template};
+use mustache::{self, Data, Error, Map, to_data};
+use mustache::{Context, ContextValue, Template};
 
 use serde::Serialize;
 use serde_json;
@@ -57,6 +57,19 @@ where T: Serialize
     Ok(String::from_utf8(bytes).expect("Failed to encode String"))
 }
 
+fn render_with(ctx: &Context, template: &str, data: &ContextValue) -> Result<String, Error> {
+    let template = try!(ctx.compile(template.chars()));
+
+    let mut bytes = vec![];
+    try!(template.render_value(&mut bytes, data));
+
+    Ok(String::from_utf8(bytes).expect("Failed to encode String"))
+}
+
+fn render_value(template: &str, data: &ContextValue) -> Result<String, Error> {
+    render_with(&Context::new(PathBuf::from(".")), template, data)
+}
+
 #[test]
 fn test_render_texts() {
     let ctx = Planet {
@@ -81,6 +94,39 @@ fn test_render_etags() {
     assert_eq!(assert_render("hello {{name}}", &ctx), "hello world");
 }
 
+struct Shout;
+
+impl mustache::Escaper for Shout {
+    fn escape(&self, s: &str) -> String {
+        s.to_uppercase()
+    }
+}
+
+#[test]
+fn test_render_etags_with_escaper() {
+    let ctx = Planet {
+        name: "<world>".to_string(),
+        info: None,
+    };
+
+    let mut template = compile_str("hello {{name}} {{{name}}}");
+    let mut bytes = vec![];
+    template.render(&mut bytes, &ctx).expect("Failed to render");
+    assert_eq!(String::from_utf8(bytes).unwrap(), "hello &lt;world&gt; <world>");
+
+    template.set_escaper(Shout);
+    let mut bytes = vec![];
+    template.render(&mut bytes, &ctx).expect("Failed to render");
+    assert_eq!(String::from_utf8(bytes).unwrap(), "hello <WORLD> <world>");
+
+    let mut context = Context::new(PathBuf::from("."));
+    context.escaper = Arc::new(mustache::escape::Url);
+    let template = context.compile("/planets/{{name}}".chars()).expect("Failed to compile");
+    let mut bytes = vec![];
+    template.render(&mut bytes, &ctx).expect("Failed to render");
+    assert_eq!(String::from_utf8(bytes).unwrap(), "/planets/%3Cworld%3E");
+}
+
 #[test]
 fn test_render_utags() {
     let ctx = Planet {
@@ -249,6 +295,1172 @@ mod context_search {
     }
 }
 
+mod numbers {
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    use mustache::{self, Context, Data, MapBuilder};
+
+    use super::render_with;
+
+    #[test]
+    fn test_encode_numbers() {
+        #[derive(Serialize)]
+        struct Invoice {
+            count: u8,
+            balance: i32,
+            price: f32,
+        }
+
+        let data = mustache::to_data(Invoice { count: 3, balance: -20, price: 0.1 }).unwrap();
+
+        assert_let!(Data::Map(ref map) = data => {
+            assert_eq!(map["count"], Data::Uint(3));
+            assert_eq!(map["balance"], Data::Int(-20));
+            assert_eq!(map["price"], Data::Float(0.1));
+        });
+    }
+
+    #[test]
+    fn test_encode_number_keys() {
+        use std::collections::BTreeMap;
+
+        let mut ids = BTreeMap::new();
+        ids.insert(-1i64, "none");
+        ids.insert(7i64, "seven");
+        let mut flags = BTreeMap::new();
+        flags.insert(true, "on");
+
+        let data = mustache::to_data((ids, flags)).unwrap();
+        assert_let!(Data::Vec(ref vec) = data => {
+            assert_let!(Data::Map(ref map) = vec[0] => {
+                assert_eq!(map["-1"], Data::String("none".to_string()));
+                assert_eq!(map["7"], Data::String("seven".to_string()));
+            });
+            assert_let!(Data::Map(ref map) = vec[1] => {
+                assert_eq!(map["true"], Data::String("on".to_string()));
+            });
+        });
+
+        let template = mustache::compile_str("{{#ids}}{{7}}{{/ids}}").unwrap();
+        let mut ids = HashMap::new();
+        ids.insert(7u32, "seven");
+        let mut data = HashMap::new();
+        data.insert("ids", ids);
+        assert_eq!(template.render_to_string(&data).unwrap(), "seven");
+    }
+
+    #[test]
+    fn test_interpolate_numbers() {
+        let ctx = Context::new(PathBuf::from("."));
+        let data = MapBuilder::new()
+            .insert_int("int", -42)
+            .insert_uint("uint", 7u32)
+            .insert_float("float", 1.5)
+            .insert_float("whole", 2.0)
+            .build();
+
+        assert_eq!(render_with(&ctx, "{{int}} {{uint}} {{{float}}} {{&whole}}", &data).unwrap(),
+                   "-42 7 1.5 2");
+        assert_eq!(render_with(&ctx, "{{#uint}}{{.}}{{/uint}}", &data).unwrap(), "7");
+    }
+
+    #[test]
+    fn test_format_specs() {
+        let ctx = Context::new(PathBuf::from("."));
+        let data = MapBuilder::new()
+            .insert_float("price", 1234.5)
+            .insert_uint("count", 42u32)
+            .insert_int("total", 1234567)
+            .insert_str("name", "<Widget>")
+            .build();
+
+        assert_eq!(render_with(&ctx, "{{price:.2}}|{{count:>6}}|{{total:,}}", &data).unwrap(),
+                   "1234.50|    42|1,234,567");
+        assert_eq!(render_with(&ctx, "{{price:*^12,.2}}|{{count:+06}}", &data).unwrap(),
+                   "**1,234.50**|+00042");
+        assert_eq!(render_with(&ctx, "[{{name:<10}}][{{{name:.4}}}]", &data).unwrap(),
+                   "[&lt;Widget&gt;  ][<Wid]");
+    }
+
+    #[test]
+    fn test_invalid_format_spec() {
+        let err = mustache::compile_str("total: {{price:.x}}").unwrap_err();
+        assert_eq!(err.to_string(), "1:8: found an invalid format spec: \".x\"");
+    }
+
+    #[test]
+    fn test_zero_is_falsy() {
+        let mut ctx = Context::new(PathBuf::from("."));
+        ctx.zero_is_falsy = true;
+        let data = MapBuilder::new()
+            .insert_int("int", 0)
+            .insert_uint("uint", 0u32)
+            .insert_float("float", 0.0)
+            .build();
+
+        let sections = "{{#int}}int{{/int}}{{#uint}}uint{{/uint}}{{#float}}float{{/float}}";
+        let inverted = "{{^int}}int{{/int}}{{^uint}}uint{{/uint}}{{^float}}float{{/float}}";
+        assert_eq!(render_with(&ctx, sections, &data).unwrap(), "");
+        assert_eq!(render_with(&ctx, inverted, &data).unwrap(), "intuintfloat");
+    }
+
+    #[test]
+    fn test_zero_is_truthy() {
+        let ctx = Context::new(PathBuf::from("."));
+        let data = MapBuilder::new().insert_int("count", 0).build();
+
+        assert_eq!(render_with(&ctx, "{{#count}}count: {{.}}{{/count}}", &data).unwrap(), "count: 0");
+        assert_eq!(render_with(&ctx, "{{^count}}none{{/count}}", &data).unwrap(), "");
+    }
+}
+
+mod filters {
+    use std::path::PathBuf;
+
+    use mustache::{Arg, Context, Data, Error, MapBuilder, ToData};
+
+    use super::{render_data, render_with};
+
+    fn context() -> Context {
+        let mut ctx = Context::new(PathBuf::from("."));
+        ctx.register_filter("upper", |value: &Data, _: &[Arg]| {
+            match *value {
+                Data::String(ref s) => Ok(Data::String(s.to_uppercase())),
+                _ => Err(Error::Filter("upper expects a string".to_string())),
+            }
+        });
+        ctx.register_filter("truncate", |value: &Data, args: &[Arg]| {
+            let len = args.get(0).and_then(|arg| arg.as_int()).unwrap_or(3) as usize;
+            let end = args.get(1).map(|arg| arg.to_string()).unwrap_or_default();
+            match *value {
+                Data::String(ref s) if s.chars().count() > len => {
+                    Ok(Data::String(s.chars().take(len).collect::<String>() + &end))
+                }
+                _ => Ok(value.to_data()),
+            }
+        });
+        ctx.register_filter("default", |value: &Data, args: &[Arg]| {
+            match *value {
+                Data::Null => Ok(Data::String(args.get(0).map(|arg| arg.to_string()).unwrap_or_default())),
+                _ => Ok(value.to_data()),
+            }
+        });
+        ctx.register_filter("double", |value: &Data, _: &[Arg]| {
+            match *value {
+                Data::Int(n) => Ok(Data::Int(n * 2)),
+                _ => Err(Error::Filter("double expects an integer".to_string())),
+            }
+        });
+        ctx
+    }
+
+    #[test]
+    fn test_filter_pipeline() {
+        let data = MapBuilder::new().insert_str("name", "<Bobby Tables>").insert_int("n", 21).build();
+
+        let ctx = context();
+        assert_eq!(render_with(&ctx, "{{ name | upper | truncate 6 \"...\" }}", &data).unwrap(),
+                   "&lt;BOBBY...");
+        assert_eq!(render_with(&ctx, "{{{ name | truncate }}}|{{& name|upper}}", &data).unwrap(),
+                   "<Bo|<BOBBY TABLES>");
+        assert_eq!(render_with(&ctx, "{{n | double:>5}}", &data).unwrap(), "   42");
+    }
+
+    #[test]
+    fn test_filter_missing_value() {
+        let data = MapBuilder::new().build();
+        let ctx = context();
+        assert_eq!(render_with(&ctx, "{{name | default \"Anonymous coward\"}}", &data).unwrap(),
+                   "Anonymous coward");
+    }
+
+    #[test]
+    fn test_unknown_filter() {
+        let data = MapBuilder::new().insert_str("name", "Bob").build();
+        let err = render_with(&context(), "hi {{name | shout}}", &data).unwrap_err();
+        assert_eq!(err.to_string(), "1:4: unknown filter `shout`");
+    }
+
+    #[test]
+    fn test_filter_error() {
+        let data = MapBuilder::new().insert_bool("name", true).build();
+        assert_let!(Err(Error::Filter(msg)) = render_with(&context(), "{{name | upper}}", &data) => {
+            assert_eq!(msg, "upper expects a string");
+        });
+    }
+
+    #[test]
+    fn test_pipelines_off() {
+        let mut ctx = context();
+        ctx.pipelines = false;
+        let data = MapBuilder::new()
+            .insert_str("a|upper", "<piped>")
+            .insert_str("time:zone", "UTC")
+            .build();
+
+        assert_eq!(render_with(&ctx, "{{a|upper}} {{{time:zone}}}", &data).unwrap(), "&lt;piped&gt; UTC");
+        assert!(render_with(&context(), "{{a|upper}}", &data).is_err());
+    }
+
+    #[test]
+    fn test_filter_results() {
+        let mut ctx = context();
+        ctx.register_filter("split", |value: &Data, _: &[Arg]| {
+            match *value {
+                Data::String(ref s) => Ok(Data::Vec(s.split(',').map(|s| Data::String(s.to_string())).collect())),
+                _ => Ok(Data::Null),
+            }
+        });
+        ctx.register_filter("empty", |value: &Data, _: &[Arg]| {
+            match *value {
+                Data::Vec(ref items) => Ok(Data::Bool(items.is_empty())),
+                _ => Ok(Data::Null),
+            }
+        });
+
+        let data = MapBuilder::new().insert_str("tags", "a,b").build();
+        assert_eq!(render_with(&ctx, "{{tags | split | empty}}", &data).unwrap(), "false");
+        assert_let!(Err(Error::Filter(msg)) = render_with(&ctx, "{{tags | split}}", &data) => {
+            assert_eq!(msg, "`split` returned a list, which can't be interpolated");
+        });
+    }
+
+    #[test]
+    fn test_template_register_filter() {
+        let mut template = mustache::compile_str("{{name | shout}}").unwrap();
+        template.register_filter("shout", |value: &Data, _: &[Arg]| {
+            match *value {
+                Data::String(ref s) => Ok(Data::String(s.to_uppercase() + "!")),
+                _ => Ok(Data::Null),
+            }
+        });
+
+        let data = MapBuilder::new().insert_str("name", "hey").build();
+        assert_eq!(render_data(&template, &data), "HEY!");
+    }
+}
+
+mod lambdas {
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    use mustache::{Context, ContextValue, Data, Error, MapBuilder};
+
+    use super::{render_value, render_with};
+
+    #[test]
+    fn test_lambda_renders_section() {
+        let data = MapBuilder::new()
+            .insert_str("name", "<Jane>")
+            .insert_lambda("bold", |ctx| {
+                let text = ctx.text().to_string();
+                ctx.render(&text).map(|text| format!("<b>{}</b>", text))
+            })
+            .build();
+
+        assert_eq!(render_value("{{#bold}}Hi {{name}}.{{/bold}}", &data).unwrap(),
+                   "<b>Hi &lt;Jane&gt;.</b>");
+        assert_eq!(render_value("{{=<% %>=}}<%#bold%>Hi <%name%>.<%/bold%>", &data).unwrap(),
+                   "<b>Hi &lt;Jane&gt;.</b>");
+    }
+
+    #[test]
+    fn test_lambda_delimiters() {
+        let data = MapBuilder::new()
+            .insert_lambda("tags", |ctx| -> Result<String, Error> {
+                let (otag, ctag) = ctx.delimiters();
+                Ok(format!("{} {}", otag, ctag))
+            })
+            .build();
+
+        assert_eq!(render_value("{{=| |=}}|#tags||/tags|", &data).unwrap(), "| |");
+    }
+
+    #[test]
+    fn test_lambda_interpolation_is_escaped() {
+        let data = MapBuilder::new()
+            .insert_str("name", "Jane")
+            .insert_lambda("greeting", |ctx| -> Result<String, Error> {
+                let name = match ctx.lookup("name").and_then(ContextValue::as_data) {
+                    Some(&Data::String(ref name)) => name.clone(),
+                    _ => "nobody".to_string(),
+                };
+                Ok(format!("<{}>", name))
+            })
+            .build();
+
+        assert_eq!(render_value("{{greeting}}|{{{greeting}}}", &data).unwrap(), "&lt;Jane&gt;|<Jane>");
+    }
+
+    #[test]
+    fn test_lambda_lookup() {
+        let data = MapBuilder::new()
+            .insert_map("user", |b| b.insert_str("name", "Jane"))
+            .insert_lambda("name", |ctx| -> Result<String, Error> {
+                Ok(match ctx.lookup("user.name").and_then(ContextValue::as_data) {
+                    Some(&Data::String(ref name)) => name.clone(),
+                    _ => String::new(),
+                })
+            })
+            .build();
+
+        assert_eq!(render_value("{{name}}", &data).unwrap(), "Jane");
+    }
+
+    #[test]
+    fn test_lambda_error() {
+        let data = MapBuilder::new()
+            .insert_lambda("fail", |_| Err("out of cheese"))
+            .build();
+
+        let err = render_value("hi\n{{#fail}}x{{/fail}}", &data).unwrap_err();
+        assert_let!(Error::Lambda(ref err, ref location) = err => {
+            assert_eq!(err.to_string(), "out of cheese");
+            assert_eq!((location.span.start.line, location.span.start.col), (2, 1));
+        });
+        assert_eq!(err.to_string(), "2:1: lambda failed: out of cheese");
+    }
+
+    #[test]
+    fn test_fun_output_in_long_section() {
+        let data = MapBuilder::new()
+            .insert_vec("items", |mut b| {
+                for i in 0..1000 {
+                    b = b.push_map(move |b| b.insert_int("n", i % 3));
+                }
+                b
+            })
+            .insert_fn("item", |text| format!("[{}]", text))
+            .build();
+
+        let output = render_value("{{#items}}{{#item}}{{n}}{{/item}}{{/items}}", &data).unwrap();
+        assert_eq!(output, "[0][1][2]".repeat(333) + "[0]");
+    }
+
+    #[test]
+    fn test_lambda_output_uses_partials() {
+        let mut partials = HashMap::new();
+        partials.insert("name".to_string(), "{{name}}".to_string());
+        let mut ctx = Context::new(PathBuf::from("."));
+        ctx.partial_loader = Some(Arc::new(partials));
+
+        let data = MapBuilder::new()
+            .insert_str("name", "Jane")
+            .insert_fn("wrap", |text| format!("<%> name%>:{}", text))
+            .build();
+
+        assert_eq!(render_with(&ctx, "{{=<% %>=}}<%#wrap%>x<%/wrap%>", &data).unwrap(), "Jane:x");
+    }
+
+    #[test]
+    fn test_lambda_render_error() {
+        let data = MapBuilder::new()
+            .insert_lambda("broken", |ctx| ctx.render("{{#unclosed}}"))
+            .build();
+
+        assert_let!(Err(Error::Parser(_)) = render_value("{{broken}}", &data) => {});
+    }
+}
+
+mod threads {
+    use std::sync::Arc;
+    use std::thread;
+
+    use mustache::{self, Data, Error, MapBuilder, Template};
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_send_sync() {
+        assert_send_sync::<Data>();
+        assert_send_sync::<Template>();
+    }
+
+    #[test]
+    fn test_render_from_threads() {
+        let template = Arc::new(mustache::compile_str("{{greeting}} {{#bold}}{{name}} #{{n}}{{/bold}}")
+            .expect("Failed to compile"));
+
+        let data = Arc::new(MapBuilder::new()
+            .insert_str("name", "Jane")
+            .insert_int("n", 7)
+            .insert_lambda("bold", |ctx| {
+                let text = ctx.text().to_string();
+                ctx.render(&text).map(|text| format!("<b>{}</b>", text))
+            })
+            .insert_fn("greeting", |_| "hi".to_string())
+            .build());
+
+        let threads: Vec<_> = (0..8).map(|_| {
+            let template = template.clone();
+            let data = data.clone();
+            thread::spawn(move || -> Result<Vec<String>, Error> {
+                let mut outputs = vec![];
+                for _ in 0..20 {
+                    let mut bytes = vec![];
+                    try!(template.render_data(&mut bytes, &data));
+                    outputs.push(String::from_utf8(bytes).expect("Failed to encode String"));
+                }
+                Ok(outputs)
+            })
+        }).collect();
+
+        for thread in threads {
+            for output in thread.join().expect("Thread panicked").expect("Failed to render") {
+                assert_eq!(output, "hi <b>Jane #7</b>");
+            }
+        }
+    }
+}
+
+mod limits {
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    use mustache::{Context, Data, Error, Limit, Limits, MapBuilder, DEFAULT_MAX_DEPTH};
+
+    use super::render_with;
+
+    fn context(limits: Limits) -> Context {
+        let mut partials = HashMap::new();
+        partials.insert("loop".to_string(), "x{{> loop}}".to_string());
+        partials.insert("item".to_string(), "{{#items}}{{> item}}{{/items}}".to_string());
+
+        let mut ctx = Context::new(PathBuf::from("."));
+        ctx.partial_loader = Some(Arc::new(partials));
+        ctx.limits = limits;
+        ctx
+    }
+
+    fn items(n: usize) -> Data {
+        MapBuilder::new()
+            .insert_vec("items", |mut b| {
+                for i in 0..n {
+                    b = b.push(&i).expect("Failed to encode item");
+                }
+                b
+            })
+            .build()
+    }
+
+    #[test]
+    fn test_recursive_partial_depth() {
+        let limits = Limits { max_depth: Some(100), ..Limits::default() };
+        let ctx = context(limits);
+        let err = render_with(&ctx, "{{> loop}}", &items(0)).unwrap_err();
+        assert_let!(Error::LimitExceeded(Limit::Depth(100), ref location) = err => {
+            assert_eq!(location.file, Some(PathBuf::from("loop")));
+        });
+        assert_eq!(err.to_string(), "loop:1:2: exceeded the nesting depth limit of 100");
+    }
+
+    #[test]
+    fn test_section_depth() {
+        let limits = Limits { max_depth: Some(2), ..Limits::default() };
+        let ctx = context(limits);
+        let data = MapBuilder::new()
+            .insert_map("a", |b| b.insert_map("b", |b| b.insert_map("c", |b| b)))
+            .build();
+
+        assert_eq!(render_with(&ctx, "{{#a}}{{#b}}x{{/b}}{{/a}}", &data).unwrap(), "x");
+        assert_let!(Err(Error::LimitExceeded(Limit::Depth(2), _)) =
+                    render_with(&ctx, "{{#a}}{{#b}}{{#c}}x{{/c}}{{/b}}{{/a}}", &data) => {});
+    }
+
+    #[test]
+    fn test_output() {
+        let limits = Limits { max_output: Some(5), ..Limits::default() };
+        let ctx = context(limits);
+        let data = items(10);
+
+        assert_eq!(render_with(&ctx, "{{#items}}{{.}}{{/items}}", &items(5)).unwrap(), "01234");
+        let err = render_with(&ctx, "{{#items}}{{.}}{{/items}}", &data).unwrap_err();
+        assert_eq!(err.to_string(), "1:11: exceeded the output limit of 5 bytes");
+    }
+
+    #[test]
+    fn test_output_format_width() {
+        let limits = Limits { max_output: Some(100), ..Limits::default() };
+        let ctx = context(limits);
+        let data = MapBuilder::new().insert_str("name", "x").insert("n", &1).unwrap().build();
+
+        assert_eq!(render_with(&ctx, "{{name:>100}}", &data).unwrap().len(), 100);
+        let err = render_with(&ctx, "ab{{name:>1024}}", &data).unwrap_err();
+        assert_eq!(err.to_string(), "1:3: exceeded the output limit of 100 bytes");
+        assert_let!(Err(Error::LimitExceeded(Limit::Output(100), _)) =
+                    render_with(&ctx, "{{n:01000}}", &data) => {});
+    }
+
+    #[test]
+    fn test_defaults() {
+        assert_eq!(Limits::default(),
+                   Limits { max_depth: Some(256), max_output: None, max_iterations: None });
+        assert_eq!(Context::new(PathBuf::from(".")).limits, Limits::default());
+    }
+
+    #[test]
+    fn test_recursive_partial_default_depth() {
+        let mut partials = HashMap::new();
+        partials.insert("self".to_string(), "x{{> self}}".to_string());
+        let mut ctx = Context::new(PathBuf::from("."));
+        ctx.partial_loader = Some(Arc::new(partials));
+
+        let err = render_with(&ctx, "{{> self}}", &Data::Null).unwrap_err();
+        assert_let!(Error::LimitExceeded(Limit::Depth(DEFAULT_MAX_DEPTH), _) = err => {});
+
+        let template = ctx.compile("{{> self}}".chars()).unwrap();
+        let streamed = template.stream(&Data::Null).collect::<Result<Vec<_>, _>>();
+        assert_let!(Err(Error::LimitExceeded(Limit::Depth(DEFAULT_MAX_DEPTH), _)) = streamed => {});
+    }
+
+    #[test]
+    fn test_context_debug() {
+        let mut ctx = Context::new(PathBuf::from("."));
+        ctx.register_filter("upper", |_, _| Ok(Data::Null));
+        ctx.limits.max_output = Some(5);
+
+        let debug = format!("{:?}", ctx);
+        assert!(debug.contains("strict: false"), "{}", debug);
+        assert!(debug.contains("partial_loader: None"), "{}", debug);
+        assert!(debug.contains("filters: [\"upper\"]"), "{}", debug);
+        assert!(debug.contains("max_output: Some(5)"), "{}", debug);
+    }
+
+    #[test]
+    fn test_iterations() {
+        let limits = Limits { max_iterations: Some(10), ..Limits::default() };
+        let ctx = context(limits);
+
+        let template = "{{#items}}{{/items}}{{#items}}{{/items}}";
+        assert_eq!(render_with(&ctx, template, &items(5)).unwrap(), "");
+        let err = render_with(&ctx, template, &items(6)).unwrap_err();
+        assert_eq!(err.to_string(), "1:21: exceeded the iteration limit of 10");
+    }
+
+    #[test]
+    fn test_unlimited() {
+        let limits = Limits { max_depth: None, max_output: None, max_iterations: None };
+        let ctx = context(limits);
+        let out = render_with(&ctx, "{{#items}}{{.}}{{/items}}", &items(1000)).unwrap();
+        assert_eq!(out.len(), 2890);
+    }
+}
+
+mod streaming {
+    use std::borrow::Cow;
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    use mustache::{self, Context, Data, Error, Limit, Limits, MapBuilder};
+
+    fn items(n: usize) -> Data {
+        MapBuilder::new()
+            .insert_vec("items", |mut b| {
+                for i in 0..n {
+                    b = b.push_map(move |b| b.insert_int("n", i as i64).insert_str("name", "<item>"));
+                }
+                b
+            })
+            .build()
+    }
+
+    #[test]
+    fn test_stream_in_chunks() {
+        let template = mustache::compile_str("<ul>\n{{#items}}  <li>{{n}}: {{name}}</li>\n{{/items}}</ul>\n")
+            .unwrap();
+        let data = items(5000);
+
+        let chunks = template.stream(&data).collect::<Result<Vec<_>, _>>().unwrap();
+        assert!(chunks.len() > 1);
+        assert!(chunks[..chunks.len() - 1].iter().all(|chunk| chunk.len() >= 8 * 1024));
+        assert_eq!(chunks.concat(), template.render_data_to_string(&data).unwrap());
+    }
+
+    #[test]
+    fn test_stream_long_text_is_borrowed() {
+        let text = "x".repeat(10 * 1024);
+        let template = mustache::compile_str(&format!("{{{{#items}}}}{{{{n}}}}{{{{/items}}}}{}", text))
+            .unwrap();
+        let data = items(3);
+
+        let chunks = template.stream(&data).collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0], "012");
+        assert_let!(Cow::Borrowed(chunk) = chunks[1] => {
+            assert_eq!(chunk, text);
+        });
+    }
+
+    #[test]
+    fn test_stream_partials() {
+        let mut partials = HashMap::new();
+        partials.insert("item".to_string(), "- {{n}}\n{{#more}}{{> item}}{{/more}}".to_string());
+        let mut ctx = Context::new(PathBuf::from("."));
+        ctx.partial_loader = Some(Arc::new(partials));
+
+        let template = ctx.compile("list:\n  {{> item}}\n{{^missing}}done{{/missing}}".chars()).unwrap();
+        let data = MapBuilder::new()
+            .insert_int("n", 1)
+            .insert_map("more", |b| b.insert_int("n", 2).insert_map("more", |b| {
+                b.insert_int("n", 3).insert_bool("more", false)
+            }))
+            .build();
+
+        let streamed = template.stream(&data).collect::<Result<Vec<_>, _>>().unwrap().concat();
+        assert_eq!(streamed, "list:\n  - 1\n  - 2\n  - 3\ndone");
+        assert_eq!(streamed, template.render_data_to_string(&data).unwrap());
+    }
+
+    #[test]
+    fn test_stream_error() {
+        let mut ctx = Context::new(PathBuf::from("."));
+        ctx.strict = true;
+        let template = ctx.compile("{{#items}}{{n}}{{/items}}{{missing}}".chars()).unwrap();
+        let data = items(3000);
+
+        let mut stream = template.stream(&data);
+        assert!(stream.next().unwrap().unwrap().len() >= 8 * 1024);
+        let err = stream.by_ref().filter_map(|chunk| chunk.err()).next().unwrap();
+        assert_let!(Error::MissingVariable(ref name, _) = err => {
+            assert_eq!(name, "missing");
+        });
+        assert!(stream.next().is_none());
+    }
+
+    #[test]
+    fn test_stream_limits() {
+        let mut template = mustache::compile_str("{{#items}}{{n}}{{/items}}").unwrap();
+        template.set_limits(Limits { max_iterations: Some(10), ..Limits::default() });
+
+        let err = template.stream(&items(11)).filter_map(|chunk| chunk.err()).next().unwrap();
+        assert_let!(Error::LimitExceeded(Limit::Iterations(10), _) = err => {});
+    }
+
+    #[test]
+    fn test_stream_value() {
+        let template = mustache::compile_str("{{#names}}<{{.}}>{{/names}}").unwrap();
+        let mut data = HashMap::new();
+        data.insert("names", vec!["a", "b"]);
+
+        let streamed = template.stream_value(&data).collect::<Result<Vec<_>, _>>().unwrap().concat();
+        assert_eq!(streamed, "<a><b>");
+    }
+}
+
+mod context_values {
+    use std::collections::{BTreeMap, HashMap};
+    use std::path::PathBuf;
+
+    use mustache::{Arg, Context, ContextValue, Data};
+
+    use super::{render_value, render_with};
+
+    #[test]
+    fn test_render_borrowed_values() {
+        let names = vec!["<a>", "b"];
+        let mut data: HashMap<&str, &ContextValue> = HashMap::new();
+        data.insert("title", &"List");
+        data.insert("names", &names);
+        data.insert("count", &2u8);
+
+        let template = "{{title}} ({{count}}):{{#names}} {{.}}{{/names}}{{^empty}}!{{/empty}}";
+        assert_eq!(render_value(template, &data).unwrap(), "List (2): &lt;a&gt; b!");
+    }
+
+    #[test]
+    fn test_render_nested_values() {
+        let mut user = BTreeMap::new();
+        user.insert("name", Some("Jane".to_string()));
+        user.insert("nick", None);
+
+        let mut data = HashMap::new();
+        data.insert("user", user);
+
+        let template = "{{user.name}}|{{user.nick}}|{{#user}}{{name}}{{/user}}|{{#user.nick}}x{{/user.nick}}";
+        assert_eq!(render_value(template, &data).unwrap(), "Jane||Jane|");
+    }
+
+    #[test]
+    fn test_value_filters_and_formats() {
+        let mut ctx = Context::new(PathBuf::from("."));
+        ctx.register_filter("upper", |value: &Data, _: &[Arg]| {
+            match *value {
+                Data::String(ref s) => Ok(Data::String(s.to_uppercase())),
+                _ => Ok(Data::Null),
+            }
+        });
+
+        let mut data: HashMap<&str, &ContextValue> = HashMap::new();
+        data.insert("name", &"pear");
+        data.insert("price", &1.5f64);
+
+        assert_eq!(render_with(&ctx, "{{name | upper}} {{price:.2}}", &data).unwrap(), "PEAR 1.50");
+    }
+
+    #[test]
+    fn test_value_zero_is_falsy() {
+        let mut data = HashMap::new();
+        data.insert("count", 0);
+
+        let template = "{{#count}}some{{/count}}{{^count}}none{{/count}}";
+        assert_eq!(render_value(template, &data).unwrap(), "some");
+
+        let mut ctx = Context::new(PathBuf::from("."));
+        ctx.zero_is_falsy = true;
+        assert_eq!(render_with(&ctx, template, &data).unwrap(), "none");
+    }
+}
+
+mod formats {
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_json() {
+        use mustache::{self, Data};
+        use serde_json::{self, Value};
+
+        let json: Value = serde_json::from_str(r#"{
+            "title": "<Order>",
+            "lines": [{"n": 1, "price": 2.5}, {"n": 2, "price": 10}],
+            "paid": false
+        }"#).unwrap();
+        let template = mustache::compile_str("{{title}}:{{#lines}} {{n}}={{price}}{{/lines}}{{^paid}} due{{/paid}}")
+            .unwrap();
+
+        let mut out = vec![];
+        template.render_json(&mut out, &json).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "&lt;Order&gt;: 1=2.5 2=10 due");
+
+        let data = Data::from(json);
+        assert_let!(Data::Map(ref m) = data => {
+            assert_let!(Some(&Data::Vec(ref lines)) = m.get("lines") => {
+                assert_let!(Data::Map(ref line) = lines[0] => {
+                    assert_let!(Some(&Data::Int(1)) = line.get("n"));
+                    assert_let!(Some(&Data::Float(_)) = line.get("price"));
+                });
+            });
+        });
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_json_order() {
+        use mustache::{Data, ToData};
+        use serde_json::{self, Value};
+
+        let json: Value = serde_json::from_str(r#"{"z": 1, "a": 2, "m": {"y": 3, "b": 4}}"#).unwrap();
+
+        assert_let!(Data::Map(ref m) = json.to_data() => {
+            assert_eq!(m.keys().collect::<Vec<_>>(), ["z", "a", "m"]);
+        });
+        assert_let!(Data::Map(ref m) = Data::from(json) => {
+            assert_eq!(m.keys().collect::<Vec<_>>(), ["z", "a", "m"]);
+            assert_let!(Some(&Data::Map(ref inner)) = m.get("m") => {
+                assert_eq!(inner.keys().collect::<Vec<_>>(), ["y", "b"]);
+            });
+        });
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_toml() {
+        use mustache::{self, Data};
+        use toml::Value;
+
+        let value: Value = "name = \"pear\"\ncount = 0\nin_stock = false\nsince = 1979-05-27\n\n[[sizes]]\nname = \"s\"\n\n[[sizes]]\nname = \"m\"\n"
+            .parse()
+            .unwrap();
+        let template = mustache::compile_str("{{name}} {{since}}{{#sizes}} {{name}}{{/sizes}}{{^in_stock}} sold out{{/in_stock}}")
+            .unwrap();
+
+        let mut out = vec![];
+        template.render_toml(&mut out, &value).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "pear 1979-05-27 s m sold out");
+
+        assert_let!(Data::Map(ref m) = Data::from(value) => {
+            assert_let!(Some(&Data::Int(0)) = m.get("count"));
+            assert_let!(Some(&Data::String(ref since)) = m.get("since") => {
+                assert_eq!(since, "1979-05-27");
+            });
+        });
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_toml_order() {
+        use mustache::{Data, ToData};
+        use toml::Value;
+
+        let value: Value = "z = 1
+a = 2
+
+[m]
+y = 3
+b = 4
+".parse().unwrap();
+
+        assert_let!(Data::Map(ref m) = value.to_data() => {
+            assert_eq!(m.keys().collect::<Vec<_>>(), ["z", "a", "m"]);
+        });
+        assert_let!(Data::Map(ref m) = Data::from(value) => {
+            assert_eq!(m.keys().collect::<Vec<_>>(), ["z", "a", "m"]);
+            assert_let!(Some(&Data::Map(ref inner)) = m.get("m") => {
+                assert_eq!(inner.keys().collect::<Vec<_>>(), ["y", "b"]);
+            });
+        });
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn test_yaml() {
+        use std::convert::TryFrom;
+
+        use mustache::{self, Data, EncoderError};
+        use serde_yaml::{self, Value};
+
+        let value: Value = serde_yaml::from_str("name: pear\ntags: !set [ripe, green]\nprice: 1.25\n").unwrap();
+        let template = mustache::compile_str("{{name}} {{price}}{{#tags}} #{{.}}{{/tags}}").unwrap();
+
+        let mut out = vec![];
+        template.render_yaml(&mut out, &value).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "pear 1.25 #ripe #green");
+
+        assert_let!(Ok(Data::Map(ref m)) = Data::try_from(value) => {
+            assert_eq!(m.keys().collect::<Vec<_>>(), ["name", "tags", "price"]);
+            assert_let!(Some(&Data::Vec(ref tags)) = m.get("tags") => assert_eq!(tags.len(), 2));
+        });
+
+        let value: Value = serde_yaml::from_str("1: one\n").unwrap();
+        assert_let!(Err(EncoderError::KeyIsNotString) = Data::try_from(value));
+    }
+}
+
+mod strict {
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::path::Path;
+
+    use tempdir::TempDir;
+
+    use mustache::{Context, Data, Error, Location, MapBuilder, Position, Span};
+
+    use super::render_with;
+
+    fn strict(dir: &Path) -> Context {
+        let mut ctx = Context::new(dir.to_path_buf());
+        ctx.strict = true;
+        ctx
+    }
+
+    fn write_partial(dir: &Path, name: &str, src: &str) {
+        File::create(dir.join(name.to_string() + ".mustache"))
+            .and_then(|mut f| f.write_all(src.as_bytes()))
+            .expect("Failed to generate partial");
+    }
+
+    fn location_at(line: usize, start: usize, end: usize) -> Location {
+        Location {
+            file: None,
+            span: Span::new(Position::new(line, start), Position::new(line, end)),
+        }
+    }
+
+    fn location_in(file: &Path, line: usize, start: usize, end: usize) -> Location {
+        Location { file: Some(file.to_path_buf()), ..location_at(line, start, end) }
+    }
+
+    fn data() -> Data {
+        MapBuilder::new()
+            .insert_map("user", |map| {
+                map.insert_str("name", "Ann")
+                   .insert("nickname", &None::<String>)
+                   .expect("Failed to encode nickname")
+            })
+            .build()
+    }
+
+    #[test]
+    fn renders_present_values() {
+        let template = "{{user.name}}{{user.nickname}}{{#user.nickname}}x{{/user.nickname}}";
+        let ctx = strict(Path::new("."));
+        assert_eq!(render_with(&ctx, template, &data()).expect("Failed to render"), "Ann");
+    }
+
+    #[test]
+    fn missing_variable() {
+        let ctx = strict(Path::new("."));
+        let err = render_with(&ctx, "hi\n  {{user.nmae}}", &data()).err().expect("Expected an error");
+        assert_let!(Error::MissingVariable(ref name, ref location) = err => {
+            assert_eq!(name, "user.nmae");
+            assert_eq!(*location, location_at(2, 3, 16));
+        });
+        assert_eq!(err.to_string(), "2:3: missing variable `user.nmae`");
+    }
+
+    #[test]
+    fn missing_section() {
+        let ctx = strict(Path::new("."));
+        let err = render_with(&ctx, "{{#users}}{{/users}}", &data()).err().expect("Expected an error");
+        assert_let!(Error::MissingSection(ref name, ref location) = err => {
+            assert_eq!(name, "users");
+            assert_eq!(*location, location_at(1, 1, 21));
+        });
+
+        let err = render_with(&ctx, "{{^user.admin}}{{/user.admin}}", &data()).err().expect("Expected an error");
+        assert_let!(Error::MissingSection(ref name, _) = err => {
+            assert_eq!(name, "user.admin");
+        });
+    }
+
+    #[test]
+    fn missing_variable_in_partial() {
+        let tmpdir = TempDir::new("").expect("Failed to make tempdir");
+        write_partial(tmpdir.path(), "user", "{{user.name}} {{user.age}}");
+
+        let template = strict(tmpdir.path()).compile("{{> user}}".chars()).expect("Failed to compile");
+        let err = template.render_data(&mut vec![], &data()).err().expect("Expected an error");
+        let file = tmpdir.path().join("user.mustache");
+        assert_let!(Error::MissingVariable(ref name, ref location) = err => {
+            assert_eq!(name, "user.age");
+            assert_eq!(*location, location_in(&file, 1, 15, 27));
+        });
+    }
+
+    #[test]
+    fn missing_partial() {
+        let tmpdir = TempDir::new("").expect("Failed to make tempdir");
+        write_partial(tmpdir.path(), "outer", "\n{{> inner}}");
+
+        let err = strict(tmpdir.path()).compile("{{> nope}}".chars()).err().expect("Expected an error");
+        assert_let!(Error::MissingPartial(ref name, ref location) = err => {
+            assert_eq!(name, "nope");
+            assert_eq!(*location, location_at(1, 1, 11));
+        });
+
+        let err = strict(tmpdir.path()).compile("{{> outer}}".chars()).err().expect("Expected an error");
+        let file = tmpdir.path().join("outer.mustache");
+        assert_let!(Error::MissingPartial(ref name, ref location) = err => {
+            assert_eq!(name, "inner");
+            assert_eq!(*location, location_in(&file, 2, 1, 12));
+        });
+    }
+
+    #[test]
+    fn dynamic_partial_outside_dir() {
+        let tmpdir = TempDir::new("").expect("Failed to make tempdir");
+        let dir = tmpdir.path().join("templates");
+        fs::create_dir(&dir).expect("Failed to make template dir");
+        write_partial(tmpdir.path(), "secret", "key");
+        write_partial(&dir, "page", "page");
+
+        let names = ["page", "../secret", "./../secret"];
+        let absolute = tmpdir.path().join("secret").to_string_lossy().into_owned();
+        let data = |name: &str| MapBuilder::new().insert_str("name", name).build();
+
+        let ctx = Context::new(dir.clone());
+        assert_eq!(render_with(&ctx, "[{{>*name}}]", &data(names[0])).unwrap(), "[page]");
+        for name in names[1..].iter().chain(Some(&&*absolute)) {
+            assert_eq!(render_with(&ctx, "[{{>*name}}]", &data(name)).unwrap(), "[]");
+        }
+
+        let err = render_with(&strict(&dir), "{{>*name}}", &data("../secret")).err();
+        assert_let!(Some(Error::MissingPartial(ref name, _)) = err => {
+            assert_eq!(name, "../secret");
+        });
+    }
+
+    #[test]
+    fn missing_partial_is_ignored_when_not_strict() {
+        let ctx = Context::new(Path::new(".").to_path_buf());
+        assert!(ctx.compile("{{> nope}}".chars()).is_ok());
+    }
+}
+
+mod loaders {
+    use std::collections::HashMap;
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+
+    use mustache::{ChainLoader, Context, Data, Error, FileLoader, MapBuilder, PartialLoader};
+
+    use super::{render_data, render_with};
+
+    fn partials(partials: &[(&str, &str)]) -> HashMap<String, String> {
+        partials.iter().map(|&(name, src)| (name.to_string(), src.to_string())).collect()
+    }
+
+    fn context<L: PartialLoader + 'static>(loader: L) -> Context {
+        let mut ctx = Context::new(PathBuf::from("."));
+        ctx.partial_loader = Some(Arc::new(loader));
+        ctx.strict = true;
+        ctx
+    }
+
+    fn data() -> Data {
+        MapBuilder::new().insert_str("name", "world").build()
+    }
+
+    #[test]
+    fn in_memory() {
+        let loader = partials(&[("greeting", "hello {{> name}}"), ("name", "{{name}}!")]);
+        let ctx = context(loader);
+        assert_eq!(render_with(&ctx, "<{{> greeting}}>", &data()).expect("Failed to render"),
+                   "<hello world!>");
+    }
+
+    #[test]
+    fn dynamic_partials() {
+        let loader = partials(&[("world", "planet")]);
+        let ctx = context(loader);
+        assert_eq!(render_with(&ctx, "{{>*name}}", &data()).expect("Failed to render"), "planet");
+    }
+
+    #[test]
+    fn chain_falls_back() {
+        let loader = ChainLoader::new()
+            .push(partials(&[("a", "first {{> b}}")]))
+            .push(partials(&[("a", "shadowed"), ("b", "second {{> c}}")]))
+            .push(FileLoader::new(PathBuf::from("tests/test-data"), "mustache".to_string()));
+
+        assert!(loader.load("nope").expect("Failed to load").is_none());
+        assert_eq!(loader.path("b"), Path::new("b"));
+        assert_eq!(loader.path("base"), Path::new("tests/test-data/base.mustache"));
+
+        let err = render_with(&context(loader), "{{> a}}", &data()).err().expect("Expected an error");
+        assert_let!(Error::MissingPartial(ref name, ref location) = err => {
+            assert_eq!(name, "c");
+            assert_eq!(location.file, Some(PathBuf::from("b")));
+        });
+    }
+
+    #[test]
+    fn errors_point_at_the_partial() {
+        let loader = partials(&[("user", "{{name}} {{age}}")]);
+        let err = render_with(&context(loader), "{{> user}}", &data()).err().expect("Expected an error");
+        assert_let!(Error::MissingVariable(ref name, ref location) = err => {
+            assert_eq!(name, "age");
+            assert_eq!(location.file, Some(PathBuf::from("user")));
+        });
+    }
+
+    #[test]
+    fn default_loader_reads_files() {
+        let ctx = Context::new(PathBuf::from("tests/test-data"));
+        let template = ctx.compile("{{> base}}".chars()).expect("Failed to compile");
+        assert!(!render_data(&template, &Data::Null).is_empty());
+    }
+}
+
+mod reload {
+    use std::fs::File;
+    use std::io::Write;
+    use std::path::Path;
+    use std::time::{Duration, SystemTime};
+
+    use tempdir::TempDir;
+
+    use mustache::{self, Arg, Context, Data, Error, MapBuilder};
+
+    use super::render_data;
+
+    // Write a template, making sure its modification time moves on even on
+    // file systems with coarse timestamps.
+    fn write(dir: &Path, name: &str, src: &str, age: u64) {
+        let mut file = File::create(dir.join(name.to_string() + ".mustache"))
+            .expect("Failed to create template");
+        file.write_all(src.as_bytes()).expect("Failed to write template");
+        file.set_modified(SystemTime::now() - Duration::from_secs(age))
+            .expect("Failed to set modification time");
+    }
+
+    fn context(dir: &Path, reload: bool) -> Context {
+        let mut ctx = Context::new(dir.to_path_buf());
+        ctx.reload = reload;
+        ctx
+    }
+
+    fn data() -> Data {
+        MapBuilder::new().insert_str("name", "world").build()
+    }
+
+    #[test]
+    fn reloads_changed_files() {
+        let tmpdir = TempDir::new("").expect("Failed to make tempdir");
+        write(tmpdir.path(), "page", "[{{> greeting}}]", 100);
+        write(tmpdir.path(), "greeting", "hello {{name}}", 100);
+
+        let template = context(tmpdir.path(), true).compile_path("page").unwrap();
+        let fixed = context(tmpdir.path(), false).compile_path("page").unwrap();
+        assert_eq!(render_data(&template, &data()), "[hello world]");
+
+        write(tmpdir.path(), "greeting", "bye {{name}}", 50);
+        assert_eq!(render_data(&template, &data()), "[bye world]");
+        assert_eq!(render_data(&fixed, &data()), "[hello world]");
+
+        write(tmpdir.path(), "page", "<{{> greeting}}{{> new}}>", 50);
+        assert_eq!(render_data(&template, &data()), "<bye world>");
+
+        // Partials that were missing are picked up once they exist.
+        write(tmpdir.path(), "new", "!", 10);
+        assert_eq!(render_data(&template, &data()), "<bye world!>");
+    }
+
+    #[test]
+    fn reloads_dynamic_partials() {
+        let tmpdir = TempDir::new("").expect("Failed to make tempdir");
+        write(tmpdir.path(), "page", "{{>*name}}", 100);
+        write(tmpdir.path(), "world", "planet", 100);
+
+        let template = context(tmpdir.path(), true).compile_path("page").unwrap();
+        assert_eq!(render_data(&template, &data()), "planet");
+
+        write(tmpdir.path(), "world", "globe", 50);
+        assert_eq!(render_data(&template, &data()), "globe");
+    }
+
+    #[test]
+    fn uses_the_current_context() {
+        let tmpdir = TempDir::new("").expect("Failed to make tempdir");
+        write(tmpdir.path(), "page", "{{name | shout}} <{{name}}>", 100);
+
+        let mut template = context(tmpdir.path(), true).compile_path("page").unwrap();
+        assert!(template.render_data_to_string(&data()).is_err());
+
+        template.register_filter("shout", |value: &Data, _: &[Arg]| {
+            match *value {
+                Data::String(ref s) => Ok(Data::String(s.to_uppercase() + "!")),
+                _ => Ok(Data::Null),
+            }
+        });
+        template.set_escaper(mustache::escape::Url);
+        assert_eq!(render_data(&template, &data()), "WORLD%21 <world>");
+
+        // And after the template is recompiled.
+        write(tmpdir.path(), "page", "{{name | shout}}", 50);
+        assert_eq!(render_data(&template, &data()), "WORLD%21");
+    }
+
+    #[test]
+    fn recovers_from_errors() {
+        let tmpdir = TempDir::new("").expect("Failed to make tempdir");
+        write(tmpdir.path(), "page", "{{name}}", 100);
+
+        let template = context(tmpdir.path(), true).compile_path("page").unwrap();
+        assert_eq!(render_data(&template, &data()), "world");
+
+        write(tmpdir.path(), "page", "{{#name}}", 50);
+        assert_let!(Err(Error::Parser(ref err)) = template.render_data_to_string(&data()) => {
+            assert_eq!(err.file(), Some(&*tmpdir.path().join("page.mustache")));
+        });
+
+        write(tmpdir.path(), "page", "{{name}}!", 10);
+        assert_eq!(render_data(&template, &data()), "world!");
+    }
+}
+
 #[test]
 fn test_render_option_nested() {
     #[derive(Debug, Serialize)]
@@ -298,6 +1510,49 @@ fn render_data(template: &Template, data: &Data) -> String {
     String::from_utf8(bytes).expect("Failed ot encode as String")
 }
 
+#[test]
+fn test_render_to_string() {
+    let template = compile_str("{{#names}}<{{.}}>{{/names}}");
+    let mut ctx = HashMap::new();
+    ctx.insert("names", vec!["a", "é"]);
+
+    assert_eq!(template.render_to_string(&ctx).unwrap(), "<a><é>");
+    assert_eq!(template.render_data_to_string(&to_data(&ctx).unwrap()).unwrap(),
+               "<a><é>");
+
+    let mut out = "names: ".to_string();
+    template.render_fmt(&mut out, &ctx).unwrap();
+    assert_eq!(out, "names: <a><é>");
+
+    assert_eq!(format!("[{:}]", template.bind(&ctx)), "[<a><é>]");
+}
+
+#[test]
+fn test_bind_error() {
+    use std::fmt::Write;
+
+    let mut ctx = Context::new(PathBuf::from("."));
+    ctx.strict = true;
+    let template = ctx.compile("{{missing}}".chars()).unwrap();
+    let data: HashMap<String, String> = HashMap::new();
+
+    let mut out = String::new();
+    assert!(write!(out, "{}", template.bind(&data)).is_err());
+}
+
+#[test]
+fn test_to_data_keeps_field_order() {
+    let info = PlanetInfo {
+        moons: vec![],
+        population: 0,
+        description: String::new(),
+    };
+
+    assert_let!(Data::Map(m) = to_data(&info).unwrap() => {
+        assert_eq!(m.keys().collect::<Vec<_>>(), ["moons", "population", "description"]);
+    });
+}
+
 #[test]
 fn test_write_failure() {
     use std::error::Error;
@@ -324,35 +1579,35 @@ fn test_write_failure() {
 
 #[test]
 fn test_render_sections() {
-    let ctx = HashMap::new();
+    let ctx = Map::new();
     let template = compile_str("0{{#a}}1 {{n}} 3{{/a}}5");
 
     assert_eq!(render_data(&template, &Data::Map(ctx)), "05".to_string());
 
-    let mut ctx = HashMap::new();
+    let mut ctx = Map::new();
     ctx.insert("a".to_string(), Data::Vec(Vec::new()));
 
     assert_eq!(render_data(&template, &Data::Map(ctx)), "05".to_string());
 
-    let mut ctx = HashMap::new();
+    let mut ctx = Map::new();
     ctx.insert("a".to_string(), Data::Vec(Vec::new()));
     assert_eq!(render_data(&template, &Data::Map(ctx)), "05".to_string());
 
-    let mut ctx0 = HashMap::new();
-    let ctx1 = HashMap::new();
+    let mut ctx0 = Map::new();
+    let ctx1 = Map::new();
     ctx0.insert("a".to_string(), Data::Vec(vec![Data::Map(ctx1)]));
 
     assert_eq!(render_data(&template, &Data::Map(ctx0)), "01  35".to_string());
 
-    let mut ctx0 = HashMap::new();
-    let mut ctx1 = HashMap::new();
+    let mut ctx0 = Map::new();
+    let mut ctx1 = Map::new();
     ctx1.insert("n".to_string(), Data::String("a".to_string()));
     ctx0.insert("a".to_string(), Data::Vec(vec![Data::Map(ctx1)]));
     assert_eq!(render_data(&template, &Data::Map(ctx0)), "01 a 35".to_string());
 
-    let mut ctx = HashMap::new();
+    let mut ctx = Map::new();
     ctx.insert("a".to_string(),
-               Data::Fun(RefCell::new(Box::new(|_text| "foo".to_string()))));
+               Data::Fun(Mutex::new(Box::new(|_text| "foo".to_string()))));
     assert_eq!(render_data(&template, &Data::Map(ctx)), "0foo5".to_string());
 }
 
@@ -360,52 +1615,52 @@ fn test_render_sections() {
 fn test_render_inverted_sections() {
     let template = compile_str("0{{^a}}1 3{{/a}}5");
 
-    let ctx = HashMap::new();
+    let ctx = Map::new();
     assert_eq!(render_data(&template, &Data::Map(ctx)), "01 35".to_string());
 
-    let mut ctx = HashMap::new();
+    let mut ctx = Map::new();
     ctx.insert("a".to_string(), Data::Vec(vec![]));
     assert_eq!(render_data(&template, &Data::Map(ctx)), "01 35".to_string());
 
-    let mut ctx0 = HashMap::new();
-    let ctx1 = HashMap::new();
+    let mut ctx0 = Map::new();
+    let ctx1 = Map::new();
     ctx0.insert("a".to_string(), Data::Vec(vec![Data::Map(ctx1)]));
     assert_eq!(render_data(&template, &Data::Map(ctx0)), "05".to_string());
 
-    let mut ctx0 = HashMap::new();
-    let mut ctx1 = HashMap::new();
+    let mut ctx0 = Map::new();
+    let mut ctx1 = Map::new();
     ctx1.insert("n".to_string(), Data::String("a".to_string()));
     ctx0.insert("a".to_string(), Data::Vec(vec![Data::Map(ctx1)]));
     assert_eq!(render_data(&template, &Data::Map(ctx0)), "05".to_string());
 }
 
 fn assert_partials_data(template: Template) {
-    let ctx = HashMap::new();
+    let ctx = Map::new();
     assert_eq!(render_data(&template, &Data::Map(ctx)),
                "<h2>Names</h2>\n".to_string());
 
-    let mut ctx = HashMap::new();
+    let mut ctx = Map::new();
     ctx.insert("names".to_string(), Data::Vec(vec![]));
     assert_eq!(render_data(&template, &Data::Map(ctx)),
                "<h2>Names</h2>\n".to_string());
 
-    let mut ctx0 = HashMap::new();
-    let ctx1 = HashMap::new();
+    let mut ctx0 = Map::new();
+    let ctx1 = Map::new();
     ctx0.insert("names".to_string(), Data::Vec(vec![Data::Map(ctx1)]));
     assert_eq!(render_data(&template, &Data::Map(ctx0)),
                "<h2>Names</h2>\n  <strong></strong>\n\n".to_string());
 
-    let mut ctx0 = HashMap::new();
-    let mut ctx1 = HashMap::new();
+    let mut ctx0 = Map::new();
+    let mut ctx1 = Map::new();
     ctx1.insert("name".to_string(), Data::String("a".to_string()));
     ctx0.insert("names".to_string(), Data::Vec(vec![Data::Map(ctx1)]));
     assert_eq!(render_data(&template, &Data::Map(ctx0)),
                "<h2>Names</h2>\n  <strong>a</strong>\n\n".to_string());
 
-    let mut ctx0 = HashMap::new();
-    let mut ctx1 = HashMap::new();
+    let mut ctx0 = Map::new();
+    let mut ctx1 = Map::new();
     ctx1.insert("name".to_string(), Data::String("a".to_string()));
-    let mut ctx2 = HashMap::new();
+    let mut ctx2 = Map::new();
     ctx2.insert("name".to_string(), Data::String("<b>".to_string()));
     ctx0.insert("names".to_string(), Data::Vec(vec![Data::Map(ctx1), Data::Map(ctx2)]));
     assert_eq!(render_data(&template, &Data::Map(ctx0)),
@@ -425,6 +1680,29 @@ fn test_render_partial() {
     assert_partials_data(template);
 }
 
+#[test]
+fn test_partial_parse_error_location() {
+    let tmpdir = TempDir::new("").expect("Failed to make tempdir");
+    for &(name, src) in &[("outer", "{{> inner}}"), ("inner", "line one\n  {{#section}}")] {
+        File::create(tmpdir.path().join(name.to_string() + ".mustache"))
+            .and_then(|mut f| f.write_all(src.as_bytes()))
+            .expect("Failed to generate partial");
+    }
+
+    let ctx = Context::new(tmpdir.path().to_path_buf());
+    let err = ctx.compile("{{> outer}}".chars()).err().expect("Expected a parse error");
+
+    assert_let!(Error::Parser(ref err) = err => {
+        assert_eq!(err.file(), Some(&*tmpdir.path().join("inner.mustache")));
+        assert_eq!(err.line(), 2);
+        assert_eq!(err.col(), 3);
+        assert_eq!(*err.kind(), mustache::ParserErrorKind::UnclosedSection("section".into()));
+    });
+    assert_eq!(err.to_string(),
+               format!("{}:2:3: found an unclosed section: \"section\"",
+                       tmpdir.path().join("inner.mustache").display()));
+}
+
 fn parse_spec_tests(src: &str) -> Vec<Json> {
     let path = PathBuf::from(src);
     let file = File::open(&path)
@@ -452,7 +1730,7 @@ fn write_partials(tmpdir: &Path, value: &Json) {
     })
 }
 
-fn run_test(test: serde_json::Map<String, Json>, data: Data) {
+fn run_test(test: serde_json::Map<String, Json>, data: Data, stream: bool) {
     let template = assert_let!(Some(&Json::String(ref s)) = test.get("template") => {
         s.clone()
     });
@@ -484,6 +1762,26 @@ fn run_test(test: serde_json::Map<String, Json>, data: Data) {
         println!("");
     }
     assert_eq!(result, expected);
+
+    // The JSON renders the same without being encoded first.
+    #[cfg(feature = "json")]
+    {
+        if let Some(json) = test.get("data") {
+            let mut rendered = vec![];
+            template.render_value(&mut rendered, json).expect("Failed to render JSON");
+            assert_eq!(String::from_utf8(rendered).unwrap(), expected);
+        }
+    }
+
+    // Streaming renders the same thing as rendering all at once.
+    if !stream {
+        return;
+    }
+    let streamed = template.stream(&data)
+        .collect::<Result<Vec<_>, _>>()
+        .expect("Failed to stream")
+        .concat();
+    assert_eq!(streamed, expected);
 }
 
 fn run_tests(spec: &str) {
@@ -493,7 +1791,7 @@ fn run_tests(spec: &str) {
         let data = test.get("data").expect("No test data").clone();
         let data = to_data(&data).expect("Failed to encode");
 
-        run_test(test, data);
+        run_test(test, data, true);
     }
 }
 
@@ -547,26 +1845,26 @@ fn test_spec_lambdas() {
         match &*s {
             "Interpolation" => {
                 let f = |_text| "world".to_string();
-                ctx.insert("lambda".to_string(), Data::Fun(RefCell::new(Box::new(f))));
+                ctx.insert("lambda".to_string(), Data::Fun(Mutex::new(Box::new(f))));
             }
             "Interpolation - Expansion" => {
                 let f = |_text| "{{planet}}".to_string();
-                ctx.insert("lambda".to_string(), Data::Fun(RefCell::new(Box::new(f))));
+                ctx.insert("lambda".to_string(), Data::Fun(Mutex::new(Box::new(f))));
             }
             "Interpolation - Alternate Delimiters" => {
                 let f = |_text| "|planet| => {{planet}}".to_string();
-                ctx.insert("lambda".to_string(), Data::Fun(RefCell::new(Box::new(f))));
+                ctx.insert("lambda".to_string(), Data::Fun(Mutex::new(Box::new(f))));
             }
             "Interpolation - Multiple Calls" => {
                 let f = move |_text: String| {
                     calls += 1;
                     calls.to_string()
                 };
-                ctx.insert("lambda".to_string(), Data::Fun(RefCell::new(Box::new(f))));
+                ctx.insert("lambda".to_string(), Data::Fun(Mutex::new(Box::new(f))));
             }
             "Escaping" => {
                 let f = |_text| ">".to_string();
-                ctx.insert("lambda".to_string(), Data::Fun(RefCell::new(Box::new(f))));
+                ctx.insert("lambda".to_string(), Data::Fun(Mutex::new(Box::new(f))));
             }
             "Section" => {
                 let f = |text: String| {
@@ -576,27 +1874,38 @@ fn test_spec_lambdas() {
                         "no".to_string()
                     }
                 };
-                ctx.insert("lambda".to_string(), Data::Fun(RefCell::new(Box::new(f))));
+                ctx.insert("lambda".to_string(), Data::Fun(Mutex::new(Box::new(f))));
             }
             "Section - Expansion" => {
                 let f = |text: String| text.clone() + "{{planet}}" + &text;
-                ctx.insert("lambda".to_string(), Data::Fun(RefCell::new(Box::new(f))));
+                ctx.insert("lambda".to_string(), Data::Fun(Mutex::new(Box::new(f))));
             }
             "Section - Alternate Delimiters" => {
                 let f = |text: String| text.clone() + "{{planet}} => |planet|" + &text;
-                ctx.insert("lambda".to_string(), Data::Fun(RefCell::new(Box::new(f))));
+                ctx.insert("lambda".to_string(), Data::Fun(Mutex::new(Box::new(f))));
             }
             "Section - Multiple Calls" => {
                 let f = |text: String| "__".to_string() + &text + "__";
-                ctx.insert("lambda".to_string(), Data::Fun(RefCell::new(Box::new(f))));
+                ctx.insert("lambda".to_string(), Data::Fun(Mutex::new(Box::new(f))));
             }
             "Inverted Section" => {
                 let f = |_text| "".to_string();
-                ctx.insert("lambda".to_string(), Data::Fun(RefCell::new(Box::new(f))));
+                ctx.insert("lambda".to_string(), Data::Fun(Mutex::new(Box::new(f))));
             }
             spec_name => unimplemented!("unimplemented lambda spec test: {}", spec_name),
         };
 
-        run_test(test, Data::Map(ctx));
+        // The lambdas count their calls, so they can't be rendered twice.
+        run_test(test, Data::Map(ctx), false);
     }
 }
+
+#[test]
+fn test_spec_inheritance() {
+    run_tests("spec/specs/~inheritance.json");
+}
+
+#[test]
+fn test_spec_dynamic_names() {
+    run_tests("spec/specs/~dynamic-names.json");
+}
@@ -1,7 +1,11 @@
 extern crate mustache;
 extern crate serde;
 extern crate serde_json;
+#[cfg(feature = "yaml")]
+extern crate serde_yaml;
 extern crate tempdir;
+#[cfg(feature = "toml")]
+extern crate toml;
 
 #[macro_use] extern crate serde_derive;
 
@@ -9,4 +13,5 @@ extern crate tempdir;
 mod macros;
 
 mod builder;
+mod registry;
 mod template;